@@ -0,0 +1,288 @@
+use crate::model::mappings::{input_for_midi, mapping_range};
+use crate::model::song::{Event, Song};
+use crate::player::schedule_for_song;
+
+/// How a song's pitches sit relative to the flute's playable range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RangeAnalysis {
+    pub below: usize,
+    pub within: usize,
+    pub above: usize,
+    pub min_pitch: Option<u8>,
+    pub max_pitch: Option<u8>,
+}
+
+/// Counts a song's notes below, within & above [`mapping_range`]. Rests aren't counted.
+pub fn range_analysis(song: &Song) -> RangeAnalysis {
+    let (min_id, max_id) = mapping_range();
+    let mut analysis = RangeAnalysis::default();
+
+    for event in song.events.iter().filter(|event| !event.is_rest()) {
+        let midi = event.note.midi;
+
+        if midi < min_id {
+            analysis.below += 1;
+        } else if midi > max_id {
+            analysis.above += 1;
+        } else {
+            analysis.within += 1;
+        }
+
+        analysis.min_pitch = Some(analysis.min_pitch.map_or(midi, |min| min.min(midi)));
+        analysis.max_pitch = Some(analysis.max_pitch.map_or(midi, |max| max.max(midi)));
+    }
+
+    analysis
+}
+
+/// For every shift from -12 to +12 semitones, how many notes would land within `range`. Rests
+/// aren't counted.
+pub fn transpose_coverage(song: &Song, range: (u8, u8)) -> Vec<(i32, usize)> {
+    let (min_id, max_id) = (range.0 as i32, range.1 as i32);
+
+    (-12..=12)
+        .map(|shift| {
+            let covered = song
+                .events
+                .iter()
+                .filter(|event| !event.is_rest())
+                .filter(|event| (min_id..=max_id).contains(&(event.note.midi as i32 + shift)))
+                .count();
+
+            (shift, covered)
+        })
+        .collect()
+}
+
+/// How many of a song's events can be played, see [`mapping_coverage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MappingCoverage {
+    pub mappable: usize,
+    pub total: usize,
+}
+
+impl MappingCoverage {
+    pub fn unmappable(&self) -> usize {
+        self.total - self.mappable
+    }
+}
+
+/// Whether the player has keys to press for an event, either its `override_keys` or the mapping
+/// for its pitch.
+pub fn is_mappable(event: &Event) -> bool {
+    event.override_keys.is_some() || input_for_midi(event.note.midi).is_some()
+}
+
+/// Counts the song's notes the player has keys for, see [`is_mappable`]. Rests aren't counted.
+pub fn mapping_coverage(song: &Song) -> MappingCoverage {
+    MappingCoverage {
+        mappable: song
+            .events
+            .iter()
+            .filter(|event| is_mappable(event))
+            .count(),
+        total: song.events.iter().filter(|event| !event.is_rest()).count(),
+    }
+}
+
+/// How many key downs playing `song` sends: each mapped note's keys plus the play key, as
+/// [`InputEngine::key_press`](crate::InputEngine::key_press) presses them. Re-triggers for the max
+/// sustain or vibrato aren't counted.
+pub fn keypress_count(song: &Song) -> usize {
+    schedule_for_song(song)
+        .iter()
+        .map(|event| event.input.keys.len() + 1)
+        .sum()
+}
+
+/// A suggested transpose for a song, see [`recommend_transpose`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransposeAdvice {
+    /// The shift in semitones, on top of whatever the song was already transposed by.
+    pub shift: i32,
+
+    /// How many of the song's events the shift keeps within range, from 0 to 100%.
+    pub coverage_percent: f64,
+
+    /// The advice in words, e.g. "Shift +5 semitones to keep 98% of notes in range".
+    pub note: String,
+}
+
+/// Recommends the shift that keeps the most notes within `range`, preferring the smallest one
+/// among equals, without changing the song. Unlike [`transpose_coverage`] it looks as far either
+/// way as any of the song's pitches could still reach the range. Rests aren't counted.
+pub fn recommend_transpose(song: &Song, range: (u8, u8)) -> TransposeAdvice {
+    let (min_id, max_id) = (range.0 as i32, range.1 as i32);
+    let pitches = song
+        .events
+        .iter()
+        .filter(|event| !event.is_rest())
+        .map(|event| event.note.midi as i32)
+        .collect::<Vec<_>>();
+
+    let (Some(&lowest), Some(&highest)) = (pitches.iter().min(), pitches.iter().max()) else {
+        return TransposeAdvice {
+            shift: 0,
+            coverage_percent: 100.0,
+            note: String::from("There are no notes to transpose"),
+        };
+    };
+
+    let (shift, covered) = ((min_id - highest)..=(max_id - lowest))
+        .map(|shift| {
+            let covered = pitches
+                .iter()
+                .filter(|&&midi| (min_id..=max_id).contains(&(midi + shift)))
+                .count();
+
+            (shift, covered)
+        })
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.abs().cmp(&a.0.abs())))
+        .unwrap_or((0, 0));
+
+    let coverage_percent = covered as f64 * 100.0 / pitches.len() as f64;
+    let note = if shift == 0 {
+        format!(
+            "Keep the pitches as they are to keep {:.0}% of notes in range",
+            coverage_percent
+        )
+    } else {
+        format!(
+            "Shift {:+} semitones to keep {:.0}% of notes in range",
+            shift, coverage_percent
+        )
+    };
+
+    TransposeAdvice {
+        shift,
+        coverage_percent,
+        note,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Metadata, Note};
+
+    fn song_from_pitches(pitches: &[u8]) -> Song {
+        Song {
+            metadata: Metadata::default(),
+            events: pitches
+                .iter()
+                .enumerate()
+                .map(|(i, &midi)| Event {
+                    note: Note {
+                        midi,
+                        velocity: 100,
+                    },
+                    time_ms: i as f64 * 100.0,
+                    duration_ms: 100.0,
+                    override_keys: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn count_notes_against_playable_range() {
+        let song = song_from_pitches(&[60, 68, 69, 81, 93, 94, 100]);
+        let analysis = range_analysis(&song);
+
+        assert_eq!(analysis.below, 2);
+        assert_eq!(analysis.within, 3);
+        assert_eq!(analysis.above, 2);
+        assert_eq!(analysis.min_pitch, Some(60));
+        assert_eq!(analysis.max_pitch, Some(100));
+    }
+
+    #[test]
+    fn transpose_coverage_peaks_at_best_shift() {
+        let song = song_from_pitches(&[65, 66, 66, 67, 67, 67, 68, 68, 69]);
+        let coverage = transpose_coverage(&song, (69, 69));
+        assert_eq!(coverage.len(), 25);
+
+        let (peak_shift, peak) = coverage
+            .iter()
+            .copied()
+            .max_by_key(|(_, covered)| *covered)
+            .unwrap();
+        assert_eq!((peak_shift, peak), (2, 3));
+
+        let peak_idx = coverage.iter().position(|(s, _)| *s == peak_shift).unwrap();
+        assert!(coverage[..=peak_idx].windows(2).all(|w| w[0].1 <= w[1].1));
+        assert!(coverage[peak_idx..].windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+
+    #[test]
+    fn advise_shifting_low_song_up() {
+        // Everything but the high D fits once shifted up by at least 19 semitones.
+        let song = song_from_pitches(&[50, 57, 60, 64, 90]);
+        let advice = recommend_transpose(&song, (69, 93));
+
+        assert_eq!(advice.shift, 19);
+        assert_eq!(advice.coverage_percent, 80.0);
+        assert_eq!(
+            advice.note,
+            "Shift +19 semitones to keep 80% of notes in range"
+        );
+
+        let advice = recommend_transpose(&song_from_pitches(&[69, 81]), (69, 93));
+        assert_eq!(advice.shift, 0);
+        assert_eq!(advice.coverage_percent, 100.0);
+    }
+
+    #[test]
+    fn analyse_around_rests() {
+        let mut song = song_from_pitches(&[60, 69, 100]);
+        song.events.insert(1, Event::rest(100.0, 50.0));
+        song.events.push(Event::rest(300.0, 100.0));
+
+        let analysis = range_analysis(&song);
+        assert_eq!((analysis.below, analysis.within, analysis.above), (1, 1, 1));
+        assert_eq!(analysis.max_pitch, Some(100));
+
+        let without_rests = song_from_pitches(&[60, 69, 100]);
+        assert_eq!(
+            transpose_coverage(&song, (69, 93)),
+            transpose_coverage(&without_rests, (69, 93))
+        );
+        assert_eq!(
+            recommend_transpose(&song, (69, 93)),
+            recommend_transpose(&without_rests, (69, 93))
+        );
+    }
+
+    #[test]
+    fn count_keys_pressed() {
+        let song = song_from_pitches(&[69, 82]);
+        let expected = [69, 82]
+            .iter()
+            .map(|&midi| input_for_midi(midi).unwrap().keys.len() + 1)
+            .sum::<usize>();
+
+        assert_eq!(keypress_count(&song), expected);
+        assert!(keypress_count(&song) > 4);
+
+        // Unmapped notes aren't pressed at all.
+        assert_eq!(keypress_count(&song_from_pitches(&[60])), 0);
+    }
+
+    #[test]
+    fn count_mappable_events() {
+        // Middle C & the top of the MIDI range have no keys, unless given some of their own.
+        let mut song = song_from_pitches(&[60, 69, 81, 127, 60]);
+        song.events[4].override_keys = Some(vec![0x61]);
+
+        let coverage = mapping_coverage(&song);
+        assert_eq!(
+            coverage,
+            MappingCoverage {
+                mappable: 3,
+                total: 5
+            }
+        );
+        assert_eq!(coverage.unmappable(), 2);
+        assert!(!is_mappable(&song.events[0]));
+    }
+}
@@ -0,0 +1,151 @@
+use spin_sleep::{SpinSleeper, SpinStrategy};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The source of time for the playback worker, so scheduling can be driven without real waits.
+pub trait Clock: Send + Sync + Debug {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    fn elapsed_since(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+
+    /// Busy-waits until `deadline` without yielding the thread, for the last moment before an
+    /// event, where a sleep could wake up too late.
+    fn spin_until(&self, deadline: Instant) {
+        while self.now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+impl<C: Clock> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        C::now(self)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        C::sleep(self, duration);
+    }
+
+    fn spin_until(&self, deadline: Instant) {
+        C::spin_until(self, deadline);
+    }
+}
+
+/// Reads the system's monotonic clock & sleeps for real.
+#[derive(Debug, Clone)]
+pub struct SystemClock {
+    sleeper: SpinSleeper,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            sleeper: SpinSleeper::new(100_000).with_spin_strategy(SpinStrategy::YieldThread),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.sleeper.sleep(duration);
+    }
+}
+
+/// A virtual clock that only moves forward when slept on, and never blocks, so a song can be played
+/// through in an instant, e.g. to preview the keys it sends.
+#[derive(Debug)]
+pub(crate) struct VirtualClock {
+    origin: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl VirtualClock {
+    pub(crate) fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// How far the clock has been advanced since it was created.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.elapsed
+            .lock()
+            .map(|elapsed| *elapsed)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn advance(&self, duration: Duration) {
+        if let Ok(mut elapsed) = self.elapsed.lock() {
+            *elapsed += duration;
+        }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    /// Jumps straight to `deadline`, since nothing else moves a virtual clock while spinning.
+    fn spin_until(&self, deadline: Instant) {
+        self.advance(deadline.saturating_duration_since(self.now()));
+    }
+}
+
+/// A virtual clock for tests to read & move forward themselves, which never blocks. Only built for
+/// tests & with the `test-util` feature.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug)]
+pub struct MockClock(VirtualClock);
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    pub fn new() -> Self {
+        Self(VirtualClock::new())
+    }
+
+    /// How far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.0.advance(duration);
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.0.now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.0.sleep(duration);
+    }
+
+    fn spin_until(&self, deadline: Instant) {
+        self.0.spin_until(deadline);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::engine::{InputEngine, ModifierToggles, VibratoConfig};
+use crate::model::mappings::{Input, VIRTUAL_KEY, key_name};
+use log::info;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// An [`InputEngine`] that writes down the key batches & sleeps `inner` would make, without
+/// sending or sleeping, so key timing can be checked away from the game.
+#[derive(Debug)]
+pub struct DescribeEngine<E> {
+    inner: E,
+    trace: Mutex<Vec<String>>,
+    echo: bool,
+}
+
+impl<E: InputEngine> DescribeEngine<E> {
+    /// Describes what `inner` would send, with all of its settings.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            trace: Mutex::new(Vec::new()),
+            echo: false,
+        }
+    }
+
+    /// Also logs every line as it's traced, e.g. to follow a song played through it.
+    pub fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Returns the trace so far & starts a new one.
+    pub fn take_trace(&self) -> Vec<String> {
+        self.trace
+            .lock()
+            .map(|mut trace| std::mem::take(&mut *trace))
+            .unwrap_or_default()
+    }
+
+    fn record(&self, line: String) {
+        if self.echo {
+            info!("    {}", line);
+        }
+
+        if let Ok(mut trace) = self.trace.lock() {
+            trace.push(line);
+        }
+    }
+
+    fn describe_batch(action: &str, combo: &Input) -> String {
+        let keys = combo
+            .keys
+            .iter()
+            .map(|&vk| {
+                key_name(vk)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("vk 0x{:02X}", vk.0))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!("{} {}: [{}]", action, combo.note_label, keys)
+    }
+}
+
+impl<E: InputEngine> InputEngine for DescribeEngine<E> {
+    fn get_articulation(&self) -> f64 {
+        self.inner.get_articulation()
+    }
+
+    fn set_articulation(&self, articulation: f64) {
+        self.inner.set_articulation(articulation);
+    }
+
+    fn max_sustain_ms(&self) -> Option<f64> {
+        self.inner.max_sustain_ms()
+    }
+
+    fn vibrato(&self) -> Option<VibratoConfig> {
+        self.inner.vibrato()
+    }
+
+    fn min_hold_ms(&self) -> f64 {
+        self.inner.min_hold_ms()
+    }
+
+    fn min_playable_hold_ms(&self) -> f64 {
+        self.inner.min_playable_hold_ms()
+    }
+
+    fn key_registration_latency_ms(&self) -> f64 {
+        self.inner.key_registration_latency_ms()
+    }
+
+    fn elevate_thread_priority(&self) -> bool {
+        self.inner.elevate_thread_priority()
+    }
+
+    fn play_key(&self) -> &'static VIRTUAL_KEY {
+        self.inner.play_key()
+    }
+
+    fn modifier_toggles(&self) -> Option<&ModifierToggles> {
+        self.inner.modifier_toggles()
+    }
+
+    fn sleep(&self, duration_ms: Duration) {
+        self.record(format!("sleep {:.3}ms", duration_ms.as_secs_f64() * 1000.0));
+    }
+
+    fn key_up(&self, input: &Input) -> anyhow::Result<()> {
+        self.record(Self::describe_batch("up", input));
+        Ok(())
+    }
+
+    fn key_down(&self, input: &Input) -> anyhow::Result<()> {
+        self.record(Self::describe_batch("down", input));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::RecordingEngine;
+    use crate::model::mappings::input_for_midi;
+
+    #[test]
+    fn describe_note_key_order() {
+        let engine = DescribeEngine::new(RecordingEngine::new(1.0));
+        let input = input_for_midi(69).expect("Midi values should be in range..!");
+
+        engine.key_press(input, 250.0, 1.0).unwrap();
+
+        let batches: Vec<String> = engine
+            .take_trace()
+            .into_iter()
+            .filter(|line| !line.starts_with("sleep"))
+            .collect();
+
+        let note_keys = input
+            .keys
+            .iter()
+            .map(|&vk| key_name(vk).unwrap())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        assert_eq!(
+            batches,
+            vec![
+                format!("down {}: [{}]", input.note_label, note_keys),
+                "down play_key: [numpad5]".to_string(),
+                "up play_key: [numpad5]".to_string(),
+                format!("up {}: [{}]", input.note_label, note_keys),
+            ]
+        );
+        assert!(engine.take_trace().is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::engine::{InputEngine, ModifierStyle, ModifierToggles, VibratoConfig};
+use crate::model::mappings::keys::*;
+use crate::model::mappings::{Input, KEY_CHOICES, PLAY_KEY};
+use anyhow::{Result, anyhow, bail};
+use log::{debug, warn};
+use spin_sleep::{SpinSleeper, SpinStrategy};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+
+/// How long a new virtual keyboard is given to be picked up before anything is sent through it.
+const DEVICE_SETTLE: Duration = Duration::from_millis(500);
+
+// From linux/input-event-codes.h & linux/uinput.h, which libc doesn't carry.
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const SYN_REPORT: u16 = 0;
+const BUS_VIRTUAL: u16 = 0x06;
+const UI_DEV_CREATE: u64 = 0x5501;
+const UI_DEV_DESTROY: u64 = 0x5502;
+const UI_DEV_SETUP: u64 = 0x405C_5503;
+const UI_SET_EVBIT: u64 = 0x4004_5564;
+const UI_SET_KEYBIT: u64 = 0x4004_5565;
+
+/// The Linux keycode for the key `key` stands for, if it's one of [`KEY_CHOICES`].
+pub(crate) fn linux_keycode(key: VIRTUAL_KEY) -> Option<u16> {
+    let code = match key {
+        VK_ESCAPE => 1,
+        VK_1 => 2,
+        VK_2 => 3,
+        VK_3 => 4,
+        VK_4 => 5,
+        VK_5 => 6,
+        VK_6 => 7,
+        VK_7 => 8,
+        VK_8 => 9,
+        VK_9 => 10,
+        VK_0 => 11,
+        VK_Q => 16,
+        VK_W => 17,
+        VK_E => 18,
+        VK_R => 19,
+        VK_T => 20,
+        VK_Y => 21,
+        VK_U => 22,
+        VK_I => 23,
+        VK_O => 24,
+        VK_P => 25,
+        VK_RETURN => 28,
+        VK_A => 30,
+        VK_S => 31,
+        VK_D => 32,
+        VK_F => 33,
+        VK_G => 34,
+        VK_H => 35,
+        VK_J => 36,
+        VK_K => 37,
+        VK_L => 38,
+        VK_Z => 44,
+        VK_X => 45,
+        VK_C => 46,
+        VK_V => 47,
+        VK_B => 48,
+        VK_N => 49,
+        VK_M => 50,
+        VK_SPACE => 57,
+        VK_NUMPAD7 => 71,
+        VK_NUMPAD8 => 72,
+        VK_NUMPAD9 => 73,
+        VK_NUMPAD4 => 75,
+        VK_NUMPAD5 => 76,
+        VK_NUMPAD6 => 77,
+        VK_NUMPAD1 => 79,
+        VK_NUMPAD2 => 80,
+        VK_NUMPAD3 => 81,
+        VK_NUMPAD0 => 82,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// A virtual keyboard made through uinput, able to press every key in [`KEY_CHOICES`]. It's
+/// removed again when dropped.
+#[derive(Debug)]
+struct UinputKeyboard {
+    file: File,
+}
+
+impl UinputKeyboard {
+    fn create() -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to open {} (is the uinput module loaded & writable?): {}..!",
+                    UINPUT_PATH,
+                    e
+                )
+            })?;
+        let keyboard = Self { file };
+
+        keyboard.ioctl(UI_SET_EVBIT, EV_KEY as libc::c_ulong)?;
+        for (_, key) in KEY_CHOICES.iter() {
+            if let Some(code) = linux_keycode(*key) {
+                keyboard.ioctl(UI_SET_KEYBIT, code as libc::c_ulong)?;
+            }
+        }
+
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = BUS_VIRTUAL;
+        for (slot, byte) in setup.name.iter_mut().zip(b"FLUTE_WELL".iter()) {
+            *slot = *byte as libc::c_char;
+        }
+
+        keyboard.ioctl(UI_DEV_SETUP, &setup as *const _ as libc::c_ulong)?;
+        keyboard.ioctl(UI_DEV_CREATE, 0)?;
+        std::thread::sleep(DEVICE_SETTLE);
+
+        Ok(keyboard)
+    }
+
+    fn ioctl(&self, request: u64, arg: libc::c_ulong) -> Result<()> {
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg) } < 0 {
+            bail!(
+                "uinput ioctl 0x{:X} failed: {}..!",
+                request,
+                std::io::Error::last_os_error()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Sends every key in `combo` as pressed (`value` 1) or released (0), then a sync report, so
+    /// they arrive together like a single `SendInput` batch.
+    fn send(&mut self, combo: &Input, value: i32) -> Result<()> {
+        let mut events = Vec::with_capacity(combo.keys.len() + 1);
+        for key in combo.keys.iter() {
+            let Some(code) = linux_keycode(*key) else {
+                bail!("No Linux keycode for vk 0x{:02X}..!", key.0);
+            };
+            events.push(input_event(EV_KEY, code, value));
+        }
+        events.push(input_event(EV_SYN, SYN_REPORT, 0));
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                events.as_ptr() as *const u8,
+                std::mem::size_of_val(events.as_slice()),
+            )
+        };
+        self.file.write_all(bytes).map_err(|e| {
+            anyhow!(
+                "Failed to send {} through uinput: {}..!",
+                combo.note_label,
+                e
+            )
+        })
+    }
+}
+
+impl Drop for UinputKeyboard {
+    fn drop(&mut self) {
+        if let Err(why) = self.ioctl(UI_DEV_DESTROY, 0) {
+            warn!("Failed to remove the virtual keyboard: {:?}", why);
+        }
+    }
+}
+
+fn input_event(type_: u16, code: u16, value: i32) -> libc::input_event {
+    let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+    event.type_ = type_;
+    event.code = code;
+    event.value = value;
+    event
+}
+
+/// Sends keys through a uinput virtual keyboard, for playing the game through Proton. Wine reads
+/// the numpad as arrows unless Num Lock is on, so it has to be.
+#[derive(Debug)]
+pub struct LinuxInputEngine {
+    sleeper: SpinSleeper,
+    /// The articulation's f64 bits, so it can be changed mid-song through a shared engine.
+    articulation: AtomicU64,
+    pub max_sustain_ms: Option<f64>,
+    pub vibrato: Option<VibratoConfig>,
+    pub elevate_thread_priority: bool,
+    pub play_key: &'static VIRTUAL_KEY,
+    pub min_hold_ms: f64,
+    pub min_playable_hold_ms: f64,
+    pub key_registration_latency_ms: f64,
+    pub modifier_style: ModifierStyle,
+    modifier_toggles: ModifierToggles,
+    keyboard: Mutex<UinputKeyboard>,
+}
+
+impl LinuxInputEngine {
+    /// Makes the virtual keyboard right away, so it's been picked up by the time playback starts.
+    /// Fails if it can't be made, since no key could be sent without it.
+    pub fn new(articulation: f64) -> Result<Self> {
+        let sleeper = SpinSleeper::default().with_spin_strategy(SpinStrategy::YieldThread);
+        let keyboard = UinputKeyboard::create()?;
+
+        Ok(Self {
+            sleeper,
+            articulation: AtomicU64::new(articulation.to_bits()),
+            max_sustain_ms: None,
+            vibrato: None,
+            elevate_thread_priority: true,
+            play_key: &PLAY_KEY,
+            min_hold_ms: 0.0,
+            min_playable_hold_ms: 0.0,
+            key_registration_latency_ms: 0.0,
+            modifier_style: ModifierStyle::default(),
+            modifier_toggles: ModifierToggles::default(),
+            keyboard: Mutex::new(keyboard),
+        })
+    }
+
+    fn send(&self, combo: &Input, value: i32) -> Result<()> {
+        let Ok(mut keyboard) = self.keyboard.lock() else {
+            bail!("Failed to lock the virtual keyboard..!")
+        };
+
+        keyboard.send(combo, value)
+    }
+}
+
+impl InputEngine for LinuxInputEngine {
+    fn get_articulation(&self) -> f64 {
+        f64::from_bits(self.articulation.load(Ordering::Relaxed))
+    }
+
+    fn set_articulation(&self, articulation: f64) {
+        self.articulation
+            .store(articulation.to_bits(), Ordering::Relaxed);
+    }
+
+    fn max_sustain_ms(&self) -> Option<f64> {
+        self.max_sustain_ms
+    }
+
+    fn vibrato(&self) -> Option<VibratoConfig> {
+        self.vibrato
+    }
+
+    fn min_hold_ms(&self) -> f64 {
+        self.min_hold_ms
+    }
+
+    fn min_playable_hold_ms(&self) -> f64 {
+        self.min_playable_hold_ms
+    }
+
+    fn key_registration_latency_ms(&self) -> f64 {
+        self.key_registration_latency_ms
+    }
+
+    fn elevate_thread_priority(&self) -> bool {
+        self.elevate_thread_priority
+    }
+
+    fn play_key(&self) -> &'static VIRTUAL_KEY {
+        self.play_key
+    }
+
+    fn modifier_toggles(&self) -> Option<&ModifierToggles> {
+        (self.modifier_style == ModifierStyle::Tap).then_some(&self.modifier_toggles)
+    }
+
+    fn sleep(&self, duration_ms: Duration) {
+        self.sleeper.sleep(duration_ms);
+    }
+
+    fn key_up(&self, combo: &Input) -> Result<()> {
+        debug!(
+            "LinuxInputEngine::key_up for {} => keys {:?}",
+            combo.note_label, combo.keys
+        );
+
+        self.send(combo, 0)
+    }
+
+    fn key_down(&self, combo: &Input) -> Result<()> {
+        debug!(
+            "LinuxInputEngine::key_down for {} => keys {:?}",
+            combo.note_label, combo.keys
+        );
+
+        self.send(combo, 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::mappings::input_for_midi;
+    use crate::util::ensure_active_window;
+    use log::info;
+
+    #[test]
+    fn linux_keycode_for_every_choice() {
+        let unmapped = KEY_CHOICES
+            .iter()
+            .filter(|(_, key)| linux_keycode(*key).is_none())
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        assert!(unmapped.is_empty(), "No keycode for {:?}..!", unmapped);
+
+        // KEY_KP5, KEY_KP8 & KEY_1.
+        assert_eq!(linux_keycode(PLAY_KEY), Some(76));
+        assert_eq!(linux_keycode(VK_NUMPAD8), Some(72));
+        assert_eq!(linux_keycode(VK_1), Some(2));
+    }
+
+    // Needs a writable /dev/uinput & ANIMAL WELL focused, so only runs when asked for.
+    #[test]
+    #[ignore]
+    fn play_teleport_tune() {
+        env_logger::try_init().unwrap_or(());
+
+        let art = 0.69;
+        let engine = LinuxInputEngine::new(art).expect("The virtual keyboard should be made..!");
+
+        ensure_active_window();
+        for midi in [69, 69, 76, 76, 73, 73, 80, 80] {
+            let input = input_for_midi(midi).expect("Midi values should be in range..!");
+            info!("Playing note: \"{}\"", input.note_label);
+            assert!(engine.key_press(input, 150.0, art).is_ok());
+        }
+    }
+}
@@ -1,16 +1,160 @@
-use crate::MAPPINGS;
-use crate::model::mappings::{Input, PLAY_KEY};
+use crate::model::mappings::{
+    Input, PLAY_KEY, VIRTUAL_KEY, active_mappings, input_for_keys, modifier_keys,
+};
 use anyhow::anyhow;
+use std::sync::Mutex;
 use std::time::Duration;
 
+/// How long a key that changes between two consecutive notes is guaranteed to be up before the
+/// next note is pressed.
+pub const CHANGED_KEY_RELEASE_MS: f64 = 4.0;
+
+/// How long the note keys are held before the play key is pressed, so the flute sounds the note
+/// they select.
+pub const PLAY_KEY_SETTLE_MS: f64 = 1.0;
+
+/// Whether going from `previous` to `next` keeps some keys held while others change, which can
+/// race in the game unless the changed keys get a moment to register as released.
+pub fn shares_changed_keys(previous: &Input, next: &Input) -> bool {
+    let shares_keys = previous.keys.iter().any(|key| next.keys.contains(key));
+    let changes_keys = previous.keys.iter().any(|key| !next.keys.contains(key));
+
+    shares_keys && changes_keys
+}
+
+/// How long the play key is left up when it's re-triggered during a long hold.
+pub const RETRIGGER_GAP_MS: f64 = 1.0;
+
+mod describe;
+#[cfg(all(target_os = "linux", feature = "linux-input"))]
+mod linux;
+#[cfg(not(target_os = "windows"))]
+mod noop;
+mod recording;
 #[cfg(target_os = "windows")]
 mod windows;
+pub use describe::DescribeEngine;
+#[cfg(all(target_os = "linux", feature = "linux-input"))]
+pub use linux::LinuxInputEngine as DefaultInputEngine;
+#[cfg(not(any(
+    target_os = "windows",
+    all(target_os = "linux", feature = "linux-input")
+)))]
+pub use noop::NoopInputEngine as DefaultInputEngine;
+#[cfg(not(target_os = "windows"))]
+pub use noop::{NoopInputEngine, can_poll_keys, is_key_down};
+pub use recording::{KeyAction, RecordedInput, RecordingEngine};
 #[cfg(target_os = "windows")]
-pub use windows::WindowsInputEngine as DefaultInputEngine;
+pub use windows::{WindowsInputEngine as DefaultInputEngine, can_poll_keys, is_key_down};
+
+/// Makes the [`DefaultInputEngine`], failing when it has no way to send keys, e.g. no writable
+/// uinput device under the `linux-input` feature.
+pub fn new_default_engine(articulation: f64) -> anyhow::Result<DefaultInputEngine> {
+    #[cfg(all(target_os = "linux", feature = "linux-input"))]
+    let engine = DefaultInputEngine::new(articulation)?;
+    #[cfg(not(all(target_os = "linux", feature = "linux-input")))]
+    let engine = DefaultInputEngine::new(articulation);
+
+    Ok(engine)
+}
+
+/// Emulates vibrato by pulsing the play key while a long note is held, since the flute can't bend.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VibratoConfig {
+    /// How many times per second the play key is re-triggered.
+    pub rate_hz: f64,
+
+    /// Notes held for this long or shorter are played without vibrato.
+    pub min_note_ms: f64,
+}
+
+impl VibratoConfig {
+    pub fn new(rate_hz: f64) -> Self {
+        Self {
+            rate_hz,
+            min_note_ms: 400.0,
+        }
+    }
+}
+
+/// How the octave & semitone modifiers are entered for a note.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModifierStyle {
+    /// Held down along with the note's other keys & released with them.
+    #[default]
+    Hold,
+
+    /// Tapped once to toggle on & again to toggle off, for game versions that latch them. A
+    /// modifier stays on across consecutive notes that use it.
+    Tap,
+}
+
+/// Which modifiers are currently toggled on under [`ModifierStyle::Tap`].
+#[derive(Debug, Default)]
+pub struct ModifierToggles {
+    active: Mutex<Vec<VIRTUAL_KEY>>,
+}
+
+impl ModifierToggles {
+    pub fn active(&self) -> Vec<VIRTUAL_KEY> {
+        self.active
+            .lock()
+            .map(|active| active.clone())
+            .unwrap_or_default()
+    }
+}
 
 pub trait InputEngine: Send + Sync {
     fn get_articulation(&self) -> f64;
 
+    /// Changes how much of each note is held, taking effect from the next note played. Engines
+    /// with a fixed articulation ignore it.
+    fn set_articulation(&self, _articulation: f64) {}
+
+    /// The longest the play key may be held before it is re-triggered to keep a note sounding.
+    fn max_sustain_ms(&self) -> Option<f64> {
+        None
+    }
+
+    fn vibrato(&self) -> Option<VibratoConfig> {
+        None
+    }
+
+    /// The shortest an articulated note is held for (in ms), so short notes stay playable. Never
+    /// extends a hold past the note's full value.
+    fn min_hold_ms(&self) -> f64 {
+        0.0
+    }
+
+    /// The shortest the play key is held for any note (in ms), extending holds past the note's
+    /// value if need be, so the game registers even the fastest notes.
+    fn min_playable_hold_ms(&self) -> f64 {
+        0.0
+    }
+
+    /// How long (in ms) the game takes to sound a note once the play key is down, which playback
+    /// makes up for by pressing every note that much early.
+    fn key_registration_latency_ms(&self) -> f64 {
+        0.0
+    }
+
+    /// Whether the playback thread should raise its own priority, so other processes are less
+    /// likely to delay its key presses.
+    fn elevate_thread_priority(&self) -> bool {
+        true
+    }
+
+    /// The key that makes the flute sound, for players who rebound it in game.
+    fn play_key(&self) -> &'static VIRTUAL_KEY {
+        &PLAY_KEY
+    }
+
+    /// Where the modifiers toggled by [`ModifierStyle::Tap`] are tracked from one note to the next,
+    /// or `None` to hold them like any other key.
+    fn modifier_toggles(&self) -> Option<&ModifierToggles> {
+        None
+    }
+
     fn sleep(&self, duration_ms: Duration);
 
     fn key_up(&self, input: &Input) -> anyhow::Result<()>;
@@ -18,10 +162,19 @@ pub trait InputEngine: Send + Sync {
     fn key_down(&self, input: &Input) -> anyhow::Result<()>;
 
     fn all_keys_up(&self) -> anyhow::Result<()> {
-        for (_midi, input) in MAPPINGS {
+        self.key_up(&Input {
+            keys: std::slice::from_ref(self.play_key()),
+            note_label: "play_key",
+        })?;
+
+        for (_midi, input) in active_mappings() {
             self.key_up(input)?;
         }
 
+        if let Some(toggles) = self.modifier_toggles() {
+            tap_modifiers(self, toggles, &[])?;
+        }
+
         Ok(())
     }
 
@@ -30,41 +183,471 @@ pub trait InputEngine: Send + Sync {
             return Err(anyhow!("hold_ms must be greater than 0..!"));
         }
 
-        let play_input = Input {
-            keys: &[PLAY_KEY],
-            note_label: "play_key",
-        };
+        let final_hold_ms = self.articulated_hold_ms(hold_ms, articulation);
+        let release_ms = hold_ms - final_hold_ms;
+
+        let held = self.note_on(input)?;
+
+        let mut remaining_ms = final_hold_ms;
+        if let Some(retrigger_ms) = self.retrigger_interval_ms(hold_ms) {
+            while remaining_ms > retrigger_ms {
+                self.sleep(Duration::from_secs_f64(retrigger_ms / 1000.0));
+                self.retrigger()?;
+
+                remaining_ms -= retrigger_ms + RETRIGGER_GAP_MS;
+            }
+        }
+
+        self.sleep(Duration::from_secs_f64(remaining_ms.max(0.0) / 1000.0));
+
+        self.note_off(&held)?;
+        if release_ms > 0.0 {
+            self.sleep(Duration::from_secs_f64(release_ms / 1000.0));
+        }
+
+        Ok(())
+    }
 
-        let mut release_ms = 0.0;
+    /// How long the play key stays down for a note of `hold_ms` under `articulation`, leaving the
+    /// rest of the note silent.
+    fn articulated_hold_ms(&self, hold_ms: f64, articulation: f64) -> f64 {
         let mut final_hold_ms = hold_ms;
 
         if articulation > 0.0 && articulation < 1.0 {
             final_hold_ms *= articulation;
-            release_ms = hold_ms * (1.0 - articulation);
+
+            let min_hold_ms = self.min_hold_ms().min(hold_ms);
+            if final_hold_ms < min_hold_ms {
+                final_hold_ms = min_hold_ms;
+            }
         }
 
         if final_hold_ms <= 0.0 {
-            release_ms = 0.0;
             final_hold_ms = hold_ms;
         }
 
+        final_hold_ms.max(self.min_playable_hold_ms())
+    }
+
+    /// How often the play key is re-triggered while a note of `hold_ms` is held, for the max
+    /// sustain & vibrato, if at all.
+    fn retrigger_interval_ms(&self, hold_ms: f64) -> Option<f64> {
+        let vibrato_interval_ms = self
+            .vibrato()
+            .filter(|vibrato| vibrato.rate_hz > 0.0 && hold_ms > vibrato.min_note_ms)
+            .map(|vibrato| 1000.0 / vibrato.rate_hz);
+
+        [self.max_sustain_ms(), vibrato_interval_ms]
+            .into_iter()
+            .flatten()
+            .filter(|ms| *ms > 1.0)
+            .reduce(f64::min)
+    }
+
+    /// Presses the keys for `input`, then the play key once they've settled, & returns the keys
+    /// that are now held for [`InputEngine::note_off`] to release.
+    fn note_on(&self, input: &Input) -> anyhow::Result<Input> {
+        // Latched modifiers are toggled to match the note up front, leaving only the rest to hold.
+        let held = match self.modifier_toggles() {
+            Some(toggles) => Input {
+                keys: tap_modifiers(self, toggles, input.keys)?,
+                note_label: input.note_label,
+            },
+            None => *input,
+        };
+
         // Always press the play key last after pressing the other input keys.
-        self.key_down(input)?;
-        self.sleep(Duration::from_millis(1));
+        self.key_down(&held)?;
+        self.sleep(Duration::from_secs_f64(PLAY_KEY_SETTLE_MS / 1000.0));
 
-        self.key_down(&play_input)?;
-        self.sleep(Duration::from_secs_f64(final_hold_ms / 1000.0));
+        self.key_down(&self.play_input())?;
 
+        Ok(held)
+    }
+
+    /// Releases a note pressed by [`InputEngine::note_on`].
+    fn note_off(&self, held: &Input) -> anyhow::Result<()> {
         // Always release the play key first before releasing any other keys.
         // This avoids accidental wrong notes from incidental keypress races.
-        self.key_up(&play_input)?;
+        self.key_up(&self.play_input())?;
         self.sleep(Duration::from_millis(1));
 
-        self.key_up(input)?;
-        if release_ms > 0.0 {
-            self.sleep(Duration::from_secs_f64(release_ms / 1000.0));
+        self.key_up(held)
+    }
+
+    /// Re-triggers the play key while the note keys stay held down.
+    fn retrigger(&self) -> anyhow::Result<()> {
+        let play_input = self.play_input();
+
+        self.key_up(&play_input)?;
+        self.sleep(Duration::from_secs_f64(RETRIGGER_GAP_MS / 1000.0));
+        self.key_down(&play_input)
+    }
+
+    fn play_input(&self) -> Input {
+        Input {
+            keys: std::slice::from_ref(self.play_key()),
+            note_label: "play_key",
+        }
+    }
+
+    /// Presses `input` after `previous` has been released for the given duration. When the two
+    /// share some keys but not all of them (see [`shares_changed_keys`]), the keys that change are
+    /// released once more & left up for at least [`CHANGED_KEY_RELEASE_MS`] before pressing.
+    fn key_press_after(
+        &self,
+        previous: Option<(&Input, Duration)>,
+        input: &Input,
+        hold_ms: f64,
+        articulation: f64,
+    ) -> anyhow::Result<()> {
+        if let Some((previous, released_for)) = previous {
+            self.release_changed_keys(previous, released_for, input)?;
+        }
+
+        self.key_press(input, hold_ms, articulation)
+    }
+
+    /// The part of [`InputEngine::key_press_after`] that happens before `input` is pressed.
+    fn release_changed_keys(
+        &self,
+        previous: &Input,
+        released_for: Duration,
+        input: &Input,
+    ) -> anyhow::Result<()> {
+        if !shares_changed_keys(previous, input) {
+            return Ok(());
+        }
+
+        let keys = previous.keys;
+        for key in keys.iter().filter(|key| !input.keys.contains(key)) {
+            self.key_up(&Input {
+                keys: std::slice::from_ref(key),
+                note_label: previous.note_label,
+            })?;
+        }
+
+        let released_ms = released_for.as_secs_f64() * 1000.0;
+        if released_ms < CHANGED_KEY_RELEASE_MS {
+            self.sleep(Duration::from_secs_f64(
+                (CHANGED_KEY_RELEASE_MS - released_ms) / 1000.0,
+            ));
         }
 
         Ok(())
     }
 }
+
+/// Taps every modifier whose toggle doesn't match whether `keys` use it, so that exactly the ones
+/// in `keys` end up on, and returns the rest of `keys` to hold.
+fn tap_modifiers<E: InputEngine + ?Sized>(
+    engine: &E,
+    toggles: &ModifierToggles,
+    keys: &'static [VIRTUAL_KEY],
+) -> anyhow::Result<&'static [VIRTUAL_KEY]> {
+    let modifiers = modifier_keys();
+    let modifiers = [modifiers.octave, modifiers.semitone];
+    let Ok(mut active) = toggles.active.lock() else {
+        return Err(anyhow!("Failed to lock the modifier toggles..!"));
+    };
+
+    for modifier in modifiers.iter() {
+        let wanted = keys.contains(modifier);
+        if wanted == active.contains(modifier) {
+            continue;
+        }
+
+        let tap = Input {
+            keys: input_for_keys(&[modifier.0]).keys,
+            note_label: "modifier",
+        };
+        engine.key_down(&tap)?;
+        engine.sleep(Duration::from_millis(1));
+        engine.key_up(&tap)?;
+
+        if wanted {
+            active.push(*modifier);
+        } else {
+            active.retain(|key| key != modifier);
+        }
+    }
+
+    if !keys.iter().any(|key| modifiers.contains(key)) {
+        return Ok(keys);
+    }
+
+    let codes = keys
+        .iter()
+        .filter(|key| !modifiers.contains(key))
+        .map(|key| key.0)
+        .collect::<Vec<_>>();
+
+    Ok(input_for_keys(&codes).keys)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::mappings::{input_for_midi, key_by_name};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Call {
+        Down(Vec<u16>),
+        Up(Vec<u16>),
+        Sleep(Duration),
+    }
+
+    #[derive(Default)]
+    struct StubEngine {
+        max_sustain_ms: Option<f64>,
+        vibrato: Option<VibratoConfig>,
+        play_key: Option<&'static VIRTUAL_KEY>,
+        min_hold_ms: f64,
+        modifier_toggles: Option<ModifierToggles>,
+        play_presses: AtomicUsize,
+        calls: Mutex<Vec<Call>>,
+    }
+
+    fn key_codes(input: &Input) -> Vec<u16> {
+        input.keys.iter().map(|vk| vk.0).collect()
+    }
+
+    impl InputEngine for StubEngine {
+        fn get_articulation(&self) -> f64 {
+            1.0
+        }
+
+        fn max_sustain_ms(&self) -> Option<f64> {
+            self.max_sustain_ms
+        }
+
+        fn vibrato(&self) -> Option<VibratoConfig> {
+            self.vibrato
+        }
+
+        fn play_key(&self) -> &'static VIRTUAL_KEY {
+            self.play_key.unwrap_or(&PLAY_KEY)
+        }
+
+        fn min_hold_ms(&self) -> f64 {
+            self.min_hold_ms
+        }
+
+        fn modifier_toggles(&self) -> Option<&ModifierToggles> {
+            self.modifier_toggles.as_ref()
+        }
+
+        fn sleep(&self, duration_ms: Duration) {
+            self.calls.lock().unwrap().push(Call::Sleep(duration_ms));
+        }
+
+        fn key_up(&self, input: &Input) -> anyhow::Result<()> {
+            self.calls.lock().unwrap().push(Call::Up(key_codes(input)));
+            Ok(())
+        }
+
+        fn key_down(&self, input: &Input) -> anyhow::Result<()> {
+            if input.keys == [*self.play_key()] {
+                self.play_presses.fetch_add(1, Ordering::SeqCst);
+            }
+            self.calls
+                .lock()
+                .unwrap()
+                .push(Call::Down(key_codes(input)));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retrigger_long_sustain() {
+        let input = input_for_midi(69).expect("Midi values should be in range..!");
+
+        let engine = StubEngine::default();
+        assert!(engine.key_press(input, 3000.0, 1.0).is_ok());
+        assert_eq!(engine.play_presses.load(Ordering::SeqCst), 1);
+
+        let engine = StubEngine {
+            max_sustain_ms: Some(1000.0),
+            ..Default::default()
+        };
+        assert!(engine.key_press(input, 3000.0, 1.0).is_ok());
+        assert_eq!(engine.play_presses.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn vibrato_pulses_long_notes() {
+        let input = input_for_midi(69).expect("Midi values should be in range..!");
+
+        let engine = StubEngine {
+            vibrato: Some(VibratoConfig::new(5.0)),
+            ..Default::default()
+        };
+        assert!(engine.key_press(input, 2000.0, 1.0).is_ok());
+        assert_eq!(engine.play_presses.load(Ordering::SeqCst), 10);
+
+        // Short notes are left alone.
+        let engine = StubEngine {
+            vibrato: Some(VibratoConfig::new(5.0)),
+            ..Default::default()
+        };
+        assert!(engine.key_press(input, 300.0, 1.0).is_ok());
+        assert_eq!(engine.play_presses.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn press_rebound_play_key() {
+        let input = input_for_midi(69).expect("Midi values should be in range..!");
+        let play_key = key_by_name("Space").expect("Space should be a play key choice..!");
+
+        let engine = StubEngine {
+            play_key: Some(play_key),
+            ..Default::default()
+        };
+        assert!(engine.key_press(input, 100.0, 1.0).is_ok());
+        assert!(engine.all_keys_up().is_ok());
+
+        let calls = engine.calls.lock().unwrap().clone();
+        let downs = calls
+            .iter()
+            .filter_map(|call| match call {
+                Call::Down(keys) => Some(keys.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(downs, vec![key_codes(input), vec![play_key.0]]);
+        assert_eq!(engine.play_presses.load(Ordering::SeqCst), 1);
+
+        assert!(
+            !calls
+                .iter()
+                .any(|call| call == &Call::Down(vec![PLAY_KEY.0]))
+        );
+        assert!(calls.iter().any(|call| call == &Call::Up(vec![play_key.0])));
+    }
+
+    #[test]
+    fn staccato_keeps_min_hold() {
+        let input = input_for_midi(69).expect("Midi values should be in range..!");
+        let staccato = 0.5;
+
+        // How long the play key was held, and how long the note rested after its keys came up.
+        let hold_and_rest = |engine: &StubEngine| {
+            let calls = engine.calls.lock().unwrap().clone();
+            let sleep_after = |call: Call| match calls.iter().position(|c| *c == call) {
+                Some(i) => match calls.get(i + 1) {
+                    Some(Call::Sleep(duration)) => duration.as_secs_f64() * 1000.0,
+                    _ => 0.0,
+                },
+                None => 0.0,
+            };
+
+            (
+                sleep_after(Call::Down(vec![PLAY_KEY.0])),
+                sleep_after(Call::Up(key_codes(input))),
+            )
+        };
+
+        let engine = StubEngine {
+            min_hold_ms: 25.0,
+            ..Default::default()
+        };
+        assert!(engine.key_press(input, 30.0, staccato).is_ok());
+        let (hold, rest) = hold_and_rest(&engine);
+        assert!((hold - 25.0).abs() < 1e-6, "Held for {}ms..!", hold);
+        assert!((rest - 5.0).abs() < 1e-6, "Rested for {}ms..!", rest);
+
+        // Long notes scale as usual.
+        let engine = StubEngine {
+            min_hold_ms: 25.0,
+            ..Default::default()
+        };
+        assert!(engine.key_press(input, 2000.0, staccato).is_ok());
+        let (hold, rest) = hold_and_rest(&engine);
+        assert!((hold - 1000.0).abs() < 1e-6, "Held for {}ms..!", hold);
+        assert!((rest - 1000.0).abs() < 1e-6, "Rested for {}ms..!", rest);
+    }
+
+    #[test]
+    fn release_changed_keys_between_notes() {
+        // A#4 & C5 both hold the octave & semitone modifiers, but move from right to down-right.
+        let previous = input_for_midi(70).expect("Midi values should be in range..!");
+        let next = input_for_midi(72).expect("Midi values should be in range..!");
+
+        let engine = StubEngine::default();
+        assert!(
+            engine
+                .key_press_after(Some((previous, Duration::ZERO)), next, 100.0, 1.0)
+                .is_ok()
+        );
+
+        let changed = previous
+            .keys
+            .iter()
+            .filter(|key| !next.keys.contains(key))
+            .map(|key| key.0)
+            .collect::<Vec<_>>();
+        assert_eq!(changed.len(), 1);
+
+        let calls = engine.calls.lock().unwrap().clone();
+        assert_eq!(calls[0], Call::Up(changed));
+        assert_eq!(
+            calls[1],
+            Call::Sleep(Duration::from_secs_f64(CHANGED_KEY_RELEASE_MS / 1000.0))
+        );
+        assert_eq!(calls[2], Call::Down(key_codes(next)));
+
+        // Notes that share nothing don't need the extra release.
+        let engine = StubEngine::default();
+        let unrelated = input_for_midi(81).expect("Midi values should be in range..!");
+        assert!(
+            engine
+                .key_press_after(Some((unrelated, Duration::ZERO)), next, 100.0, 1.0)
+                .is_ok()
+        );
+        assert_eq!(engine.calls.lock().unwrap()[0], Call::Down(key_codes(next)));
+    }
+
+    #[test]
+    fn tap_shared_modifier_once() {
+        let octave = modifier_keys().octave;
+        let (a4, b4, a5) = (
+            input_for_midi(69).unwrap(),
+            input_for_midi(71).unwrap(),
+            input_for_midi(81).unwrap(),
+        );
+        assert!(a4.keys.contains(&octave) && b4.keys.contains(&octave));
+        assert!(!a5.keys.contains(&octave));
+
+        let engine = StubEngine {
+            modifier_toggles: Some(ModifierToggles::default()),
+            ..Default::default()
+        };
+        let octave_taps = || {
+            engine
+                .calls
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|call| **call == Call::Down(vec![octave.0]))
+                .count()
+        };
+
+        assert!(engine.key_press(a4, 200.0, 1.0).is_ok());
+        assert!(engine.key_press(b4, 200.0, 1.0).is_ok());
+        assert_eq!(octave_taps(), 1);
+        assert_eq!(engine.modifier_toggles().unwrap().active(), vec![octave]);
+
+        // The modifier is never held down along with a note.
+        let held_with_note = engine.calls.lock().unwrap().iter().any(
+            |call| matches!(call, Call::Down(keys) if keys.len() > 1 && keys.contains(&octave.0)),
+        );
+        assert!(!held_with_note);
+
+        // Leaving the octave taps it off again.
+        assert!(engine.key_press(a5, 200.0, 1.0).is_ok());
+        assert_eq!(octave_taps(), 2);
+        assert!(engine.modifier_toggles().unwrap().active().is_empty());
+    }
+}
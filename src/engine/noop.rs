@@ -0,0 +1,118 @@
+use crate::engine::{InputEngine, ModifierStyle, ModifierToggles, VibratoConfig};
+use crate::model::mappings::{Input, PLAY_KEY, VIRTUAL_KEY};
+use anyhow::Result;
+use log::debug;
+use spin_sleep::{SpinSleeper, SpinStrategy};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The default engine where there's no input backend to send keys through. It keeps the same
+/// settings & timing as the Windows engine but only logs the key batches it would send, so songs
+/// can still be imported & played through on any platform.
+#[derive(Debug)]
+pub struct NoopInputEngine {
+    sleeper: SpinSleeper,
+    /// The articulation's f64 bits, so it can be changed mid-song through a shared engine.
+    articulation: AtomicU64,
+    pub max_sustain_ms: Option<f64>,
+    pub vibrato: Option<VibratoConfig>,
+    pub elevate_thread_priority: bool,
+    pub play_key: &'static VIRTUAL_KEY,
+    pub min_hold_ms: f64,
+    pub min_playable_hold_ms: f64,
+    pub key_registration_latency_ms: f64,
+    pub modifier_style: ModifierStyle,
+    modifier_toggles: ModifierToggles,
+}
+
+impl NoopInputEngine {
+    pub fn new(articulation: f64) -> Self {
+        let sleeper = SpinSleeper::default().with_spin_strategy(SpinStrategy::YieldThread);
+        Self {
+            sleeper,
+            articulation: AtomicU64::new(articulation.to_bits()),
+            max_sustain_ms: None,
+            vibrato: None,
+            elevate_thread_priority: true,
+            play_key: &PLAY_KEY,
+            min_hold_ms: 0.0,
+            min_playable_hold_ms: 0.0,
+            key_registration_latency_ms: 0.0,
+            modifier_style: ModifierStyle::default(),
+            modifier_toggles: ModifierToggles::default(),
+        }
+    }
+}
+
+/// Never true, since only the Windows engine can poll the keyboard.
+pub fn is_key_down(_key: VIRTUAL_KEY) -> bool {
+    false
+}
+
+/// Whether [`is_key_down`] can tell if a key is held, which only the Windows engine can.
+pub fn can_poll_keys() -> bool {
+    false
+}
+
+impl InputEngine for NoopInputEngine {
+    fn get_articulation(&self) -> f64 {
+        f64::from_bits(self.articulation.load(Ordering::Relaxed))
+    }
+
+    fn set_articulation(&self, articulation: f64) {
+        self.articulation
+            .store(articulation.to_bits(), Ordering::Relaxed);
+    }
+
+    fn max_sustain_ms(&self) -> Option<f64> {
+        self.max_sustain_ms
+    }
+
+    fn vibrato(&self) -> Option<VibratoConfig> {
+        self.vibrato
+    }
+
+    fn min_hold_ms(&self) -> f64 {
+        self.min_hold_ms
+    }
+
+    fn min_playable_hold_ms(&self) -> f64 {
+        self.min_playable_hold_ms
+    }
+
+    fn key_registration_latency_ms(&self) -> f64 {
+        self.key_registration_latency_ms
+    }
+
+    fn elevate_thread_priority(&self) -> bool {
+        self.elevate_thread_priority
+    }
+
+    fn play_key(&self) -> &'static VIRTUAL_KEY {
+        self.play_key
+    }
+
+    fn modifier_toggles(&self) -> Option<&ModifierToggles> {
+        (self.modifier_style == ModifierStyle::Tap).then_some(&self.modifier_toggles)
+    }
+
+    fn sleep(&self, duration_ms: Duration) {
+        self.sleeper.sleep(duration_ms);
+    }
+
+    fn key_up(&self, combo: &Input) -> Result<()> {
+        debug!(
+            "NoopInputEngine::key_up for {} => keys {:?}",
+            combo.note_label, combo.keys
+        );
+        Ok(())
+    }
+
+    fn key_down(&self, combo: &Input) -> Result<()> {
+        debug!(
+            "NoopInputEngine::key_down for {} => keys {:?}",
+            combo.note_label, combo.keys
+        );
+        Ok(())
+    }
+}
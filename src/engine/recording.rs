@@ -0,0 +1,102 @@
+use crate::clock::Clock;
+use crate::engine::InputEngine;
+use crate::model::mappings::{Input, VIRTUAL_KEY};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedInput {
+    pub action: KeyAction,
+    pub keys: Vec<VIRTUAL_KEY>,
+    pub at: Instant,
+}
+
+/// An [`InputEngine`] that records every key batch it is asked to send instead of sending it.
+#[derive(Debug)]
+pub struct RecordingEngine {
+    articulation: AtomicU64,
+    input_delay: Duration,
+    clock: Option<Arc<dyn Clock>>,
+    log: Mutex<Vec<RecordedInput>>,
+}
+
+impl RecordingEngine {
+    pub fn new(articulation: f64) -> Self {
+        Self {
+            articulation: AtomicU64::new(articulation.to_bits()),
+            input_delay: Duration::ZERO,
+            clock: None,
+            log: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Simulates a slow input backend by blocking for `delay` on every key batch.
+    pub fn with_input_delay(mut self, delay: Duration) -> Self {
+        self.input_delay = delay;
+        self
+    }
+
+    /// Sleeps & timestamps each key batch on `clock` instead of the system's, e.g. the
+    /// [`MockClock`](crate::MockClock) playback runs on.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    pub fn recorded(&self) -> Vec<RecordedInput> {
+        self.log.lock().map(|log| log.clone()).unwrap_or_default()
+    }
+
+    fn record(&self, action: KeyAction, input: &Input) {
+        if !self.input_delay.is_zero() {
+            self.sleep(self.input_delay);
+        }
+
+        let at = self
+            .clock
+            .as_ref()
+            .map_or_else(Instant::now, |clock| clock.now());
+        if let Ok(mut log) = self.log.lock() {
+            log.push(RecordedInput {
+                action,
+                keys: input.keys.to_vec(),
+                at,
+            });
+        }
+    }
+}
+
+impl InputEngine for RecordingEngine {
+    fn get_articulation(&self) -> f64 {
+        f64::from_bits(self.articulation.load(Ordering::Relaxed))
+    }
+
+    fn set_articulation(&self, articulation: f64) {
+        self.articulation
+            .store(articulation.to_bits(), Ordering::Relaxed);
+    }
+
+    fn sleep(&self, duration_ms: Duration) {
+        match &self.clock {
+            Some(clock) => clock.sleep(duration_ms),
+            None => std::thread::sleep(duration_ms),
+        }
+    }
+
+    fn key_up(&self, input: &Input) -> anyhow::Result<()> {
+        self.record(KeyAction::Up, input);
+        Ok(())
+    }
+
+    fn key_down(&self, input: &Input) -> anyhow::Result<()> {
+        self.record(KeyAction::Down, input);
+        Ok(())
+    }
+}
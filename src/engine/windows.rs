@@ -1,19 +1,30 @@
-use crate::engine::InputEngine;
-use crate::model::mappings::Input;
+use crate::engine::{InputEngine, ModifierStyle, ModifierToggles, VibratoConfig};
+use crate::model::mappings::{Input, PLAY_KEY};
 use anyhow::Result;
-use log::debug;
+use log::{debug, warn};
 use spin_sleep::{SpinSleeper, SpinStrategy};
 use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT, KEYEVENTF_KEYUP, SendInput,
+    GetAsyncKeyState, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT,
+    KEYEVENTF_KEYUP, SendInput, VIRTUAL_KEY,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct WindowsInputEngine {
     sleeper: SpinSleeper,
-    pub articulation: f64,
+    /// The articulation's f64 bits, so it can be changed mid-song through a shared engine.
+    articulation: AtomicU64,
+    pub max_sustain_ms: Option<f64>,
+    pub vibrato: Option<VibratoConfig>,
     pub elevate_thread_priority: bool,
+    pub play_key: &'static VIRTUAL_KEY,
+    pub min_hold_ms: f64,
+    pub min_playable_hold_ms: f64,
+    pub key_registration_latency_ms: f64,
+    pub modifier_style: ModifierStyle,
+    modifier_toggles: ModifierToggles,
 }
 
 impl WindowsInputEngine {
@@ -21,12 +32,20 @@ impl WindowsInputEngine {
         let sleeper = SpinSleeper::default().with_spin_strategy(SpinStrategy::YieldThread);
         Self {
             sleeper,
-            articulation,
+            articulation: AtomicU64::new(articulation.to_bits()),
+            max_sustain_ms: None,
+            vibrato: None,
             elevate_thread_priority: true,
+            play_key: &PLAY_KEY,
+            min_hold_ms: 0.0,
+            min_playable_hold_ms: 0.0,
+            key_registration_latency_ms: 0.0,
+            modifier_style: ModifierStyle::default(),
+            modifier_toggles: ModifierToggles::default(),
         }
     }
 
-    fn build_keydown_inputs(combo: &Input) -> Vec<INPUT> {
+    pub(crate) fn build_keydown_inputs(combo: &Input) -> Vec<INPUT> {
         combo
             .keys
             .iter()
@@ -47,7 +66,7 @@ impl WindowsInputEngine {
             .collect()
     }
 
-    fn build_keyup_inputs(combo: &Input) -> Vec<INPUT> {
+    pub(crate) fn build_keyup_inputs(combo: &Input) -> Vec<INPUT> {
         combo
             .keys
             .iter()
@@ -68,25 +87,93 @@ impl WindowsInputEngine {
             .collect()
     }
 
-    fn send_inputs_batch(inputs: &mut [INPUT]) -> Result<()> {
-        unsafe {
-            let sent = SendInput(inputs, size_of::<INPUT>() as i32);
-            if sent == inputs.len() as u32 {
-                Ok(())
-            } else {
-                Err(anyhow::anyhow!(
-                    "SendInput failed: requested {}, sent {}..!",
-                    inputs.len(),
-                    sent
-                ))
+    fn send_input(inputs: &mut [INPUT]) -> u32 {
+        unsafe { SendInput(inputs, size_of::<INPUT>() as i32) }
+    }
+
+    fn send_inputs_batch<F>(combo: &Input, inputs: &mut [INPUT], mut send: F) -> Result<()>
+    where
+        F: FnMut(&mut [INPUT]) -> u32,
+    {
+        let sent = send(inputs);
+        if sent == inputs.len() as u32 {
+            return Ok(());
+        }
+
+        if sent > 0 {
+            // Only part of the combo made it through, so release all of it to get back to a known
+            // state rather than leaving a modifier stuck down.
+            let mut release = Self::build_keyup_inputs(combo);
+            let released = send(&mut release);
+
+            if released != release.len() as u32 {
+                warn!(
+                    "Failed to release {} after a partial SendInput: requested {}, sent {}..!",
+                    combo.note_label,
+                    release.len(),
+                    released
+                );
             }
         }
+
+        Err(anyhow::anyhow!(
+            "SendInput failed: requested {}, sent {}..!",
+            inputs.len(),
+            sent
+        ))
     }
 }
 
+/// Whether `key` is held down right now, whichever window has focus.
+pub fn is_key_down(key: VIRTUAL_KEY) -> bool {
+    unsafe { GetAsyncKeyState(key.0 as i32) < 0 }
+}
+
+/// Whether [`is_key_down`] can tell if a key is held, which it always can here.
+pub fn can_poll_keys() -> bool {
+    true
+}
+
 impl InputEngine for WindowsInputEngine {
     fn get_articulation(&self) -> f64 {
+        f64::from_bits(self.articulation.load(Ordering::Relaxed))
+    }
+
+    fn set_articulation(&self, articulation: f64) {
         self.articulation
+            .store(articulation.to_bits(), Ordering::Relaxed);
+    }
+
+    fn max_sustain_ms(&self) -> Option<f64> {
+        self.max_sustain_ms
+    }
+
+    fn vibrato(&self) -> Option<VibratoConfig> {
+        self.vibrato
+    }
+
+    fn min_hold_ms(&self) -> f64 {
+        self.min_hold_ms
+    }
+
+    fn min_playable_hold_ms(&self) -> f64 {
+        self.min_playable_hold_ms
+    }
+
+    fn key_registration_latency_ms(&self) -> f64 {
+        self.key_registration_latency_ms
+    }
+
+    fn elevate_thread_priority(&self) -> bool {
+        self.elevate_thread_priority
+    }
+
+    fn play_key(&self) -> &'static VIRTUAL_KEY {
+        self.play_key
+    }
+
+    fn modifier_toggles(&self) -> Option<&ModifierToggles> {
+        (self.modifier_style == ModifierStyle::Tap).then_some(&self.modifier_toggles)
     }
 
     fn sleep(&self, duration_ms: Duration) {
@@ -101,7 +188,7 @@ impl InputEngine for WindowsInputEngine {
             combo.note_label, combo.keys
         );
 
-        Self::send_inputs_batch(&mut inputs)
+        Self::send_inputs_batch(combo, &mut inputs, Self::send_input)
     }
 
     fn key_down(&self, combo: &Input) -> Result<()> {
@@ -112,16 +199,53 @@ impl InputEngine for WindowsInputEngine {
             combo.note_label, combo.keys
         );
 
-        Self::send_inputs_batch(&mut inputs)
+        Self::send_inputs_batch(combo, &mut inputs, Self::send_input)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::model::mappings::{Input, MAPPINGS, PLAY_KEY, input_for_midi};
+    use crate::model::mappings::{
+        DIR_7_UP, Input, MAPPINGS, PLAY_KEY, SEMITONE_MODIFIER, input_for_midi,
+    };
     use crate::util::ensure_active_window;
     use log::info;
+    use windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
+
+    #[test]
+    fn release_combo_after_partial_send() {
+        let combo = input_for_midi(70).expect("Midi values should be in range..!");
+        let mut inputs = WindowsInputEngine::build_keydown_inputs(combo);
+
+        let mut batches: Vec<Vec<(VIRTUAL_KEY, KEYBD_EVENT_FLAGS)>> = Vec::new();
+        let result = WindowsInputEngine::send_inputs_batch(combo, &mut inputs, |batch| {
+            batches.push(
+                batch
+                    .iter()
+                    .map(|input| unsafe { (input.Anonymous.ki.wVk, input.Anonymous.ki.dwFlags) })
+                    .collect(),
+            );
+
+            // Only the first key of the initial keydown batch goes through.
+            if batches.len() == 1 {
+                1
+            } else {
+                batch.len() as u32
+            }
+        });
+
+        assert!(result.is_err());
+        assert_eq!(batches.len(), 2);
+        assert_eq!(
+            batches[1],
+            combo
+                .keys
+                .iter()
+                .map(|&vk| (vk, KEYEVENTF_KEYUP))
+                .collect::<Vec<_>>()
+        );
+    }
 
     #[test]
     fn press_play_key() {
@@ -150,7 +274,7 @@ mod test {
                 delay_ms /= 2.0;
                 info!("Speeding up..!");
             }
-            for entry in MAPPINGS {
+            for entry in MAPPINGS.iter() {
                 ensure_active_window();
                 info!("Playing note: \"{}\"", entry.1.note_label);
                 assert!(engine.key_press(&entry.1, delay_ms, art).is_ok());
@@ -193,4 +317,30 @@ mod test {
             assert!(engine.key_press(&input, 150.0, art).is_ok());
         }
     }
+
+    /// Alternates A5, A6 & the raised G#6 combo that A6 could be confused with, to check by ear
+    /// that only the first two sound an octave apart.
+    #[test]
+    fn play_top_a() {
+        env_logger::try_init().unwrap_or(());
+
+        let art = 0.9;
+        let engine = WindowsInputEngine::new(art);
+        let raised_g_sharp = Input {
+            keys: &[DIR_7_UP, SEMITONE_MODIFIER],
+            note_label: "G#6 + semitone",
+        };
+
+        ensure_active_window();
+        for _ in 0..2 {
+            for midi in [81, 93] {
+                let input = input_for_midi(midi).expect("Midi values should be in range..!");
+                info!("Playing note: \"{}\"", input.note_label);
+                assert!(engine.key_press(input, 600.0, art).is_ok());
+            }
+
+            info!("Playing note: \"{}\"", raised_g_sharp.note_label);
+            assert!(engine.key_press(&raised_g_sharp, 600.0, art).is_ok());
+        }
+    }
 }
@@ -0,0 +1,285 @@
+use crate::model::mappings::{
+    DIR_1_RIGHT, DIR_2_DOWNRIGHT, DIR_3_DOWN, DIR_4_DOWNLEFT, DIR_5_LEFT, DIR_6_UPLEFT, DIR_7_UP,
+    DIR_8_UPRIGHT, PLAY_KEY, VIRTUAL_KEY, modifier_keys,
+};
+use crate::model::song::Song;
+use crate::player::schedule_for_song;
+use crate::window::GAME_WINDOW_TITLE;
+use anyhow::{Result, anyhow};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Writes an AutoHotkey (v1) script that sends the same key combos as a [`Player`](crate::Player)
+/// would for `song`, held for each note's full value.
+pub fn export_ahk<P: AsRef<Path>>(song: &Song, path: P) -> Result<()> {
+    export_ahk_with_articulation(song, path, 1.0, PLAY_KEY)
+}
+
+/// Like [`export_ahk`], but shortens each hold by the given articulation the same way the input
+/// engine does, & sounds each note with `play_key` instead of [`PLAY_KEY`].
+pub fn export_ahk_with_articulation<P: AsRef<Path>>(
+    song: &Song,
+    path: P,
+    articulation: f64,
+    play_key: VIRTUAL_KEY,
+) -> Result<()> {
+    let script = ahk_script(song, articulation, play_key);
+
+    fs::write(path.as_ref(), script).map_err(|e| {
+        anyhow!(
+            "Failed to write AHK script to {}: {}",
+            path.as_ref().display(),
+            e
+        )
+    })
+}
+
+fn ahk_script(song: &Song, articulation: f64, play_key: VIRTUAL_KEY) -> String {
+    let schedule = schedule_for_song(song);
+    let title = song.metadata.title.as_deref().unwrap_or("No Title");
+
+    let mut out = String::new();
+    let _ = writeln!(out, "; Generated by FLUTE_WELL from '{}'", title);
+    let _ = writeln!(out, "#NoEnv");
+    let _ = writeln!(out, "SendMode Input");
+    let _ = writeln!(out, "SetKeyDelay, -1");
+    let _ = writeln!(out, "WinActivate, {}", GAME_WINDOW_TITLE);
+    let _ = writeln!(out, "WinWaitActive, {}", GAME_WINDOW_TITLE);
+
+    let play_key = ahk_key_name(play_key.0);
+    let mut cursor_ms = 0.0;
+
+    for (i, event) in schedule.iter().enumerate() {
+        if event.time_ms > cursor_ms {
+            let _ = writeln!(out, "Sleep, {}", (event.time_ms - cursor_ms).round());
+            cursor_ms = event.time_ms;
+        }
+
+        // Never let a hold run into the next event's start, same as during playback.
+        let mut hold_ms = match schedule.get(i + 1) {
+            Some(next) if next.time_ms < event.time_ms + event.duration_ms => {
+                (next.time_ms - event.time_ms).max(1.0)
+            }
+            _ => event.duration_ms,
+        };
+
+        if articulation > 0.0 && articulation < 1.0 {
+            hold_ms *= articulation;
+        }
+
+        let keys = event
+            .input
+            .keys
+            .iter()
+            .map(|vk| ahk_key_name(vk.0))
+            .collect::<Vec<_>>();
+
+        let _ = writeln!(out, "; {}", event.input.note_label);
+        let _ = writeln!(out, "Send, {}", ahk_keys(&keys, "down"));
+        let _ = writeln!(out, "Sleep, 1");
+        let _ = writeln!(out, "Send, {{{} down}}", play_key);
+        let _ = writeln!(out, "Sleep, {}", hold_ms.round());
+        let _ = writeln!(out, "Send, {{{} up}}", play_key);
+        let _ = writeln!(out, "Sleep, 1");
+        let _ = writeln!(out, "Send, {}", ahk_keys(&keys, "up"));
+
+        cursor_ms += hold_ms + 2.0;
+    }
+
+    out
+}
+
+/// Renders `song` as a tab to play it by hand: one line per note with the beat it starts on, the
+/// direction to hold, the modifiers & how many beats it lasts, at the song's tempo (or 120 BPM).
+/// Notes the flute can't play are left out, like during playback.
+pub fn render_flute_tab(song: &Song) -> String {
+    let bpm = song.metadata.tempo_bpm.unwrap_or(120.0);
+    let ms_per_beat = 60_000.0 / bpm;
+    let modifiers = modifier_keys();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{} @ {:.0} bpm",
+        song.metadata.title.as_deref().unwrap_or("No Title"),
+        bpm
+    );
+    let _ = writeln!(out, "▼ octave down, ♯ semitone up");
+    let _ = writeln!(out, "{:>8}  dir mod  {:>6}  note", "beat", "beats");
+
+    for event in schedule_for_song(song) {
+        let arrow = event
+            .input
+            .keys
+            .iter()
+            .find_map(|&key| direction_arrow(key))
+            .unwrap_or('?');
+        let octave = if event.input.keys.contains(&modifiers.octave) {
+            '▼'
+        } else {
+            ' '
+        };
+        let semitone = if event.input.keys.contains(&modifiers.semitone) {
+            '♯'
+        } else {
+            ' '
+        };
+
+        let _ = writeln!(
+            out,
+            "{:>8.2}   {}  {}{}  {:>6.2}  {}",
+            event.time_ms / ms_per_beat,
+            arrow,
+            octave,
+            semitone,
+            event.duration_ms / ms_per_beat,
+            event.input.note_label
+        );
+    }
+
+    out
+}
+
+/// The arrow for the direction a key points the flute in.
+fn direction_arrow(key: VIRTUAL_KEY) -> Option<char> {
+    [
+        (DIR_1_RIGHT, '→'),
+        (DIR_2_DOWNRIGHT, '↘'),
+        (DIR_3_DOWN, '↓'),
+        (DIR_4_DOWNLEFT, '↙'),
+        (DIR_5_LEFT, '←'),
+        (DIR_6_UPLEFT, '↖'),
+        (DIR_7_UP, '↑'),
+        (DIR_8_UPRIGHT, '↗'),
+    ]
+    .into_iter()
+    .find_map(|(direction, arrow)| (direction == key).then_some(arrow))
+}
+
+fn ahk_keys(keys: &[String], action: &str) -> String {
+    keys.iter()
+        .map(|key| format!("{{{} {}}}", key, action))
+        .collect::<String>()
+}
+
+/// The AutoHotkey name for a Windows virtual-key code.
+fn ahk_key_name(vk: u16) -> String {
+    match vk {
+        0x30..=0x39 | 0x41..=0x5A => char::from(vk as u8).to_string(),
+        0x60..=0x69 => format!("Numpad{}", vk - 0x60),
+        _ => format!("vk{:02X}", vk),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Event, Metadata, Note};
+
+    fn two_note_song() -> Song {
+        Song {
+            metadata: Metadata {
+                title: Some(String::from("Two Notes")),
+                tempo_bpm: None,
+                markers: Vec::new(),
+            },
+            events: vec![
+                Event {
+                    note: Note {
+                        midi: 69,
+                        velocity: 100,
+                    },
+                    time_ms: 0.0,
+                    duration_ms: 400.0,
+                    override_keys: None,
+                },
+                Event {
+                    note: Note {
+                        midi: 71,
+                        velocity: 100,
+                    },
+                    time_ms: 500.0,
+                    duration_ms: 200.0,
+                    override_keys: None,
+                },
+            ],
+        }
+    }
+
+    fn export_script(articulation: f64, play_key: VIRTUAL_KEY) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "flute_well_export_{}_{}.ahk",
+            std::process::id(),
+            play_key.0
+        ));
+        assert!(
+            export_ahk_with_articulation(&two_note_song(), &path, articulation, play_key).is_ok()
+        );
+
+        let script = fs::read_to_string(&path).expect("Script should have been written..!");
+        let _ = fs::remove_file(&path);
+        script
+    }
+
+    #[test]
+    fn export_two_note_script() {
+        let script = export_script(0.5, PLAY_KEY);
+
+        // A4 is the octave modifier + right, B4 the octave modifier + down-right.
+        assert!(script.contains("Send, {1 down}{Numpad6 down}"));
+        assert!(script.contains("Send, {1 down}{Numpad3 down}"));
+        assert!(script.contains("Send, {Numpad5 down}"));
+        assert!(script.contains("Send, {Numpad5 up}"));
+
+        let sleeps = script
+            .lines()
+            .filter_map(|line| line.strip_prefix("Sleep, "))
+            .collect::<Vec<_>>();
+        assert_eq!(sleeps, vec!["1", "200", "1", "298", "1", "100", "1"]);
+    }
+
+    #[test]
+    fn export_with_another_play_key() {
+        let script = export_script(1.0, VIRTUAL_KEY(0x20));
+
+        assert!(script.contains("Send, {vk20 down}"));
+        assert!(script.contains("Send, {vk20 up}"));
+        assert!(!script.contains("Numpad5"));
+    }
+
+    #[test]
+    fn tab_shows_direction_and_modifiers() {
+        let song = Song {
+            metadata: Metadata {
+                title: Some(String::from("Tab")),
+                tempo_bpm: Some(120.0),
+                markers: Vec::new(),
+            },
+            events: [(69, 0.0, 500.0), (82, 500.0, 1000.0)]
+                .into_iter()
+                .map(|(midi, time_ms, duration_ms)| Event {
+                    note: Note {
+                        midi,
+                        velocity: 100,
+                    },
+                    time_ms,
+                    duration_ms,
+                    override_keys: None,
+                })
+                .collect(),
+        };
+
+        let tab = render_flute_tab(&song);
+        let lines = tab.lines().skip(3).collect::<Vec<_>>();
+
+        // A4 is right with the octave modifier, A#5 right with the semitone modifier.
+        assert_eq!(
+            lines,
+            vec![
+                "    0.00   →  ▼     1.00  A4 (69)",
+                "    1.00   →   ♯    2.00  A#5 (82)",
+            ]
+        );
+    }
+}
@@ -1,15 +1,28 @@
 #![allow(non_snake_case)]
 
+mod analysis;
+mod clock;
 mod engine;
+mod export;
 mod midi_importer;
 mod model;
 mod util;
 mod player;
+#[cfg(feature = "audio")]
+mod preview;
+mod window;
 
+pub use analysis::*;
+pub use clock::*;
 pub use engine::*;
+pub use export::*;
 pub use midi_importer::*;
+pub use model::articulation::*;
 pub use model::config::*;
 pub use model::song::*;
 pub use model::mappings::*;
 pub use util::*;
 pub use player::*;
+#[cfg(feature = "audio")]
+pub use preview::*;
+pub use window::*;
@@ -1,24 +1,285 @@
-use FLUTE_WELL::{Args, Player, import_midi_file, input_for_midi, parse_articulation, parse_policy, DefaultInputEngine};
-use anyhow::Result;
+use FLUTE_WELL::{Args, DefaultInputEngine, ImportOptions, export_ahk_with_articulation, Player, VibratoConfig, import_midi_file_with_options, import_per_channel, input_for_midi, parse_policy, range_analysis, save_song, mapping_range, PLAY_KEY, articulation_presets, transpose_coverage, ModifierKeys, key_by_name, set_modifier_keys, midi_file_info, build_scale_song, articulation_min_hold_ms, logger_builder, parse_log_format, load_articulation_schedule, InputEngine, import_and_merge_with_offsets, parse_modifier_style, load_recording, parse_articulation_checked, parse_emit_velocity, WindowTarget, parse_scale, parse_channel_gains, read_midi_bytes, is_key_down, ChangeReason, ImportDiagnostics, import_midi_file_with_diagnostics, recommend_transpose, render_flute_tab, mapping_coverage, active_bindings, Song, DescribeEngine, VIRTUAL_KEY, can_poll_keys, new_default_engine};
+use anyhow::{Result, bail};
 use clap::Parser;
 use log::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Below this share of notes in range, a better transpose is suggested.
+const ADVISE_TRANSPOSE_BELOW_PERCENT: f64 = 90.0;
+
+/// How often the tap tempo key is checked while playing.
+const HOTKEY_POLL_INTERVAL: Duration = Duration::from_millis(5);
 
 fn main() -> Result<()> {
-    env_logger::init();
     let args = Args::parse();
+    logger_builder(parse_log_format(&args.log_format)?).init();
+
     let policy = parse_policy(&args.policy);
-    let articulation = parse_articulation(&args.articulation_style, args.custom_articulation);
 
-    info!("Importing MIDI file: '{}'...", args.midi.display());
-    let song = import_midi_file(
-        &args.midi,
-        args.transpose,
+    if args.list_articulations {
+        for (name, articulation) in articulation_presets() {
+            info!(
+                "{:15} holds {:.0}% of each note",
+                name,
+                articulation * 100.0
+            );
+        }
+        info!("{:15} holds `--hold-percentage` of each note", "custom");
+        return Ok(());
+    }
+
+    if args.show_bindings {
+        for (action, key) in active_bindings(&args) {
+            info!("{:10} {}", action, key);
+        }
+        return Ok(());
+    }
+
+    if args.midi_info {
+        if args.midi.is_empty() {
+            bail!("No MIDI file given..!");
+        }
+
+        for midi in args.midi.iter() {
+            if args.midi.len() > 1 {
+                info!("{}:", midi.display());
+            }
+            let info = midi_file_info(midi)?;
+
+            info!(
+                "Format {} with {} track(s), {}",
+                info.format,
+                info.tracks.len(),
+                match info.ticks_per_quarter {
+                    Some(ticks) => format!("{} ticks per quarter", ticks),
+                    None => String::from("SMPTE timing"),
+                }
+            );
+            for (tick, bpm) in info.tempo_changes.iter() {
+                info!("Tempo {:.2} bpm at tick {}", bpm, tick);
+            }
+            for (tick, numerator, denominator) in info.time_signatures.iter() {
+                info!(
+                    "Time signature {}/{} at tick {}",
+                    numerator, denominator, tick
+                );
+            }
+            for (i, track) in info.tracks.iter().enumerate() {
+                let range = match track.pitch_range {
+                    Some((low, high)) => format!("pitches {}..={}", low, high),
+                    None => String::from("no pitches"),
+                };
+                info!(
+                    "Track {} ({}): {} notes, {}",
+                    i,
+                    track.name.as_deref().unwrap_or("unnamed"),
+                    track.notes,
+                    range
+                );
+            }
+        }
+
+        return Ok(());
+    }
+
+    let parsed = parse_articulation_checked(&args.articulation_style, args.custom_articulation)?;
+    if let Some(given) = parsed.clamped_from {
+        warn!(
+            "--hold-percentage {} is outside 0.0..=1.0, using {} instead..!",
+            given, parsed.articulation
+        );
+    }
+    if parsed.ignored_custom {
+        warn!(
+            "Ignoring --hold-percentage since the articulation style is {}, pass `custom` to use it..!",
+            parsed.preset
+        );
+    }
+    let articulation = parsed.articulation;
+
+    if args.octave_key.is_some() || args.semitone_key.is_some() {
+        let mut modifiers = ModifierKeys::default();
+
+        if let Some(name) = &args.octave_key {
+            let Some(key) = key_by_name(name) else {
+                bail!("Unknown octave modifier key '{}'..!", name);
+            };
+            modifiers.octave = *key;
+        }
+
+        if let Some(name) = &args.semitone_key {
+            let Some(key) = key_by_name(name) else {
+                bail!("Unknown semitone modifier key '{}'..!", name);
+            };
+            modifiers.semitone = *key;
+        }
+
+        if modifiers.octave == modifiers.semitone {
+            bail!("The octave & semitone modifiers can't share a key..!");
+        }
+
+        set_modifier_keys(modifiers);
+    }
+
+    if let Some(note_ms) = args.test_scale {
+        if note_ms <= 0.0 {
+            bail!("The test scale's note duration must be greater than 0..!");
+        }
+
+        let player = build_player(&args, articulation)?;
+        let scales = (0..args.test_scale_repeats.max(1))
+            .map(|_| build_scale_song(note_ms))
+            .collect();
+        player.load_playlist(scales)?;
+
+        play_until_done(player, &args)?;
+        return Ok(());
+    }
+
+    if let Some(path) = &args.replay {
+        let player = build_player(&args, articulation)?;
+        player.load_recorded(load_recording(path)?)?;
+
+        return play_until_done(player, &args);
+    }
+
+    // The flute only plays one note at a time, so an unreduced song is only good for inspecting.
+    if args.no_reduce && !(args.split_export.is_some() || args.dry_run || args.tab) {
+        bail!("--no-reduce only works with --split-export, --dry-run or --tab..!");
+    }
+
+    let options = ImportOptions {
+        transpose_semitones: args.transpose,
         policy,
-        args.merge_midi,
-        Some((69, 93)),
-    )?;
+        merge: args.merge_midi,
+        reduce: !args.no_reduce,
+        clip_to_range: Some(mapping_range()),
+        scale_constraint: args.scale.as_deref().map(parse_scale).transpose()?,
+        optimize_transpose: args.optimize_transpose,
+        program_filter: args.programs.clone(),
+        dedupe_note_ons: args.dedupe_note_ons,
+        lenient_channel_matching: args.lenient_noteoff,
+        merge_tracks: !args.no_merge_tracks,
+        max_autoclose_ms: args.max_autoclose,
+        use_aftertouch: args.use_aftertouch,
+        channel_gain: parse_channel_gains(&args.channel_gain)?,
+        use_release_velocity: args.use_release_velocity,
+        tempo_override_bpm: args.tempo,
+        default_tempo_bpm: args.default_tempo,
+        tempo_change_threshold_bpm: args.tempo_threshold,
+        arpeggiate_min_notes: args.arpeggiate_min_notes,
+        drop_probability: args.drop_prob,
+        drop_seed: args.drop_seed,
+        max_bars: args.bars,
+        gliss_threshold_semitones: args.gliss,
+        emit_velocity: parse_emit_velocity(&args.emit_velocity)?,
+        insert_rests: args.insert_rests,
+    };
+
+    let midi = match args.midi.as_slice() {
+        [] => bail!("No MIDI file given..!"),
+        [midi] => midi,
+        files => return play_playlist(&args, files, &options, articulation),
+    };
+    info!("Importing MIDI file: '{}'...", midi.display());
+
+    if args.analyze {
+        let unclipped = ImportOptions {
+            clip_to_range: None,
+            ..options
+        };
+        let song = import_midi_file_with_options(midi, &unclipped)?;
+        let analysis = range_analysis(&song);
+        let lowest = analysis
+            .min_pitch
+            .map_or("-".into(), |midi| midi.to_string());
+        let highest = analysis
+            .max_pitch
+            .map_or("-".into(), |midi| midi.to_string());
+
+        let (min_id, max_id) = mapping_range();
+        let label =
+            |midi: u8| input_for_midi(midi).map_or(midi.to_string(), |i| i.note_label.into());
+
+        info!(
+            "{} notes: {} below {}, {} within {}..={}, {} above {} | lowest: {} highest: {}",
+            mapping_coverage(&song).total,
+            analysis.below,
+            label(min_id),
+            analysis.within,
+            min_id,
+            max_id,
+            analysis.above,
+            label(max_id),
+            lowest,
+            highest,
+        );
+        return Ok(());
+    }
+
+    if args.transpose_report {
+        let unclipped = ImportOptions {
+            clip_to_range: None,
+            ..options
+        };
+        let song = import_midi_file_with_options(midi, &unclipped)?;
+
+        let (lowest, highest) = mapping_range();
+        let notes = mapping_coverage(&song).total;
+        for (shift, covered) in transpose_coverage(&song, (lowest, highest)) {
+            info!(
+                "{:+3} semitones: {}/{} notes within {}..={}",
+                shift, covered, notes, lowest, highest
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(dir) = &args.split_export {
+        let bytes = read_midi_bytes(midi)?;
+        let songs = import_per_channel(&bytes, &options)?;
+
+        fs::create_dir_all(dir)?;
+        for (channel, song) in songs.iter() {
+            let path = dir.join(format!("channel_{}.json", channel));
+            save_song(song, &path)?;
+            info!(
+                "Exported channel {} ({} events) to {}..!",
+                channel,
+                song.events.len(),
+                path.display()
+            );
+        }
+        return Ok(());
+    }
+
+    let song = if args.add.is_empty() {
+        let (song, diagnostics) = import_midi_file_with_diagnostics(midi, &options)?;
+
+        // Reading stdin twice would find it empty.
+        if !args.optimize_transpose && midi.as_path() != Path::new("-") {
+            advise_transpose(midi, &options, &diagnostics, args.transpose)?;
+        }
+
+        song
+    } else {
+        let mut files = vec![(midi.clone(), 0.0)];
+        for (i, path) in args.add.iter().enumerate() {
+            let offset_ms = args.add_offset.get(i).copied().unwrap_or(0.0);
+            info!(
+                "Merging in MIDI file: '{}' at {}ms...",
+                path.display(),
+                offset_ms
+            );
+            files.push((path.clone(), offset_ms));
+        }
+
+        import_and_merge_with_offsets(&files, &options)?
+    };
 
     debug!(
         "Imported song '{}' with {} events..!",
@@ -29,40 +290,333 @@ fn main() -> Result<()> {
         song.events.len()
     );
 
-    if args.dry_run {
+    if args.tab {
+        print!("{}", render_flute_tab(&song));
+        return Ok(());
+    }
+
+    if let Some(path) = &args.export_ahk {
+        export_ahk_with_articulation(&song, path, articulation, *play_key(&args)?)?;
+        info!("Exported AutoHotkey script to {}..!", path.display());
+        return Ok(());
+    }
+
+    #[cfg(feature = "audio")]
+    if args.preview_audio {
+        FLUTE_WELL::play_audio_preview(&song)?;
+        return Ok(());
+    }
+
+    if args.dry_run || args.strict_dry_run {
         info!("Previewing at most {} events..!", args.dry_run_max);
         for (i, ev) in song.events.iter().enumerate() {
             if i >= args.dry_run_max {
                 break;
             }
             let midi = ev.note.midi;
-            let keys = input_for_midi(midi)
-                .map(|inp| format!("{:?}", inp.keys))
-                .unwrap_or_else(|| "<no-mapping>".into());
+            let keys = if ev.is_rest() {
+                String::from("<rest>")
+            } else {
+                input_for_midi(midi)
+                    .map(|inp| format!("{:?}", inp.keys))
+                    .unwrap_or_else(|| "<no-mapping>".into())
+            };
 
             info!(
                 "Event {}: midi={} time_ms={:.3} dur_ms={:.3} keys={}",
                 i, midi, ev.time_ms, ev.duration_ms, keys
             );
         }
+
+        let coverage = mapping_coverage(&song);
+        info!(
+            "{} of {} events mappable..!",
+            coverage.mappable, coverage.total
+        );
+        if args.strict_dry_run && coverage.unmappable() > 0 {
+            bail!(
+                "{} event(s) have no keys to press..!",
+                coverage.unmappable()
+            );
+        }
         return Ok(());
     }
 
-    let player = Player::new(
-        DefaultInputEngine::new(articulation),
-        args.verbose,
-        args.delay_start,
-    );
+    if args.preview_keys {
+        return preview_keys(&args, &song, articulation);
+    }
 
+    let player = build_player(&args, articulation)?;
     player.load_song(song)?;
+
+    play_until_done(player, &args)
+}
+
+/// Logs the key batches & sleeps the input engine would send for the start of `song`, without
+/// sending any.
+fn preview_keys(args: &Args, song: &Song, articulation: f64) -> Result<()> {
+    let engine = DescribeEngine::new(build_engine(args, articulation)?).with_echo(true);
+    let player = configure_player(Player::new(engine, true, 0), args)?.with_virtual_time();
+
+    // The song goes through the same timeline as real playback, just without waiting on it.
+    let mut song = song.clone();
+    song.events.truncate(args.dry_run_max);
+    player.load_song(song)?;
+
+    info!("Previewing keys for at most {} events..!", args.dry_run_max);
+    player.play(true)
+}
+
+/// Suggests a better `--transpose` when the chosen one leaves many notes outside the flute's range,
+/// before octave folding moves them in. The file is only imported again, unclipped, when the
+/// `diagnostics` of its first import show some notes were out of range.
+fn advise_transpose(
+    midi: &Path,
+    options: &ImportOptions,
+    diagnostics: &ImportDiagnostics,
+    transpose: i32,
+) -> Result<()> {
+    let out_of_range = diagnostics.changes.iter().any(|change| {
+        matches!(
+            change.reason,
+            ChangeReason::OutOfRange | ChangeReason::OctaveFolded
+        )
+    });
+    if !out_of_range {
+        return Ok(());
+    }
+
+    let unclipped = ImportOptions {
+        clip_to_range: None,
+        ..options.clone()
+    };
+    let song = import_midi_file_with_options(midi, &unclipped)?;
+    let analysis = range_analysis(&song);
+    let notes = analysis.below + analysis.within + analysis.above;
+    if notes == 0 {
+        return Ok(());
+    }
+
+    let covered_percent = analysis.within as f64 * 100.0 / notes as f64;
+    if covered_percent >= ADVISE_TRANSPOSE_BELOW_PERCENT {
+        return Ok(());
+    }
+
+    let advice = recommend_transpose(&song, mapping_range());
+    if advice.shift != 0 {
+        warn!(
+            "Only {:.0}% of notes are in range at --transpose {}. {} (--transpose {})..!",
+            covered_percent,
+            transpose,
+            advice.note,
+            transpose + advice.shift
+        );
+    }
+
+    Ok(())
+}
+
+/// Imports every file & plays them back to back as one playlist.
+fn play_playlist(
+    args: &Args,
+    files: &[PathBuf],
+    options: &ImportOptions,
+    articulation: f64,
+) -> Result<()> {
+    if args.analyze
+        || args.transpose_report
+        || args.split_export.is_some()
+        || args.export_ahk.is_some()
+        || args.tab
+        || args.dry_run
+        || args.strict_dry_run
+        || args.preview_keys
+        || !args.add.is_empty()
+    {
+        bail!(
+            "--analyze, --transpose-report, --split-export, --export-ahk, --tab, --dry-run, \
+             --strict-dry-run, --preview-keys & --add only take a single MIDI file..!"
+        );
+    }
+
+    let songs = files
+        .iter()
+        .map(|midi| {
+            info!("Importing MIDI file: '{}'...", midi.display());
+            import_midi_file_with_options(midi, options)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let player = build_player(args, articulation)?;
+    player.load_playlist(songs)?;
+
+    play_until_done(player, args)
+}
+
+/// Sets up the input engine from the playback flags.
+fn build_engine(args: &Args, articulation: f64) -> Result<DefaultInputEngine> {
+    let mut engine = new_default_engine(articulation)?;
+    engine.max_sustain_ms = args.max_sustain;
+    engine.vibrato = args.vibrato_rate.map(VibratoConfig::new);
+    engine.min_hold_ms = args
+        .min_hold
+        .unwrap_or_else(|| articulation_min_hold_ms(&args.articulation_style));
+
+    if let Some(hold_ms) = args.min_playable_hold {
+        if !(hold_ms.is_finite() && hold_ms >= 0.0) {
+            bail!("The min playable hold must be a non-negative number of ms..!");
+        }
+        engine.min_playable_hold_ms = hold_ms;
+    }
+
+    if let Some(latency_ms) = args.key_latency {
+        if !(latency_ms.is_finite() && latency_ms >= 0.0) {
+            bail!("The key latency must be a non-negative number of ms..!");
+        }
+        engine.key_registration_latency_ms = latency_ms;
+    }
+
+    engine.play_key = play_key(args)?;
+    engine.modifier_style = parse_modifier_style(&args.modifier_style)?;
+    engine.elevate_thread_priority = !args.no_priority_boost;
+
+    Ok(engine)
+}
+
+/// Sets up the player from the playback flags.
+fn build_player(args: &Args, articulation: f64) -> Result<Player<DefaultInputEngine>> {
+    let engine = build_engine(args, articulation)?;
+    let player = Player::new(engine, args.verbose, args.delay_start);
+    let mut player = configure_player(player, args)?.with_hold_final_note(args.hold_final);
+
+    if let Some(path) = &args.record {
+        player = player.with_recording(path);
+    }
+
+    if let Some(path) = &args.progress_file {
+        if !(args.progress_interval.is_finite() && args.progress_interval > 0.0) {
+            bail!("The progress interval must be a positive number of seconds..!");
+        }
+        player = player.with_progress_file(path, Duration::from_secs_f64(args.progress_interval));
+    }
+
+    if let Some(pid) = args.window_pid {
+        player = player.with_window_target(WindowTarget::Pid(pid));
+    }
+
+    Ok(player)
+}
+
+/// Applies the flags that shape how each note is timed & held, shared by playback & the preview.
+fn configure_player<E: InputEngine + 'static>(player: Player<E>, args: &Args) -> Result<Player<E>> {
+    if !(0.0..=20.0).contains(&args.final_spin) {
+        bail!("The final spin must be between 0 & 20ms..!");
+    }
+
+    let mut player = player
+        .with_warmup(args.warmup)
+        .with_window_check_interval(Duration::from_millis(args.window_check_interval))
+        .with_play_key_offset(args.play_key_offset.unwrap_or(0.0))
+        .with_catch_up(!args.no_catch_up)
+        .with_final_spin(Duration::from_secs_f64(args.final_spin / 1000.0));
+
+    if let Some(path) = &args.articulation_schedule {
+        player = player.with_articulation_schedule(load_articulation_schedule(path)?);
+    }
+
+    Ok(player)
+}
+
+/// Plays whatever the player has loaded to the end, or until Ctrl-C or the panic key stops it. The
+/// pause key, if any, pauses & resumes it along the way.
+fn play_until_done(player: Player<DefaultInputEngine>, args: &Args) -> Result<()> {
+    // Without a way to read the keyboard, a hotkey would never fire.
+    let hotkeys = [&args.tap_key, &args.panic_key, &args.pause_key];
+    if !can_poll_keys() && hotkeys.iter().any(|key| key.is_some()) {
+        bail!("Hotkeys need the Windows input engine to read the keyboard..!");
+    }
+
     let player = Arc::new(player);
-    let player_for_handler = Arc::clone(&player);
-    let (done_tx, _done_rx) = mpsc::channel::<()>();
 
+    let tap_key = match &args.tap_key {
+        Some(name) => {
+            let Some(tap_key) = key_by_name(name) else {
+                bail!("Unknown tap key '{}'..!", name);
+            };
+            info!("Tap {} along with the song to set its tempo..!", name);
+            Some(tap_key)
+        }
+        None => None,
+    };
+
+    let panic_key = match &args.panic_key {
+        Some(name) => {
+            let Some(panic_key) = key_by_name(name) else {
+                bail!("Unknown panic key '{}'..!", name);
+            };
+            info!("Press {} to release every key & stop playback..!", name);
+            Some(panic_key)
+        }
+        None => None,
+    };
+
+    let pause_key = match &args.pause_key {
+        Some(name) => {
+            let Some(pause_key) = key_by_name(name) else {
+                bail!("Unknown pause key '{}'..!", name);
+            };
+            info!("Press {} to pause & resume playback..!", name);
+            Some(pause_key)
+        }
+        None => None,
+    };
+
+    // Polls for as long as the process runs, which ends along with playback.
+    let player_for_hotkeys = Arc::clone(&player);
+    thread::spawn(move || {
+        let (mut tap_was_down, mut panic_was_down, mut pause_was_down) = (false, false, false);
+        let mut paused = false;
+        loop {
+            if let Some(pause_key) = pause_key {
+                let down = is_key_down(*pause_key);
+                if down && !pause_was_down {
+                    let toggled = if paused {
+                        player_for_hotkeys.resume()
+                    } else {
+                        player_for_hotkeys.pause()
+                    };
+                    if toggled.is_ok() {
+                        paused = !paused;
+                    }
+                }
+                pause_was_down = down;
+            }
+
+            if let Some(tap_key) = tap_key {
+                let down = is_key_down(*tap_key);
+                if down && !tap_was_down {
+                    player_for_hotkeys.tap_tempo();
+                }
+                tap_was_down = down;
+            }
+
+            if let Some(panic_key) = panic_key {
+                let down = is_key_down(*panic_key);
+                if down && !panic_was_down {
+                    warn!("Panic key pressed, stopping playback..!");
+                    let _ = player_for_hotkeys.stop();
+                }
+                panic_was_down = down;
+            }
+
+            thread::sleep(HOTKEY_POLL_INTERVAL);
+        }
+    });
+
+    let player_for_handler = Arc::clone(&player);
     ctrlc::set_handler(move || {
         warn!("Ctrl-C received, stopping playback..!");
         let _ = player_for_handler.stop();
-        let _ = done_tx.send(());
     })
     .expect("Error setting Ctrl-C handler..!");
 
@@ -71,3 +625,30 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// The key chosen with `--play-key`, or the default one.
+fn play_key(args: &Args) -> Result<&'static VIRTUAL_KEY> {
+    let Some(name) = &args.play_key else {
+        return Ok(&PLAY_KEY);
+    };
+    let Some(play_key) = key_by_name(name) else {
+        bail!("Unknown play key '{}'..!", name);
+    };
+
+    Ok(play_key)
+}
+
+// The uinput engine would make a real virtual keyboard, or fail without one.
+#[cfg(all(test, not(all(target_os = "linux", feature = "linux-input"))))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_priority_boost_reaches_engine() {
+        let args = Args::try_parse_from(["FLUTE_WELL", "song.mid"]).unwrap();
+        assert!(build_engine(&args, 0.75).unwrap().elevate_thread_priority);
+
+        let args = Args::try_parse_from(["FLUTE_WELL", "song.mid", "--no-priority-boost"]).unwrap();
+        assert!(!build_engine(&args, 0.75).unwrap().elevate_thread_priority);
+    }
+}
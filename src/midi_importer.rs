@@ -1,15 +1,36 @@
+use crate::model::mappings::{FLUTE_RANGE, input_for_midi, modifier_keys};
 use crate::model::song::*;
-use anyhow::{Result, anyhow};
-use log::{debug, warn};
-use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use anyhow::{Result, anyhow, bail};
+use log::{debug, info, warn};
+use midly::{Format, MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
 use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
 const EPSILON_MS: f64 = 2.0;
+const BOUNDARY_EPSILON_MS: f64 = 1e-6;
 const DEFAULT_MPQN: u32 = 500_000;
 const MICROSECONDS_PER_MINUTE: f64 = 60_000_000.0;
 
+/// The gap between the onsets of consecutive notes in a rolled chord.
+const ARPEGGIO_STEP_MS: f64 = 40.0;
+
+/// The most of a note's tail a glissando into the next note may take up.
+const GLISS_BUDGET_MS: f64 = 120.0;
+
+/// The shortest connecting note in a glissando.
+const MIN_GLISS_STEP_MS: f64 = 15.0;
+
+/// The release velocity sent by devices that don't sense one, also used for implied NoteOffs.
+const DEFAULT_RELEASE_VELOCITY: u8 = 64;
+
+/// The shortest fraction of its value a note is held for under [`release_articulation`].
+const MIN_RELEASE_ARTICULATION: f64 = 0.5;
+
+/// How long a held note takes to fade to half its onset velocity under [`EmitVelocity::Decay`].
+const VELOCITY_HALF_LIFE_MS: f64 = 1000.0;
+
 /// Simple policy for converting polyphonic MIDI to a single monophonic flute line.
 #[derive(Debug, Clone, Copy, Default)]
 pub enum PolyPolicy {
@@ -27,14 +48,225 @@ pub enum PolyPolicy {
     Densest,
 }
 
+/// Which velocity an event emitted by the monophonic reduction carries, when the note it comes from
+/// was split or trimmed by other notes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmitVelocity {
+    /// The velocity the note was struck with.
+    #[default]
+    Onset,
+
+    /// The loudest velocity of any note sounding while the emitted event plays, including its own.
+    Peak,
+
+    /// The onset velocity, halved for every [`VELOCITY_HALF_LIFE_MS`] the note has been held by the
+    /// end of the emitted event, like a sustained note fading out.
+    Decay,
+}
+
+/// Options controlling how a MIDI file is converted into a monophonic [`Song`].
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Transpose in semitones (positive or negative).
+    pub transpose_semitones: i32,
+
+    /// Policy used to reduce overlapping events to a single line.
+    pub policy: PolyPolicy,
+
+    /// Whether to merge consecutive events of the same pitch during reduction.
+    pub merge: bool,
+
+    /// Reduce the song to a single monophonic line. When false, every overlapping event is kept,
+    /// still transposed & folded into range, e.g. to export the song for another tool to edit.
+    pub reduce: bool,
+
+    /// Notes outside this range are octave-folded into it, or dropped if they can't be.
+    pub clip_to_range: Option<(u8, u8)>,
+
+    /// Snap every transposed note to the nearest pitch whose pitch class (0 for C up to 11 for B)
+    /// is in this list, staying within `clip_to_range`, to keep a song in a scale. See
+    /// [`snap_to_scale`].
+    pub scale_constraint: Option<Vec<u8>>,
+
+    /// Replace `transpose_semitones` with the shift that fits the most notes while toggling the
+    /// octave & semitone modifiers the least. See [`optimal_transpose_for_mappings`].
+    pub optimize_transpose: bool,
+
+    /// Only keep notes played while their channel is set to one of these (0-based) GM programs.
+    pub program_filter: Option<Vec<u8>>,
+
+    /// Coalesce repeated NoteOns for a (channel, key) that is already sounding into one note,
+    /// which is then closed by the outermost NoteOff.
+    pub dedupe_note_ons: bool,
+
+    /// Let a NoteOff with no open note of its key on its own channel close the oldest open note of
+    /// that key on any other channel, for files that send them on the wrong channel.
+    pub lenient_channel_matching: bool,
+
+    /// Combine the notes of every track before reducing them to monophony. When false, each track
+    /// is reduced on its own & only the one with the highest note density is kept, as it always is
+    /// under [`PolyPolicy::Densest`]. Format 0 files hold a single track, so this only makes a
+    /// difference for format 1.
+    pub merge_tracks: bool,
+
+    /// Caps how long a NoteOn that never gets its NoteOff may sound, rather than letting it run
+    /// until the end of the song.
+    pub max_autoclose_ms: Option<f64>,
+
+    /// Let channel pressure (aftertouch) replace the velocity of every note sounding on its
+    /// channel, for files that shape their dynamics with pressure rather than velocity.
+    pub use_aftertouch: bool,
+
+    /// Multiply the velocity of every note on a (0-based) channel by that channel's gain before the
+    /// reduction to monophony, clamped to 127, e.g. to boost a quietly recorded melody channel
+    /// above the accompaniment for the Loudest policy. Channels without a gain are left as is.
+    pub channel_gain: HashMap<u8, f64>,
+
+    /// Shorten notes by how sharply they were released, according to [`release_articulation`].
+    pub use_release_velocity: bool,
+
+    /// Ignore every tempo change in the file & play it at this constant BPM instead.
+    pub tempo_override_bpm: Option<f64>,
+
+    /// The BPM assumed until the file's first tempo change, or 120 BPM when `None`.
+    pub default_tempo_bpm: Option<f64>,
+
+    /// Ignore tempo changes within this many BPM of the tempo already in effect, smoothing out the
+    /// jitter of files with a flood of tiny, humanized tempo changes.
+    pub tempo_change_threshold_bpm: Option<f64>,
+
+    /// Roll chords into quick arpeggios whenever at least this many pitches sound at their onset,
+    /// leaving smaller overlaps to the policy. Zero never rolls.
+    pub arpeggiate_min_notes: usize,
+
+    /// The chance each imported event is left out, as if a player skipped it. See [`drop_notes`].
+    pub drop_probability: f64,
+
+    /// Seeds which events are dropped, so the same seed always drops the same ones.
+    pub drop_seed: u64,
+
+    /// Only import this many bars from the start, measured by the file's time signatures, clipping
+    /// any note that runs past the last one.
+    pub max_bars: Option<u32>,
+
+    /// Fill leaps of more than this many semitones between consecutive notes with quick connecting
+    /// notes in the tail of the first one, emulating a glissando the flute can't bend.
+    pub gliss_threshold_semitones: Option<u8>,
+
+    /// The velocity given to each event the reduction to monophony emits.
+    pub emit_velocity: EmitVelocity,
+
+    /// Fill the gaps between the notes of the reduced song with [`Event::rest`]s, see
+    /// [`fill_rests`]. Only applies when `reduce` is set.
+    pub insert_rests: bool,
+}
+
+/// Things worth knowing about how an import went, beyond the [`Song`] itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportDiagnostics {
+    /// How many pairs of overlapping notes with different pitches were folded onto the same
+    /// pitch by `clip_to_range`, turning them into unisons the reduction then merges.
+    pub fold_collisions: usize,
+
+    /// The most notes sounding at once before the reduction to monophony.
+    pub max_concurrent_notes: usize,
+
+    /// How much of the song, from its first onset to its last release, had more than one note
+    /// sounding before the reduction, from 0 to 1.
+    pub polyphonic_fraction: f64,
+
+    /// Every note that was dropped or moved to another pitch on its way into the song, in the
+    /// order the notes start. Notes left out by the reduction to monophony aren't included.
+    pub changes: Vec<EventChange>,
+}
+
+/// A note the import dropped or moved, see [`ImportDiagnostics::changes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventChange {
+    /// The note's pitch in the file, before any transpose.
+    pub midi: u8,
+
+    /// When the note starts.
+    pub time_ms: f64,
+
+    pub action: ChangeAction,
+    pub reason: ChangeReason,
+}
+
+/// What happened to a note, see [`EventChange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeAction {
+    /// Left out of the song.
+    Dropped,
+
+    /// Kept, but played at this pitch instead.
+    Moved(u8),
+}
+
+/// Why a note was changed, see [`EventChange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeReason {
+    /// Outside `clip_to_range` (or MIDI's 0..=127) after the transpose, with no octave inside it.
+    OutOfRange,
+
+    /// Shifted by octaves into `clip_to_range`.
+    OctaveFolded,
+
+    /// Moved to the nearest pitch of `scale_constraint`.
+    SnappedToScale,
+
+    /// Ends at or before its start.
+    NoDuration,
+
+    /// Shorter than the shortest event worth playing, which would only click.
+    TooShort,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            transpose_semitones: 0,
+            policy: PolyPolicy::default(),
+            merge: false,
+            reduce: true,
+            clip_to_range: None,
+            scale_constraint: None,
+            optimize_transpose: false,
+            program_filter: None,
+            dedupe_note_ons: false,
+            lenient_channel_matching: false,
+            merge_tracks: true,
+            max_autoclose_ms: None,
+            use_aftertouch: false,
+            channel_gain: HashMap::new(),
+            use_release_velocity: false,
+            tempo_override_bpm: None,
+            default_tempo_bpm: None,
+            tempo_change_threshold_bpm: None,
+            arpeggiate_min_notes: 0,
+            drop_probability: 0.0,
+            drop_seed: 0,
+            max_bars: None,
+            gliss_threshold_semitones: None,
+            emit_velocity: EmitVelocity::default(),
+            insert_rests: false,
+        }
+    }
+}
+
 struct NoteInterval {
     pub midi: u8,
     pub start_tick: u64,
     pub end_tick: u64,
     pub velocity: u8,
-    pub _channel: u8,
+    pub release_velocity: u8,
+    pub channel: u8,
+    pub track: usize,
 }
 
+/// Notes still waiting on their NoteOff, keyed by (channel, key) as (start tick, velocity, track).
+type OpenNotes = HashMap<(u8, u8), Vec<(u64, u8, usize)>>;
+
 #[derive(Debug, Clone)]
 struct TempoSegment {
     pub mpqn: u32,
@@ -42,6 +274,101 @@ struct TempoSegment {
     pub ms_at_start: f64,
 }
 
+/// Converts absolute ticks to milliseconds across every tempo change in the file.
+#[derive(Debug, Clone)]
+struct TempoMap {
+    segments: Vec<TempoSegment>,
+    ticks_per_quarter: u64,
+
+    /// Set for SMPTE timecode timing, where ticks run at a fixed rate & tempo changes don't move
+    /// them. `ticks_per_quarter` then holds the ticks in a quarter note at the default tempo.
+    ticks_per_second: Option<f64>,
+
+    /// Every time signature as `(tick, numerator, denominator as a power of 2)`, in tick order.
+    time_signatures: Vec<(u64, u8, u8)>,
+    /// Every marker & cue point as `(tick, label)`, in tick order.
+    markers: Vec<(u64, String)>,
+}
+
+impl TempoMap {
+    fn ticks_to_ms(&self, tick: u64) -> f64 {
+        if let Some(ticks_per_second) = self.ticks_per_second {
+            return (tick as f64) * 1000.0 / ticks_per_second;
+        }
+
+        let ticks_per_quarter = self.ticks_per_quarter as f64;
+
+        if self.segments.is_empty() {
+            // default 120bpm
+            return (tick as f64) * DEFAULT_MPQN as f64 / ticks_per_quarter / 1000.0;
+        }
+
+        let segment = match self.segments.iter().rfind(|seg| seg.start_tick <= tick) {
+            Some(s) => s,
+            None => &self.segments[0],
+        };
+
+        let delta_ticks = (tick - segment.start_tick) as f64;
+        segment.ms_at_start + delta_ticks * (segment.mpqn as f64) / ticks_per_quarter / 1000.0
+    }
+
+    fn ms_to_ticks(&self, ms: f64) -> u64 {
+        if let Some(ticks_per_second) = self.ticks_per_second {
+            return (ms * ticks_per_second / 1000.0).round().max(0.0) as u64;
+        }
+
+        let ticks_per_quarter = self.ticks_per_quarter as f64;
+
+        let (start_tick, ms_at_start, mpqn) =
+            match self.segments.iter().rfind(|seg| seg.ms_at_start <= ms) {
+                Some(seg) => (seg.start_tick, seg.ms_at_start, seg.mpqn),
+                None => (0, 0.0, DEFAULT_MPQN),
+            };
+
+        let delta_ticks = (ms - ms_at_start) * 1000.0 * ticks_per_quarter / mpqn as f64;
+        start_tick + delta_ticks.round().max(0.0) as u64
+    }
+
+    /// The tick at which the given number of bars has gone by, following the time signatures
+    /// (4/4 until the first one). A bar cut short by a time signature change still counts as one.
+    fn ticks_for_bars(&self, bars: u32) -> u64 {
+        let bar_ticks = |numerator: u8, denominator_pow: u8| {
+            ((self.ticks_per_quarter * 4 * numerator as u64) >> denominator_pow.min(15)).max(1)
+        };
+
+        let mut tick = 0;
+        let mut bars_left = bars as u64;
+        let mut signature = (4, 2);
+
+        for &(change_tick, numerator, denominator_pow) in self.time_signatures.iter() {
+            if change_tick > tick {
+                let bar_len = bar_ticks(signature.0, signature.1);
+                let bars_until_change = (change_tick - tick).div_ceil(bar_len);
+
+                if bars_left <= bars_until_change {
+                    return (tick + bars_left * bar_len).min(change_tick);
+                }
+
+                bars_left -= bars_until_change;
+                tick = change_tick;
+            }
+
+            signature = (numerator, denominator_pow);
+        }
+
+        tick + bars_left * bar_ticks(signature.0, signature.1)
+    }
+
+    /// The tempo at tick 0, i.e. the file's own if it sets one there & the default otherwise.
+    fn initial_bpm(&self) -> f64 {
+        let mpqn = self
+            .segments
+            .first()
+            .map_or(DEFAULT_MPQN, |tempo| tempo.mpqn);
+        MICROSECONDS_PER_MINUTE / (mpqn as f64)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Point {
     time_ms: f64,
@@ -51,6 +378,21 @@ struct Point {
     duration_ms: f64,
 }
 
+/// Reads a MIDI file's bytes, or all of standard input when the path is `-`.
+pub fn read_midi_bytes<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+
+    if path == Path::new("-") {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| anyhow!("Failed to read MIDI from stdin: {}", e))?;
+        return Ok(bytes);
+    }
+
+    fs::read(path).map_err(|e| anyhow!("Failed to read MIDI file {}: {}", path.display(), e))
+}
+
 pub fn import_midi_file<P: AsRef<Path>>(
     path: P,
     transpose_semitones: i32,
@@ -58,622 +400,3220 @@ pub fn import_midi_file<P: AsRef<Path>>(
     merge: bool,
     clip_to_range: Option<(u8, u8)>,
 ) -> Result<Song> {
-    let bytes = fs::read(path.as_ref()).map_err(|e| {
-        anyhow!(
-            "Failed to read MIDI file {}: {}",
-            path.as_ref().display(),
-            e
-        )
-    })?;
-
-    midi_bytes_to_song(
-        &bytes,
-        path.as_ref(),
+    let options = ImportOptions {
         transpose_semitones,
         policy,
         merge,
         clip_to_range,
-    )
+        ..Default::default()
+    };
+
+    import_midi_file_with_options(path, &options)
 }
 
-fn midi_bytes_to_song(
-    bytes: &[u8],
-    source_path: &Path,
-    transpose_semitones: i32,
-    policy: PolyPolicy,
-    merge: bool,
-    clip_to_range: Option<(u8, u8)>,
+pub fn import_midi_file_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &ImportOptions,
 ) -> Result<Song> {
-    let smf = Smf::parse(bytes).map_err(|e| anyhow!("Failed to parse MIDI: {:?}", e))?;
-
-    let ticks_per_quarter = match smf.header.timing {
-        Timing::Metrical(t) => t.as_int() as u64,
-        Timing::Timecode(_fps, _subframe) => {
-            return Err(anyhow!(
-                "SMPTE timecode midi timing is not currently supported..!"
-            ));
-        }
-    };
-
-    let mut track_name = String::new();
+    let bytes = read_midi_bytes(path.as_ref())?;
 
-    debug!("Ticks per quarter note: {}", ticks_per_quarter);
-    debug!(
-        "MIDI format: {:?}, tracks: {}",
-        smf.header.format,
-        smf.tracks.len()
-    );
+    midi_bytes_to_song(&bytes, path.as_ref(), options)
+}
 
-    let mut tempo_changes: Vec<(u64, u32)> = Vec::new();
-    tempo_changes.push((0u64, DEFAULT_MPQN)); // default tempo to ~120bpm until a tempo meta appears
+/// Like [`import_midi_file_with_options`], also returning the [`ImportDiagnostics`].
+pub fn import_midi_file_with_diagnostics<P: AsRef<Path>>(
+    path: P,
+    options: &ImportOptions,
+) -> Result<(Song, ImportDiagnostics)> {
+    let bytes = read_midi_bytes(path.as_ref())?;
 
-    let mut intervals: Vec<NoteInterval> = Vec::new();
-    let mut open_notes: HashMap<(u8, u8), Vec<(u64, u8)>> = HashMap::new();
+    midi_bytes_to_song_with_diagnostics(&bytes, path.as_ref(), options)
+}
 
-    for (track_idx, track) in smf.tracks.iter().enumerate() {
-        let mut abs_tick: u64 = 0;
-        for event in track.iter() {
-            abs_tick = abs_tick.saturating_add(event.delta.as_int() as u64);
+/// The fraction of its value a note is held for given its NoteOff velocity. Releases at or below
+/// the default of 64 keep the whole note, while faster ones shorten it down to half at 127.
+pub fn release_articulation(release_velocity: u8) -> f64 {
+    let sharpness = release_velocity.saturating_sub(DEFAULT_RELEASE_VELOCITY) as f64
+        / (127 - DEFAULT_RELEASE_VELOCITY) as f64;
 
-            match &event.kind {
-                TrackEventKind::Meta(meta) => match meta {
-                    MetaMessage::Tempo(micro) => {
-                        let mpqn: u32 = micro.as_int();
-                        tempo_changes.push((abs_tick, mpqn));
-                        debug!(
-                            "Tempo change at tick {} -> {} us/qn (track {})",
-                            abs_tick, mpqn, track_idx
-                        );
-                    }
-                    MetaMessage::TrackName(bytes) => {
-                        if track_name.is_empty() {
-                            track_name = String::from_utf8(bytes.to_vec())?;
-                            debug!("Track name: {}", track_name);
-                        }
-                    }
-                    _ => {}
-                },
-                TrackEventKind::Midi { channel, message } => {
-                    let ch: u8 = channel.as_int();
+    1.0 - sharpness.min(1.0) * (1.0 - MIN_RELEASE_ARTICULATION)
+}
 
-                    match message {
-                        MidiMessage::NoteOn { key, vel } => {
-                            let velocity: u8 = vel.as_int();
+/// Picks the transposition of a time-ordered sequence of pitches that keeps the most notes mappable
+/// within `range`, breaking ties by how rarely the octave & semitone modifiers need to be toggled
+/// between consecutive notes, and then by the smallest shift.
+pub fn optimal_transpose_for_mappings(pitches: &[u8], range: (u8, u8)) -> i32 {
+    let (Some(&lowest), Some(&highest)) = (pitches.iter().min(), pitches.iter().max()) else {
+        return 0;
+    };
 
-                            if velocity == 0 {
-                                close_note(
-                                    &mut open_notes,
-                                    &mut intervals,
-                                    ch,
-                                    key.as_int(),
-                                    abs_tick,
-                                );
-                            } else {
-                                open_notes
-                                    .entry((ch, key.as_int()))
-                                    .or_default()
-                                    .push((abs_tick, velocity));
-                            }
-                        }
-                        MidiMessage::NoteOff { key, vel: _ } => {
-                            close_note(&mut open_notes, &mut intervals, ch, key.as_int(), abs_tick);
-                        }
-                        _ => {}
-                    }
-                }
-                _ => {}
+    let (min_id, max_id) = (range.0 as i32, range.1 as i32);
+    let modifiers = modifier_keys();
+    let mut best: Option<(usize, usize, i32)> = None;
+
+    for shift in (min_id - highest as i32)..=(max_id - lowest as i32) {
+        let inputs = pitches
+            .iter()
+            .map(|&midi| midi as i32 + shift)
+            .filter(|note_id| (min_id..=max_id).contains(note_id))
+            .filter_map(|note_id| input_for_midi(note_id as u8))
+            .collect::<Vec<_>>();
+
+        let churn = inputs
+            .windows(2)
+            .map(|pair| {
+                [modifiers.octave, modifiers.semitone]
+                    .iter()
+                    .filter(|modifier| {
+                        pair[0].keys.contains(modifier) != pair[1].keys.contains(modifier)
+                    })
+                    .count()
+            })
+            .sum::<usize>();
+
+        let is_better = match best {
+            None => true,
+            Some((coverage, best_churn, best_shift)) => {
+                inputs.len() > coverage
+                    || (inputs.len() == coverage
+                        && (churn < best_churn
+                            || (churn == best_churn && shift.abs() < best_shift.abs())))
             }
+        };
+
+        if is_better {
+            best = Some((inputs.len(), churn, shift));
         }
     }
 
-    let last_tick_estimate = intervals
-        .iter()
-        .map(|interval| interval.end_tick)
-        .max()
-        .unwrap_or(0)
-        .max(
-            tempo_changes
-                .iter()
-                .map(|(tempo, _)| *tempo)
-                .max()
-                .unwrap_or(0),
-        );
+    best.map(|(_, _, shift)| shift).unwrap_or(0)
+}
 
-    for ((ch, key), stack) in open_notes.into_iter() {
-        for (start_tick, start_vel) in stack {
-            let end_tick = if last_tick_estimate > start_tick {
-                last_tick_estimate
-            } else {
-                start_tick + ticks_per_quarter
-            };
+fn midi_bytes_to_song(bytes: &[u8], source_path: &Path, options: &ImportOptions) -> Result<Song> {
+    midi_bytes_to_song_with_diagnostics(bytes, source_path, options).map(|(song, _)| song)
+}
 
-            intervals.push(NoteInterval {
-                midi: key,
-                start_tick,
-                end_tick,
-                velocity: start_vel,
-                _channel: ch,
-            });
+fn midi_bytes_to_song_with_diagnostics(
+    bytes: &[u8],
+    source_path: &Path,
+    options: &ImportOptions,
+) -> Result<(Song, ImportDiagnostics)> {
+    let (intervals, tempo_map) = parse_note_intervals(bytes, options)?;
 
-            warn!(
-                "Unclosed NoteOn for {}, channel: {} at tick: {} auto-closing at: {}..!",
-                key, ch, start_tick, end_tick
-            );
-        }
-    }
+    reduce_intervals(intervals, &tempo_map, file_title(source_path), options)
+}
 
-    let mut last_tick: u64 = 0;
-    let mut ms_accum: f64 = 0.0;
-    let mut last_mpqn: u32 = DEFAULT_MPQN;
-    let mut tempo_segments: Vec<TempoSegment> = Vec::new();
+/// Imports several MIDI files as one song, e.g. a melody & its countermelody kept in separate
+/// files, reducing the notes of all of them together to a single line. See
+/// [`import_and_merge_with_offsets`] to start some files later than others.
+pub fn import_and_merge(paths: &[PathBuf], options: &ImportOptions) -> Result<Song> {
+    let files = paths
+        .iter()
+        .map(|path| (path.clone(), 0.0))
+        .collect::<Vec<_>>();
 
-    tempo_changes.sort_unstable_by_key(|(tick, _)| *tick);
+    import_and_merge_with_offsets(&files, options)
+}
 
-    for (tick, mpqn) in tempo_changes.into_iter() {
-        if tick < last_tick {
-            continue;
-        }
+/// Like [`import_and_merge`], with each file starting this many milliseconds into the song.
+pub fn import_and_merge_with_offsets(
+    files: &[(PathBuf, f64)],
+    options: &ImportOptions,
+) -> Result<Song> {
+    let mut sources = Vec::new();
+    for (path, offset_ms) in files.iter() {
+        let bytes = read_midi_bytes(path)?;
+        sources.push((bytes, *offset_ms));
+    }
 
-        if tick > last_tick {
-            let delta_ticks = (tick - last_tick) as f64;
-            ms_accum += delta_ticks * (last_mpqn as f64) / (ticks_per_quarter as f64) / 1000.0;
-        }
+    let title = files
+        .iter()
+        .filter_map(|(path, _)| file_title(path))
+        .collect::<Vec<_>>()
+        .join(" + ");
 
-        // ms_at_start reflects the ms accumulated up to this tick
-        tempo_segments.push(TempoSegment {
-            start_tick: tick,
-            mpqn,
-            ms_at_start: ms_accum,
-        });
+    let sources = sources
+        .iter()
+        .map(|(bytes, offset_ms)| (bytes.as_slice(), *offset_ms))
+        .collect::<Vec<_>>();
 
-        last_tick = tick;
-        last_mpqn = mpqn;
+    merge_midi_bytes(&sources, Some(title), options).map(|(song, _)| song)
+}
+
+/// Pools the notes of every file on the first file's time base, each shifted by its offset in
+/// milliseconds, and reduces them as one. Tracks stay apart, so `merge_tracks: false` still picks
+/// the densest track across all files.
+fn merge_midi_bytes(
+    sources: &[(&[u8], f64)],
+    title: Option<String>,
+    options: &ImportOptions,
+) -> Result<(Song, ImportDiagnostics)> {
+    let mut pooled: Vec<NoteInterval> = Vec::new();
+    let mut base_map: Option<TempoMap> = None;
+    let mut track_base = 0;
+
+    for &(bytes, offset_ms) in sources.iter() {
+        let (intervals, tempo_map) = parse_note_intervals(bytes, options)?;
+        let base = base_map.get_or_insert_with(|| TempoMap {
+            markers: Vec::new(),
+            ..tempo_map.clone()
+        });
+        let offset_ms = offset_ms.max(0.0);
+
+        let markers = tempo_map
+            .markers
+            .iter()
+            .map(|(tick, label)| {
+                let ms = tempo_map.ticks_to_ms(*tick) + offset_ms;
+                (base.ms_to_ticks(ms), label.clone())
+            })
+            .collect::<Vec<_>>();
+        base.markers.extend(markers);
+
+        let tracks = intervals
+            .iter()
+            .map(|interval| interval.track + 1)
+            .max()
+            .unwrap_or(0);
+
+        pooled.extend(intervals.into_iter().map(|interval| NoteInterval {
+            start_tick: base.ms_to_ticks(tempo_map.ticks_to_ms(interval.start_tick) + offset_ms),
+            end_tick: base.ms_to_ticks(tempo_map.ticks_to_ms(interval.end_tick) + offset_ms),
+            track: track_base + interval.track,
+            ..interval
+        }));
+        track_base += tracks;
     }
 
-    let ticks_to_ms = |tick: u64| -> f64 {
-        if tempo_segments.is_empty() {
-            // default 120bpm
-            return (tick as f64) * DEFAULT_MPQN as f64 / (ticks_per_quarter as f64) / 1000.0;
-        }
+    let Some(mut tempo_map) = base_map else {
+        bail!("No MIDI files to merge..!");
+    };
+    tempo_map.markers.sort_by_key(|(tick, _)| *tick);
 
-        let segment = match tempo_segments.iter().rfind(|seg| seg.start_tick <= tick) {
-            Some(s) => s,
-            None => &tempo_segments[0],
-        };
+    reduce_intervals(pooled, &tempo_map, title, options)
+}
 
-        let delta_ticks = (tick - segment.start_tick) as f64;
-        segment.ms_at_start
-            + delta_ticks * (segment.mpqn as f64) / (ticks_per_quarter as f64) / 1000.0
+fn file_title(path: &Path) -> Option<String> {
+    path.file_name()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_string())
+}
+
+/// Turns parsed note intervals into the final song, see [`intervals_to_song`].
+fn reduce_intervals(
+    intervals: Vec<NoteInterval>,
+    tempo_map: &TempoMap,
+    title: Option<String>,
+    options: &ImportOptions,
+) -> Result<(Song, ImportDiagnostics)> {
+    check_transpose(&intervals, options)?;
+
+    let mut diagnostics = ImportDiagnostics::default();
+    let song = if options.merge_tracks && !matches!(options.policy, PolyPolicy::Densest) {
+        intervals_to_song(intervals, tempo_map, title, options, &mut diagnostics)
+    } else {
+        densest_track_song(intervals, tempo_map, title, options, &mut diagnostics)
     };
 
-    let mut raw_events: Vec<Event> = Vec::new();
-    for interval in intervals.into_iter() {
-        let mut note_id = interval.midi as i32 + transpose_semitones;
+    let song = if options.drop_probability > 0.0 {
+        drop_notes(song, options.drop_probability, options.drop_seed)
+    } else {
+        song
+    };
 
-        if let Some((min_id, max_id)) = clip_to_range {
-            let min_id = min_id as i32;
-            let max_id = max_id as i32;
+    if options.insert_rests && options.reduce {
+        Ok((fill_rests(song), diagnostics))
+    } else {
+        Ok((song, diagnostics))
+    }
+}
 
-            let mut attempts = 0;
-            while (note_id < min_id || note_id > max_id) && attempts < 8 {
-                if note_id < min_id {
-                    note_id += 12;
-                } else if note_id > max_id {
-                    note_id -= 12;
-                }
-                attempts += 1;
-            }
+/// Reduces every track to monophony on its own, keeping only the track with the most notes per
+/// second (ties go to the earlier track).
+fn densest_track_song(
+    intervals: Vec<NoteInterval>,
+    tempo_map: &TempoMap,
+    title: Option<String>,
+    options: &ImportOptions,
+    diagnostics: &mut ImportDiagnostics,
+) -> Song {
+    let mut by_track: BTreeMap<usize, Vec<NoteInterval>> = BTreeMap::new();
+    for interval in intervals.into_iter() {
+        by_track.entry(interval.track).or_default().push(interval);
+    }
 
-            if note_id < min_id || note_id > max_id {
-                warn!(
-                    "Dropping note {} (during octave transpose) as it was not in range [{}..={}]..!",
-                    interval.midi, min_id, max_id
-                );
-                continue;
-            }
-        }
+    if by_track.len() < 2 {
+        debug!("Only one track holds notes, so there's nothing to choose between..!");
+    }
 
-        if !(0..=127).contains(&note_id) {
-            warn!("Dropping out-of-range MIDI {} after transpose..!", note_id);
-            continue;
-        }
+    let mut densest: Option<(usize, f64, Song, ImportDiagnostics)> = None;
+    for (track, intervals) in by_track.into_iter() {
+        let mut track_diagnostics = ImportDiagnostics::default();
+        let song = intervals_to_song(
+            intervals,
+            tempo_map,
+            title.clone(),
+            options,
+            &mut track_diagnostics,
+        );
+        let density = note_density(&song);
+        debug!("Track {} holds {:.2} notes/s..!", track, density);
 
-        let start_ms = ticks_to_ms(interval.start_tick);
-        let end_ms = ticks_to_ms(interval.end_tick);
+        if densest.as_ref().is_none_or(|(_, best, ..)| density > *best) {
+            densest = Some((track, density, song, track_diagnostics));
+        }
+    }
 
-        if end_ms <= start_ms {
-            debug!(
-                "Skipping zero/negative duration midi note {}, start: {} end: {}..!",
-                interval.midi, start_ms, end_ms
-            );
-            continue;
-        } else if end_ms - start_ms < EPSILON_MS {
-            warn!(
-                "Culling a tiny event to prevent audible artifacting..! Duration: {}ms",
-                end_ms - start_ms
+    match densest {
+        Some((track, density, song, track_diagnostics)) => {
+            info!(
+                "Playing track {} alone, the densest at {:.2} notes/s..!",
+                track, density
             );
-            continue;
+            *diagnostics = track_diagnostics;
+            song
         }
+        None => intervals_to_song(Vec::new(), tempo_map, title, options, diagnostics),
+    }
+}
 
-        let event = Event {
-            note: Note {
-                midi: note_id as u8,
-                velocity: interval.velocity,
-            },
-            time_ms: start_ms,
-            duration_ms: end_ms - start_ms,
-        };
+/// Notes per second across the span of the song, from the first onset to the last release.
+fn note_density(song: &Song) -> f64 {
+    let (Some(first), Some(last_end)) = (
+        song.events.first(),
+        song.events
+            .iter()
+            .map(|e| e.time_ms + e.duration_ms)
+            .max_by(f64::total_cmp),
+    ) else {
+        return 0.0;
+    };
 
-        raw_events.push(event);
+    let span_s = (last_end - first.time_ms) / 1000.0;
+    if span_s <= 0.0 {
+        return 0.0;
     }
 
-    raw_events.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms));
+    song.events.len() as f64 / span_s
+}
+
+/// Imports every MIDI channel as its own monophonic [`Song`], keyed by (0-based) channel number.
+pub fn import_per_channel(bytes: &[u8], options: &ImportOptions) -> Result<HashMap<u8, Song>> {
+    let (intervals, tempo_map) = parse_note_intervals(bytes, options)?;
+
+    let mut by_channel: HashMap<u8, Vec<NoteInterval>> = HashMap::new();
+    for interval in intervals.into_iter() {
+        by_channel
+            .entry(interval.channel)
+            .or_default()
+            .push(interval);
+    }
 
-    let final_events = reduce_to_monophonic(raw_events, policy, merge)
+    let songs = by_channel
         .into_iter()
-        .filter(|event| {
-            if event.duration_ms < EPSILON_MS {
-                warn!(
-                    "Culling final event with a duration below the allowed epsilon..! [{:.3}ms]",
-                    event.duration_ms
-                );
-                return false;
-            }
-            true
+        .map(|(ch, intervals)| {
+            let title = Some(format!("Channel {}", ch));
+            let mut diagnostics = ImportDiagnostics::default();
+            let song = if matches!(options.policy, PolyPolicy::Densest) {
+                densest_track_song(intervals, &tempo_map, title, options, &mut diagnostics)
+            } else {
+                intervals_to_song(intervals, &tempo_map, title, options, &mut diagnostics)
+            };
+
+            (ch, song)
         })
-        .collect::<Vec<_>>();
+        .collect();
 
-    // skipping first segment because it was built from our default mpqn
-    let tempo_bpm = if let Some(tempo) = tempo_segments.get(1) {
-        Some(MICROSECONDS_PER_MINUTE / (tempo.mpqn as f64))
-    } else {
-        Some(MICROSECONDS_PER_MINUTE / (DEFAULT_MPQN as f64))
+    Ok(songs)
+}
+
+/// A summary of a MIDI file's header & tracks, see [`midi_file_info`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiInfo {
+    /// The SMF format: 0 for a single track, 1 for parallel tracks & 2 for sequential ones.
+    pub format: u16,
+
+    /// Ticks per quarter note, or `None` for SMPTE timecode timing.
+    pub ticks_per_quarter: Option<u16>,
+
+    /// Every tempo change as `(tick, bpm)`, in file order.
+    pub tempo_changes: Vec<(u64, f64)>,
+
+    /// Every time signature as `(tick, numerator, denominator)`, in file order.
+    pub time_signatures: Vec<(u64, u8, u16)>,
+
+    pub tracks: Vec<TrackInfo>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackInfo {
+    pub name: Option<String>,
+    pub notes: usize,
+
+    /// The lowest & highest pitch played on the track, if it has any notes.
+    pub pitch_range: Option<(u8, u8)>,
+}
+
+/// Reads the header & a per-track summary of a MIDI file without importing its notes.
+pub fn midi_file_info<P: AsRef<Path>>(path: P) -> Result<MidiInfo> {
+    let bytes = read_midi_bytes(path.as_ref())?;
+
+    midi_bytes_info(&bytes)
+}
+
+fn midi_bytes_info(bytes: &[u8]) -> Result<MidiInfo> {
+    let smf = Smf::parse(bytes).map_err(|e| anyhow!("Failed to parse MIDI: {:?}", e))?;
+
+    let format = match smf.header.format {
+        Format::SingleTrack => 0,
+        Format::Parallel => 1,
+        Format::Sequential => 2,
     };
 
-    let song = Song {
-        metadata: Metadata {
-            title: source_path
-                .file_name()
-                .and_then(|s| s.to_str())
-                .map(|s| s.to_string()),
-            tempo_bpm,
-        },
-        events: final_events,
+    let ticks_per_quarter = match smf.header.timing {
+        Timing::Metrical(t) => Some(t.as_int()),
+        Timing::Timecode(_fps, _subframe) => None,
     };
 
-    Ok(song)
+    let mut tempo_changes: Vec<(u64, f64)> = Vec::new();
+    let mut time_signatures: Vec<(u64, u8, u16)> = Vec::new();
+    let mut tracks: Vec<TrackInfo> = Vec::new();
+
+    for track in smf.tracks.iter() {
+        let mut info = TrackInfo::default();
+        let mut abs_tick: u64 = 0;
+
+        for event in track.iter() {
+            abs_tick = abs_tick.saturating_add(event.delta.as_int() as u64);
+
+            match &event.kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(micro)) => {
+                    tempo_changes.push((abs_tick, MICROSECONDS_PER_MINUTE / micro.as_int() as f64));
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(
+                    numerator,
+                    denominator_pow,
+                    ..,
+                )) => {
+                    time_signatures.push((
+                        abs_tick,
+                        *numerator,
+                        1u16 << (*denominator_pow).min(15),
+                    ));
+                }
+                TrackEventKind::Meta(MetaMessage::TrackName(bytes)) if info.name.is_none() => {
+                    info.name = Some(String::from_utf8_lossy(bytes).into_owned());
+                }
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } if vel.as_int() > 0 => {
+                    let key = key.as_int();
+                    info.notes += 1;
+                    info.pitch_range = Some(match info.pitch_range {
+                        Some((low, high)) => (low.min(key), high.max(key)),
+                        None => (key, key),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        tracks.push(info);
+    }
+
+    Ok(MidiInfo {
+        format,
+        ticks_per_quarter,
+        tempo_changes,
+        time_signatures,
+        tracks,
+    })
 }
 
-fn close_note(
-    open_notes: &mut HashMap<(u8, u8), Vec<(u64, u8)>>,
-    intervals: &mut Vec<NoteInterval>,
-    ch: u8,
-    midi_num: u8,
-    abs_tick: u64,
-) {
-    if let Some(stack) = open_notes.get_mut(&(ch, midi_num)) {
-        if let Some((start_tick, start_vel)) = stack.pop() {
-            intervals.push(NoteInterval {
-                midi: midi_num,
-                start_tick,
-                end_tick: abs_tick,
-                velocity: start_vel,
-                _channel: ch,
-            });
-        } else {
+/// Collects every note in the file as a tick interval, along with the tempo map needed to place
+/// those ticks in time.
+fn parse_note_intervals(
+    bytes: &[u8],
+    options: &ImportOptions,
+) -> Result<(Vec<NoteInterval>, TempoMap)> {
+    let smf = Smf::parse(bytes).map_err(|e| anyhow!("Failed to parse MIDI: {:?}", e))?;
+
+    let (ticks_per_quarter, ticks_per_second) = match smf.header.timing {
+        Timing::Metrical(t) => {
+            debug!("Ticks per quarter note: {}", t);
+            (t.as_int() as u64, None)
+        }
+        Timing::Timecode(fps, subframe) => {
+            let ticks_per_second = fps.as_f32() as f64 * subframe as f64;
+            if ticks_per_second <= 0.0 {
+                bail!("SMPTE timecode midi timing has no subframes per frame..!");
+            }
+
             debug!(
-                "Orphaned NoteOff for {} ch{} at tick {}..!",
-                midi_num, ch, abs_tick
+                "SMPTE timecode at {} fps & {} subframes: {} ticks per second",
+                fps.as_f32(),
+                subframe,
+                ticks_per_second
             );
+            let ticks_per_quarter = ticks_per_second * DEFAULT_MPQN as f64 / 1_000_000.0;
+            (
+                ticks_per_quarter.round().max(1.0) as u64,
+                Some(ticks_per_second),
+            )
         }
-    } else {
-        debug!(
-            "Orphaned NoteOff for {} ch{} at tick {}..!",
-            midi_num, ch, abs_tick
-        );
+    };
+
+    let mut track_name = String::new();
+
+    debug!(
+        "MIDI format: {:?}, tracks: {}",
+        smf.header.format,
+        smf.tracks.len()
+    );
+
+    let mut time_signatures: Vec<(u64, u8, u8)> = Vec::new();
+    let mut markers: Vec<(u64, String)> = Vec::new();
+    let default_mpqn = match options.default_tempo_bpm {
+        Some(bpm) if bpm.is_finite() && bpm > 0.0 => (MICROSECONDS_PER_MINUTE / bpm).round() as u32,
+        Some(bpm) => {
+            return Err(anyhow!(
+                "Default tempo must be a positive BPM, got {}..!",
+                bpm
+            ));
+        }
+        None => DEFAULT_MPQN,
+    };
+
+    // Assume the default tempo until a tempo meta appears, which replaces it if at the same tick.
+    let mut tempo_changes: Vec<(u64, u32)> = Vec::new();
+    tempo_changes.push((0u64, default_mpqn));
+
+    if let Some(bpm) = options.tempo_override_bpm {
+        if !(bpm.is_finite() && bpm > 0.0) {
+            return Err(anyhow!(
+                "Tempo override must be a positive BPM, got {}..!",
+                bpm
+            ));
+        }
+
+        let mpqn = (MICROSECONDS_PER_MINUTE / bpm).round() as u32;
+        tempo_changes.push((0u64, mpqn));
+        info!("Ignoring the file's tempo in favour of {} BPM..!", bpm);
     }
-}
 
-/// Given a possibly-overlapping set of events, reduce to a single monophonic sequence according
-/// to the specified policy. The events emitted by this function should not overlap.
-///
-/// Basic approach: create a sorted set of time points where something changes (start or end), and
-/// at each point decide which note should be active using the policy.
-fn reduce_to_monophonic(events: Vec<Event>, policy: PolyPolicy, merge: bool) -> Vec<Event> {
-    if events.is_empty() {
-        return events;
+    let mut intervals: Vec<NoteInterval> = Vec::new();
+    let mut open_notes: OpenNotes = HashMap::new();
+    let mut program_changes: Vec<(u8, u64, u8)> = Vec::new();
+    let mut stacked_note_ons: HashMap<(u8, u8), usize> = HashMap::new();
+
+    for (track_idx, track) in smf.tracks.iter().enumerate() {
+        let mut abs_tick: u64 = 0;
+        for event in track.iter() {
+            abs_tick = abs_tick.saturating_add(event.delta.as_int() as u64);
+
+            match &event.kind {
+                TrackEventKind::Meta(meta) => match meta {
+                    MetaMessage::Tempo(micro) if options.tempo_override_bpm.is_none() => {
+                        let mpqn: u32 = micro.as_int();
+                        tempo_changes.push((abs_tick, mpqn));
+                        debug!(
+                            "Tempo change at tick {} -> {} us/qn (track {})",
+                            abs_tick, mpqn, track_idx
+                        );
+                    }
+                    MetaMessage::TimeSignature(numerator, denominator_pow, ..) => {
+                        time_signatures.push((abs_tick, *numerator, *denominator_pow));
+                    }
+                    MetaMessage::Marker(label) | MetaMessage::CuePoint(label) => {
+                        let label = String::from_utf8_lossy(label).into_owned();
+                        debug!("Marker \"{}\" at tick {}", label, abs_tick);
+                        markers.push((abs_tick, label));
+                    }
+                    MetaMessage::TrackName(bytes) if track_name.is_empty() => {
+                        track_name = String::from_utf8(bytes.to_vec())?;
+                        debug!("Track name: {}", track_name);
+                    }
+                    _ => {}
+                },
+                TrackEventKind::Midi { channel, message } => {
+                    let ch: u8 = channel.as_int();
+
+                    match message {
+                        MidiMessage::NoteOn { key, vel } => {
+                            let velocity: u8 = vel.as_int();
+
+                            if velocity == 0 {
+                                release_note(
+                                    &mut open_notes,
+                                    &mut stacked_note_ons,
+                                    &mut intervals,
+                                    ch,
+                                    key.as_int(),
+                                    abs_tick,
+                                    DEFAULT_RELEASE_VELOCITY,
+                                    options.lenient_channel_matching,
+                                );
+                            } else {
+                                let stack = open_notes.entry((ch, key.as_int())).or_default();
+
+                                if options.dedupe_note_ons && !stack.is_empty() {
+                                    debug!(
+                                        "Coalescing stacked NoteOn for {} ch{} at tick {}..!",
+                                        key, ch, abs_tick
+                                    );
+                                    *stacked_note_ons.entry((ch, key.as_int())).or_default() += 1;
+                                } else {
+                                    stack.push((abs_tick, velocity, track_idx));
+                                }
+                            }
+                        }
+                        MidiMessage::NoteOff { key, vel } => {
+                            release_note(
+                                &mut open_notes,
+                                &mut stacked_note_ons,
+                                &mut intervals,
+                                ch,
+                                key.as_int(),
+                                abs_tick,
+                                vel.as_int(),
+                                options.lenient_channel_matching,
+                            );
+                        }
+                        MidiMessage::ChannelAftertouch { vel } if options.use_aftertouch => {
+                            let pressure = vel.as_int();
+                            for ((_, key), stack) in
+                                open_notes.iter_mut().filter(|((c, _), _)| *c == ch)
+                            {
+                                for (_, velocity, _) in stack.iter_mut() {
+                                    *velocity = pressure;
+                                }
+                                debug!(
+                                    "Channel pressure {} applied to {} ch{} at tick {}..!",
+                                    pressure, key, ch, abs_tick
+                                );
+                            }
+                        }
+                        MidiMessage::ProgramChange { program } => {
+                            program_changes.push((ch, abs_tick, program.as_int()));
+                            debug!(
+                                "Program change at tick {} -> {} on channel {} (track {})",
+                                abs_tick, program, ch, track_idx
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                // Nothing to play, but their deltas still count towards `abs_tick` above.
+                TrackEventKind::SysEx(data) | TrackEventKind::Escape(data) => {
+                    debug!(
+                        "Skipping a {} byte SysEx/escape at tick {} (track {})..!",
+                        data.len(),
+                        abs_tick,
+                        track_idx
+                    );
+                }
+            }
+        }
     }
 
-    let mut points: Vec<Point> = Vec::new();
-    for ev in events.into_iter() {
-        points.push(Point {
-            time_ms: ev.time_ms,
-            is_start: true,
-            midi: ev.note.midi,
-            velocity: ev.note.velocity,
-            duration_ms: ev.duration_ms,
-        });
-        points.push(Point {
-            time_ms: ev.time_ms + ev.duration_ms,
-            is_start: false,
-            midi: ev.note.midi,
-            velocity: ev.note.velocity,
-            duration_ms: ev.duration_ms,
+    let last_tick_estimate = intervals
+        .iter()
+        .map(|interval| interval.end_tick)
+        .max()
+        .unwrap_or(0)
+        .max(
+            tempo_changes
+                .iter()
+                .map(|(tempo, _)| *tempo)
+                .max()
+                .unwrap_or(0),
+        );
+
+    let mut last_tick: u64 = 0;
+    let mut ms_accum: f64 = 0.0;
+    let mut last_mpqn: u32 = default_mpqn;
+    let mut tempo_segments: Vec<TempoSegment> = Vec::new();
+    let mut ignored_tempo_changes = 0;
+
+    // Stable, so a tempo set at tick 0 still lands after (and overrides) the default one.
+    tempo_changes.sort_by_key(|(tick, _)| *tick);
+
+    for (tick, mpqn) in tempo_changes.into_iter() {
+        if tick < last_tick {
+            continue;
+        }
+
+        // The file's own starting tempo always replaces the default.
+        let bpm_delta =
+            MICROSECONDS_PER_MINUTE / mpqn as f64 - MICROSECONDS_PER_MINUTE / last_mpqn as f64;
+        if tick > 0
+            && options
+                .tempo_change_threshold_bpm
+                .is_some_and(|threshold| bpm_delta.abs() < threshold)
+        {
+            ignored_tempo_changes += 1;
+            continue;
+        }
+
+        if tick > last_tick {
+            let delta_ticks = (tick - last_tick) as f64;
+            ms_accum += delta_ticks * (last_mpqn as f64) / (ticks_per_quarter as f64) / 1000.0;
+        }
+
+        // A later change at the same tick, e.g. the file's starting tempo, replaces the earlier one.
+        if tempo_segments
+            .last()
+            .is_some_and(|segment| segment.start_tick == tick)
+        {
+            tempo_segments.pop();
+        }
+
+        // ms_at_start reflects the ms accumulated up to this tick
+        tempo_segments.push(TempoSegment {
+            start_tick: tick,
+            mpqn,
+            ms_at_start: ms_accum,
         });
-    }
 
-    // Events with `is_start == false` come before `is_start == true` for the same time_ms,
-    // so that notes ending at some time_ms `t` will not be counted active for another event starting at the same `t`.
-    points.sort_by(|a, b| {
-        a.time_ms
-            .partial_cmp(&b.time_ms)
-            .unwrap_or(std::cmp::Ordering::Equal)
-            .then_with(|| (a.is_start as u8).cmp(&(b.is_start as u8)))
+        last_tick = tick;
+        last_mpqn = mpqn;
+    }
+
+    if ignored_tempo_changes > 0 {
+        debug!(
+            "Ignored {} tempo change(s) below the threshold..!",
+            ignored_tempo_changes
+        );
+    }
+
+    time_signatures.sort_by_key(|(tick, ..)| *tick);
+    markers.sort_by_key(|(tick, _)| *tick);
+
+    let mut tempo_map = TempoMap {
+        segments: tempo_segments,
+        ticks_per_quarter,
+        ticks_per_second,
+        time_signatures,
+        markers,
+    };
+
+    for ((ch, key), stack) in open_notes.into_iter() {
+        for (start_tick, start_vel, track) in stack {
+            let mut end_tick = if last_tick_estimate > start_tick {
+                last_tick_estimate
+            } else {
+                start_tick + ticks_per_quarter
+            };
+
+            if let Some(max_ms) = options.max_autoclose_ms {
+                let cap = tempo_map.ms_to_ticks(tempo_map.ticks_to_ms(start_tick) + max_ms);
+                end_tick = end_tick.min(cap.max(start_tick + 1));
+            }
+
+            intervals.push(NoteInterval {
+                midi: key,
+                start_tick,
+                end_tick,
+                velocity: start_vel,
+                release_velocity: DEFAULT_RELEASE_VELOCITY,
+                channel: ch,
+                track,
+            });
+
+            warn!(
+                "Unclosed NoteOn for {}, channel: {} at tick: {} auto-closing at: {}..!",
+                key, ch, start_tick, end_tick
+            );
+        }
+    }
+
+    if let Some(programs) = &options.program_filter {
+        // Tracks are parsed one after another, so a channel's program can only be resolved once
+        // every track's program changes are known.
+        program_changes.sort_by_key(|(_, tick, _)| *tick);
+
+        let program_at = |channel: u8, tick: u64| -> u8 {
+            program_changes
+                .iter()
+                .rfind(|(ch, change_tick, _)| *ch == channel && *change_tick <= tick)
+                .map(|(_, _, program)| *program)
+                .unwrap_or(0)
+        };
+
+        let before = intervals.len();
+        intervals.retain(|interval| {
+            programs.contains(&program_at(interval.channel, interval.start_tick))
+        });
+
+        if intervals.len() < before {
+            info!(
+                "Dropped {} note(s) played outside of the selected programs {:?}..!",
+                before - intervals.len(),
+                programs
+            );
+        }
+    }
+
+    if let Some(bars) = options.max_bars {
+        let cutoff = tempo_map.ticks_for_bars(bars);
+        let before = intervals.len();
+
+        intervals.retain(|interval| interval.start_tick < cutoff);
+        for interval in intervals.iter_mut() {
+            interval.end_tick = interval.end_tick.min(cutoff);
+        }
+        tempo_map.markers.retain(|(tick, _)| *tick < cutoff);
+
+        info!(
+            "Keeping the first {} bar(s), up to {:.3}ms, which drops {} note(s)..!",
+            bars,
+            tempo_map.ticks_to_ms(cutoff),
+            before - intervals.len()
+        );
+    }
+
+    Ok((intervals, tempo_map))
+}
+
+/// Places note intervals in time, transposes & folds them into range, and reduces them to a
+/// single monophonic line unless `options.reduce` is false.
+fn intervals_to_song(
+    intervals: Vec<NoteInterval>,
+    tempo_map: &TempoMap,
+    title: Option<String>,
+    options: &ImportOptions,
+    diagnostics: &mut ImportDiagnostics,
+) -> Song {
+    let ImportOptions {
+        policy,
+        merge,
+        clip_to_range,
+        emit_velocity,
+        ..
+    } = *options;
+
+    let transpose_semitones = if options.optimize_transpose {
+        let mut ordered = intervals.iter().collect::<Vec<_>>();
+        ordered.sort_by_key(|interval| interval.start_tick);
+
+        let pitches = ordered
+            .iter()
+            .map(|interval| interval.midi)
+            .collect::<Vec<_>>();
+        let shift = optimal_transpose_for_mappings(&pitches, clip_to_range.unwrap_or((0, 127)));
+
+        info!("Optimized transpose: {:+} semitones..!", shift);
+        shift
+    } else {
+        options.transpose_semitones
+    };
+
+    let mut raw_events: Vec<Event> = Vec::new();
+    // (original pitch, pitch after folding, start, end) of every kept note while clipping to a
+    // range, moved or not, since a folded note can just as well collide with one left in place.
+    let mut folded_notes: Vec<(u8, u8, f64, f64)> = Vec::new();
+
+    for interval in intervals.into_iter() {
+        let start_ms = tempo_map.ticks_to_ms(interval.start_tick);
+        let mut end_ms = tempo_map.ticks_to_ms(interval.end_tick);
+        let mut change = |action, reason| {
+            diagnostics.changes.push(EventChange {
+                midi: interval.midi,
+                time_ms: start_ms,
+                action,
+                reason,
+            })
+        };
+
+        let transposed = interval.midi as i32 + transpose_semitones;
+        let folded = fold_into_range(transposed, clip_to_range);
+        let note_id = match &options.scale_constraint {
+            Some(scale) => snap_to_scale(folded, scale, clip_to_range),
+            None => folded,
+        };
+
+        if let Some((min_id, max_id)) = clip_to_range {
+            let min_id = min_id as i32;
+            let max_id = max_id as i32;
+
+            if note_id < min_id || note_id > max_id {
+                warn!(
+                    "Dropping note {} (during octave transpose) as it was not in range [{}..={}]..!",
+                    interval.midi, min_id, max_id
+                );
+                change(ChangeAction::Dropped, ChangeReason::OutOfRange);
+                continue;
+            }
+        }
+
+        if !(0..=127).contains(&note_id) {
+            warn!("Dropping out-of-range MIDI {} after transpose..!", note_id);
+            change(ChangeAction::Dropped, ChangeReason::OutOfRange);
+            continue;
+        }
+
+        if options.use_release_velocity {
+            end_ms =
+                start_ms + (end_ms - start_ms) * release_articulation(interval.release_velocity);
+        }
+
+        if end_ms <= start_ms {
+            debug!(
+                "Skipping zero/negative duration midi note {}, start: {} end: {}..!",
+                interval.midi, start_ms, end_ms
+            );
+            change(ChangeAction::Dropped, ChangeReason::NoDuration);
+            continue;
+        } else if end_ms - start_ms < EPSILON_MS {
+            warn!(
+                "Culling a tiny event to prevent audible artifacting..! Duration: {}ms",
+                end_ms - start_ms
+            );
+            change(ChangeAction::Dropped, ChangeReason::TooShort);
+            continue;
+        }
+
+        if folded != transposed {
+            change(
+                ChangeAction::Moved(folded as u8),
+                ChangeReason::OctaveFolded,
+            );
+        }
+        if note_id != folded {
+            change(
+                ChangeAction::Moved(note_id as u8),
+                ChangeReason::SnappedToScale,
+            );
+        }
+
+        let velocity = match options.channel_gain.get(&interval.channel) {
+            Some(gain) => (interval.velocity as f64 * gain).round().clamp(0.0, 127.0) as u8,
+            None => interval.velocity,
+        };
+
+        let event = Event {
+            note: Note {
+                midi: note_id as u8,
+                velocity,
+            },
+            time_ms: start_ms,
+            duration_ms: end_ms - start_ms,
+            override_keys: None,
+        };
+
+        if clip_to_range.is_some() {
+            folded_notes.push((interval.midi, note_id as u8, start_ms, end_ms));
+        }
+
+        raw_events.push(event);
+    }
+
+    // Intervals close as their notes are released, so list the changes by when the notes start.
+    diagnostics
+        .changes
+        .sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms));
+
+    let collisions = count_fold_collisions(folded_notes);
+    if collisions > 0 {
+        warn!(
+            "Octave folding turned {} pair(s) of distinct overlapping notes into unisons..!",
+            collisions
+        );
+    }
+    diagnostics.fold_collisions += collisions;
+
+    sort_events(&mut raw_events);
+
+    if options.arpeggiate_min_notes > 0 {
+        raw_events = arpeggiate_chords(raw_events, options.arpeggiate_min_notes);
+    }
+
+    let (max_concurrent, polyphonic_fraction) = measure_polyphony(&raw_events);
+    if max_concurrent > 1 {
+        info!(
+            "Up to {} notes sound at once, with {:.1}% of the song polyphonic..!",
+            max_concurrent,
+            polyphonic_fraction * 100.0
+        );
+    }
+    diagnostics.max_concurrent_notes = max_concurrent;
+    diagnostics.polyphonic_fraction = polyphonic_fraction;
+
+    if !options.reduce {
+        debug!(
+            "Keeping all {} overlapping event(s) unreduced..!",
+            raw_events.len()
+        );
+        return Song {
+            metadata: song_metadata(title, tempo_map),
+            events: raw_events,
+        };
+    }
+
+    let mut final_events = reduce_to_monophonic_with(raw_events, policy, merge, emit_velocity);
+
+    if let Some(threshold) = options.gliss_threshold_semitones {
+        final_events = fill_glissandos(
+            final_events,
+            threshold,
+            clip_to_range.unwrap_or(FLUTE_RANGE),
+        );
+    }
+
+    Song {
+        metadata: song_metadata(title, tempo_map),
+        events: final_events,
+    }
+}
+
+fn song_metadata(title: Option<String>, tempo_map: &TempoMap) -> Metadata {
+    Metadata {
+        title,
+        tempo_bpm: Some(tempo_map.initial_bpm()),
+        markers: tempo_map
+            .markers
+            .iter()
+            .map(|(tick, label)| (tempo_map.ticks_to_ms(*tick), label.clone()))
+            .collect(),
+    }
+}
+
+/// Shifts a transposed note by as few octaves as it takes to reach `range`, however far away it
+/// starts. The result is only still outside the range when no octave of the note fits in it.
+/// Without a range the note is left as is.
+fn fold_into_range(note_id: i32, range: Option<(u8, u8)>) -> i32 {
+    let Some((min_id, max_id)) = range else {
+        return note_id;
+    };
+    let min_id = min_id as i32;
+    let max_id = max_id as i32;
+
+    if note_id < min_id {
+        note_id + (min_id - note_id + 11) / 12 * 12
+    } else if note_id > max_id {
+        note_id - (note_id - max_id + 11) / 12 * 12
+    } else {
+        note_id
+    }
+}
+
+/// Moves a note to the nearest pitch whose pitch class is in `scale`, picking the lower one when
+/// two are equally near. Pitches outside `range` (or MIDI's 0..=127 without one) aren't picked,
+/// so a note at the edge of the range snaps inwards. Notes with no allowed pitch in range, e.g.
+/// for an empty scale, are left as is.
+fn snap_to_scale(note_id: i32, scale: &[u8], range: Option<(u8, u8)>) -> i32 {
+    let (min_id, max_id) = range.unwrap_or((0, 127));
+    let allowed = |candidate: i32| {
+        (min_id as i32..=max_id as i32).contains(&candidate)
+            && scale.contains(&(candidate.rem_euclid(12) as u8))
+    };
+
+    (0..128)
+        .flat_map(|distance| [note_id - distance, note_id + distance])
+        .find(|&candidate| allowed(candidate))
+        .unwrap_or(note_id)
+}
+
+/// Counts pairs of notes that overlap in time & were folded onto the same pitch from different
+/// original pitches. Takes `(original pitch, folded pitch, start, end)` for each note.
+fn count_fold_collisions(mut notes: Vec<(u8, u8, f64, f64)>) -> usize {
+    notes.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+    let mut collisions = 0;
+    let mut sounding: Vec<(u8, u8, f64, f64)> = Vec::new();
+
+    for note in notes.into_iter() {
+        let (original, folded, start_ms, _end_ms) = note;
+        sounding.retain(|(.., end_ms)| *end_ms > start_ms + EPSILON_MS);
+
+        for (other_original, other_folded, other_start_ms, _) in sounding.iter() {
+            if *other_folded == folded && *other_original != original {
+                debug!(
+                    "Notes {} & {} both fold to {} at {:.3}ms..!",
+                    other_original,
+                    original,
+                    folded,
+                    other_start_ms.max(start_ms)
+                );
+                collisions += 1;
+            }
+        }
+
+        sounding.push(note);
+    }
+
+    collisions
+}
+
+/// Returns the most events sounding at once & the fraction of their span, from the first onset to
+/// the last release, during which more than one does. Notes ending at `t` don't overlap notes
+/// starting at `t`.
+fn measure_polyphony(events: &[Event]) -> (usize, f64) {
+    let mut points = events
+        .iter()
+        .flat_map(|e| [(e.time_ms, 1), (e.time_ms + e.duration_ms, -1)])
+        .collect::<Vec<(f64, i32)>>();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return (0, 0.0);
+    };
+    let span_ms = last.0 - first.0;
+
+    let mut sounding = 0i32;
+    let mut max_sounding = 0i32;
+    let mut polyphonic_ms = 0.0;
+    let mut previous_ms = first.0;
+    for (time_ms, delta) in points.into_iter() {
+        if sounding > 1 {
+            polyphonic_ms += time_ms - previous_ms;
+        }
+
+        sounding += delta;
+        max_sounding = max_sounding.max(sounding);
+        previous_ms = time_ms;
+    }
+
+    let fraction = if span_ms > 0.0 {
+        polyphonic_ms / span_ms
+    } else {
+        0.0
+    };
+    (max_sounding as usize, fraction)
+}
+
+/// Fails up front when the requested transpose would leave most notes unplayable, i.e. outside
+/// MIDI's 0..=127 or the clip range even after octave folding, rather than quietly dropping them.
+fn check_transpose(intervals: &[NoteInterval], options: &ImportOptions) -> Result<()> {
+    let transpose = options.transpose_semitones;
+    if options.optimize_transpose || transpose == 0 || intervals.is_empty() {
+        return Ok(());
+    }
+
+    let (min_id, max_id) = options.clip_to_range.unwrap_or((0, 127));
+    let dropped = intervals
+        .iter()
+        .filter(|interval| {
+            let note_id = fold_into_range(interval.midi as i32 + transpose, options.clip_to_range);
+            note_id < (min_id as i32).max(0) || note_id > (max_id as i32).min(127)
+        })
+        .count();
+
+    if dropped * 2 > intervals.len() {
+        let lowest = intervals.iter().map(|i| i.midi).min().unwrap_or(0);
+        let highest = intervals.iter().map(|i| i.midi).max().unwrap_or(0);
+
+        bail!(
+            "Transposing by {:+} semitones would drop {} of {} notes: the song spans MIDI {}..={}, \
+             which lands at {}..={} & can't be folded into {}..={}. Try a smaller --transpose..!",
+            transpose,
+            dropped,
+            intervals.len(),
+            lowest,
+            highest,
+            lowest as i32 + transpose,
+            highest as i32 + transpose,
+            min_id,
+            max_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Closes the newest open note of `midi_num` on `ch`. When `lenient`, a NoteOff with no such note
+/// closes the oldest one open on any other channel instead.
+fn close_note(
+    open_notes: &mut OpenNotes,
+    intervals: &mut Vec<NoteInterval>,
+    ch: u8,
+    midi_num: u8,
+    abs_tick: u64,
+    release_velocity: u8,
+    lenient: bool,
+) {
+    let mut open = open_notes
+        .get_mut(&(ch, midi_num))
+        .and_then(|stack| stack.pop())
+        .map(|note| (ch, note));
+
+    if open.is_none() && lenient {
+        open = open_notes
+            .iter()
+            .filter(|((other_ch, key), _)| *key == midi_num && *other_ch != ch)
+            .flat_map(|((other_ch, _), stack)| {
+                stack
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, (start_tick, ..))| (*start_tick, *other_ch, i))
+            })
+            .min()
+            .and_then(|(_, other_ch, i)| {
+                let stack = open_notes.get_mut(&(other_ch, midi_num))?;
+                Some((other_ch, stack.remove(i)))
+            });
+
+        if let Some((other_ch, _)) = open {
+            debug!(
+                "Closing {} ch{} with a NoteOff on ch{} at tick {}..!",
+                midi_num, other_ch, ch, abs_tick
+            );
+        }
+    }
+
+    let Some((open_ch, (start_tick, start_vel, track))) = open else {
+        debug!(
+            "Orphaned NoteOff for {} ch{} at tick {}..!",
+            midi_num, ch, abs_tick
+        );
+        return;
+    };
+
+    intervals.push(NoteInterval {
+        midi: midi_num,
+        start_tick,
+        end_tick: abs_tick,
+        velocity: start_vel,
+        release_velocity,
+        channel: open_ch,
+        track,
     });
+}
+
+/// Closes the open note for a NoteOff, unless the NoteOff belongs to a coalesced NoteOn.
+#[allow(clippy::too_many_arguments)]
+fn release_note(
+    open_notes: &mut OpenNotes,
+    stacked_note_ons: &mut HashMap<(u8, u8), usize>,
+    intervals: &mut Vec<NoteInterval>,
+    ch: u8,
+    midi_num: u8,
+    abs_tick: u64,
+    release_velocity: u8,
+    lenient: bool,
+) {
+    if let Some(count) = stacked_note_ons.get_mut(&(ch, midi_num))
+        && *count > 0
+    {
+        *count -= 1;
+        return;
+    }
+
+    close_note(
+        open_notes,
+        intervals,
+        ch,
+        midi_num,
+        abs_tick,
+        release_velocity,
+        lenient,
+    );
+}
+
+/// Inserts a quick chromatic run between consecutive notes that leap by more than `threshold`
+/// semitones, taking up to [`GLISS_BUDGET_MS`] (& at most half) of the first note's tail. Notes
+/// separated by a longer rest than that are left alone, as are runs that don't fit a single
+/// [`MIN_GLISS_STEP_MS`] step. Connecting notes are kept within `range` & spread evenly across the
+/// leap when there isn't time for every semitone. Expects `events` monophonic & in time order.
+fn fill_glissandos(events: Vec<Event>, threshold: u8, range: (u8, u8)) -> Vec<Event> {
+    let mut filled: Vec<Event> = Vec::with_capacity(events.len());
+    let mut glissandos = 0;
+
+    for event in events.into_iter() {
+        let Some(previous) = filled.last_mut() else {
+            filled.push(event);
+            continue;
+        };
+
+        let (from, to) = (previous.note.midi as i32, event.note.midi as i32);
+        let leap = to - from;
+        let previous_end = previous.time_ms + previous.duration_ms;
+
+        if leap.unsigned_abs() <= threshold as u32 || event.time_ms - previous_end > GLISS_BUDGET_MS
+        {
+            filled.push(event);
+            continue;
+        }
+
+        let budget_ms = GLISS_BUDGET_MS.min(previous.duration_ms / 2.0);
+        let steps =
+            ((leap.unsigned_abs() - 1) as usize).min((budget_ms / MIN_GLISS_STEP_MS) as usize);
+        if steps == 0 {
+            filled.push(event);
+            continue;
+        }
+
+        let (min_id, max_id) = (range.0 as i32, range.1 as i32);
+        let mut pitches = (1..=steps as i32)
+            .map(|k| from + (leap as f64 * k as f64 / (steps + 1) as f64).round() as i32)
+            .map(|midi| midi.clamp(min_id, max_id) as u8)
+            .collect::<Vec<_>>();
+        pitches.dedup();
+        pitches.retain(|&midi| midi as i32 != from && midi as i32 != to);
+
+        if pitches.is_empty() {
+            filled.push(event);
+            continue;
+        }
+
+        let step_ms = budget_ms / pitches.len() as f64;
+        let start_ms = previous_end - budget_ms;
+        let velocity = previous.note.velocity;
+        previous.duration_ms -= budget_ms;
+
+        filled.extend(pitches.into_iter().enumerate().map(|(k, midi)| Event {
+            note: Note { midi, velocity },
+            time_ms: start_ms + k as f64 * step_ms,
+            duration_ms: step_ms,
+            override_keys: None,
+        }));
+        filled.push(event);
+        glissandos += 1;
+    }
+
+    if glissandos > 0 {
+        info!("Filled {} leap(s) with a glissando..!", glissandos);
+    }
+
+    filled
+}
+
+/// Rolls every chord that leaves at least `min_notes` pitches sounding at its onset into a quick
+/// upward arpeggio, so reduction keeps each of its notes instead of picking one. Expects `events`
+/// in time order & returns them in time order.
+fn arpeggiate_chords(mut events: Vec<Event>, min_notes: usize) -> Vec<Event> {
+    let mut rolled = 0;
+    let mut i = 0;
+
+    while i < events.len() {
+        let onset = events[i].time_ms;
+        let mut j = i + 1;
+        while j < events.len() && events[j].time_ms - onset <= BOUNDARY_EPSILON_MS {
+            j += 1;
+        }
+
+        let held = events[..i]
+            .iter()
+            .filter(|e| e.time_ms + e.duration_ms > onset + BOUNDARY_EPSILON_MS)
+            .count();
+
+        if j - i >= 2 && held + (j - i) >= min_notes {
+            let chord = &mut events[i..j];
+            chord.sort_by_key(|e| e.note.midi);
+
+            let end = chord
+                .iter()
+                .map(|e| e.time_ms + e.duration_ms)
+                .fold(onset, f64::max);
+            let step = ARPEGGIO_STEP_MS.min((end - onset) / chord.len() as f64);
+
+            let last = chord.len() - 1;
+            for (k, event) in chord.iter_mut().enumerate() {
+                event.time_ms = onset + k as f64 * step;
+                event.duration_ms = if k == last { end - event.time_ms } else { step };
+            }
+            rolled += 1;
+        }
+
+        i = j;
+    }
+
+    if rolled > 0 {
+        debug!("Rolled {} chord(s) into arpeggios..!", rolled);
+        sort_events(&mut events);
+    }
+
+    events
+}
+
+/// Reduces an already built, possibly polyphonic [`Song`] to a single line the same way importing
+/// does, keeping its metadata. A song has no tracks for [`PolyPolicy::Densest`] to choose between,
+/// so that policy is rejected. Rests are left out of the reduction & refilled afterwards, see
+/// [`fill_rests`].
+pub fn reduce_song(song: Song, policy: PolyPolicy, merge: bool) -> Result<Song> {
+    if matches!(policy, PolyPolicy::Densest) {
+        bail!("The densest policy needs a MIDI file's tracks to choose between..!");
+    }
+
+    let had_rests = song.events.iter().any(Event::is_rest);
+    let notes = song
+        .events
+        .into_iter()
+        .filter(|event| !event.is_rest())
+        .collect();
+    let reduced = Song {
+        metadata: song.metadata,
+        events: reduce_to_monophonic(notes, policy, merge),
+    };
+
+    Ok(if had_rests {
+        fill_rests(reduced)
+    } else {
+        reduced
+    })
+}
+
+/// Inserts an [`Event::rest`] into every gap of more than [`EPSILON_MS`] between consecutive
+/// events, so the song's timeline runs from its first note to its last without any. Expects
+/// `song` monophonic & in time order.
+pub fn fill_rests(song: Song) -> Song {
+    let mut events: Vec<Event> = Vec::with_capacity(song.events.len());
+    let mut sounding_until: Option<f64> = None;
+
+    for event in song.events.into_iter() {
+        if let Some(end_ms) = sounding_until
+            && event.time_ms - end_ms > EPSILON_MS
+        {
+            events.push(Event::rest(end_ms, event.time_ms - end_ms));
+        }
+
+        let end_ms = event.time_ms + event.duration_ms;
+        sounding_until = Some(sounding_until.map_or(end_ms, |until| until.max(end_ms)));
+        events.push(event);
+    }
+
+    Song {
+        metadata: song.metadata,
+        events,
+    }
+}
+
+/// Randomly leaves out each event with the given probability, keeping the timing of the rest. The
+/// choice only depends on `seed` & each event's position, so the same seed always drops the same
+/// events from the same song.
+pub fn drop_notes(song: Song, probability: f64, seed: u64) -> Song {
+    let probability = probability.clamp(0.0, 1.0);
+    let before = song.events.len();
+
+    let events = song
+        .events
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _event)| unit_noise(seed, *i as u64) >= probability)
+        .map(|(_i, event)| event)
+        .collect::<Vec<_>>();
+
+    if events.len() < before {
+        debug!(
+            "Dropped {} of {} events at random..!",
+            before - events.len(),
+            before
+        );
+    }
+
+    Song {
+        metadata: song.metadata,
+        events,
+    }
+}
+
+/// A uniform value in `[0, 1)` derived from `seed` & `index` (one SplitMix64 step).
+fn unit_noise(seed: u64, index: u64) -> f64 {
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Given a possibly-overlapping set of events, reduce to a single monophonic sequence according
+/// to the specified policy. The events emitted by this function should not overlap.
+///
+/// Basic approach: create a sorted set of time points where something changes (start or end), and
+/// at each point decide which note should be active using the policy.
+fn reduce_to_monophonic(events: Vec<Event>, policy: PolyPolicy, merge: bool) -> Vec<Event> {
+    reduce_to_monophonic_with(events, policy, merge, EmitVelocity::default())
+}
+
+/// [`reduce_to_monophonic`], giving each emitted event its velocity according to `emit_velocity`.
+fn reduce_to_monophonic_with(
+    events: Vec<Event>,
+    policy: PolyPolicy,
+    merge: bool,
+    emit_velocity: EmitVelocity,
+) -> Vec<Event> {
+    if events.is_empty() {
+        return events;
+    }
+
+    let mut points: Vec<Point> = Vec::new();
+    for ev in events.into_iter() {
+        points.push(Point {
+            time_ms: ev.time_ms,
+            is_start: true,
+            midi: ev.note.midi,
+            velocity: ev.note.velocity,
+            duration_ms: ev.duration_ms,
+        });
+        points.push(Point {
+            time_ms: ev.time_ms + ev.duration_ms,
+            is_start: false,
+            midi: ev.note.midi,
+            velocity: ev.note.velocity,
+            duration_ms: ev.duration_ms,
+        });
+    }
+
+    // Events with `is_start == false` come before `is_start == true` for the same time_ms,
+    // so that notes ending at some time_ms `t` will not be counted active for another event starting at the same `t`.
+    points.sort_by(|a, b| {
+        a.time_ms
+            .partial_cmp(&b.time_ms)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| (a.is_start as u8).cmp(&(b.is_start as u8)))
+    });
+
+    let mut result: Vec<Event> = Vec::new();
+    let mut current_note: Option<u8> = None;
+    let mut current_start: Option<f64> = None;
+    let mut current_onset: Option<(u8, f64)> = None;
+    let mut active: BTreeMap<u8, f64> = BTreeMap::new();
+    let mut note_velocity_lookup: HashMap<u8, u8> = HashMap::new();
+    let mut note_onset_lookup: HashMap<u8, f64> = HashMap::new();
+    let mut segment_peak: u8 = 0;
+
+    let mut reduced = false;
+    let mut points = points.into_iter().peekable();
+    while let Some(first) = points.next() {
+        // Apply every point that coincides with this boundary before consulting the policy,
+        // so a note ending at `t` and another starting at `t` can never produce an intermediate
+        // selection (and with it a sliver event or a spurious overlap warning).
+        let boundary_ms = first.time_ms;
+        let mut next = Some(first);
+
+        // The loudest note sounding just before this boundary, for the segment that may end here.
+        let peak_before = segment_peak;
+
+        while let Some(pt) = next {
+            if pt.is_start {
+                note_velocity_lookup.insert(pt.midi, pt.velocity);
+                note_onset_lookup.insert(pt.midi, pt.time_ms);
+                active.insert(pt.midi, pt.time_ms + pt.duration_ms);
+            } else {
+                active.remove(&pt.midi);
+                note_velocity_lookup.remove(&pt.midi);
+                note_onset_lookup.remove(&pt.midi);
+            }
+
+            next = points.next_if(|pt| pt.time_ms - boundary_ms <= BOUNDARY_EPSILON_MS);
+        }
+
+        let chosen: Option<u8> = match policy {
+            // Densest picks its track before reducing (see `densest_track_song`), so within that
+            // track it keeps the highest pitch. Songs without tracks reject it in `reduce_song`.
+            PolyPolicy::Highest | PolyPolicy::Densest => active.keys().next_back().copied(),
+            PolyPolicy::Lowest => active.keys().next().copied(),
+            PolyPolicy::Loudest => active
+                .keys()
+                .filter_map(|note| note_velocity_lookup.get(note).map(|&vel| (vel, *note)))
+                .max_by_key(|(vel, _)| *vel)
+                .map(|(_, note)| note),
+        };
+
+        if active.len() > 1 && !reduced {
+            reduced = true;
+            warn!(
+                "MIDI contains multiple overlapping events, so reducing to monophony according to the chosen policy [{:?}]...",
+                policy
+            );
+        }
+
+        let loudest_active = note_velocity_lookup.values().copied().max().unwrap_or(0);
+
+        if chosen != current_note {
+            if let (Some(cn), Some(cs), Some((onset_velocity, onset_ms))) =
+                (current_note, current_start, current_onset)
+                && boundary_ms > cs + EPSILON_MS
+            {
+                let velocity = match emit_velocity {
+                    EmitVelocity::Onset => onset_velocity,
+                    EmitVelocity::Peak => peak_before.max(onset_velocity),
+                    EmitVelocity::Decay => {
+                        let halvings = (boundary_ms - onset_ms) / VELOCITY_HALF_LIFE_MS;
+                        (onset_velocity as f64 * 0.5f64.powf(halvings))
+                            .round()
+                            .max(1.0) as u8
+                    }
+                };
+
+                result.push(Event {
+                    note: Note { midi: cn, velocity },
+                    time_ms: cs,
+                    duration_ms: boundary_ms - cs,
+                    override_keys: None,
+                });
+            }
+
+            if let Some(ch) = chosen {
+                current_note = Some(ch);
+                current_start = Some(boundary_ms);
+                current_onset = note_velocity_lookup
+                    .get(&ch)
+                    .copied()
+                    .zip(note_onset_lookup.get(&ch).copied());
+            } else {
+                current_note = None;
+                current_start = None;
+                current_onset = None;
+            }
+
+            segment_peak = loudest_active;
+        } else {
+            segment_peak = segment_peak.max(loudest_active);
+        }
+    }
+
+    let mut n = 0;
+    let mut merged: Vec<Event> = Vec::new();
+    for ev in result.into_iter() {
+        if let Some(last) = merged.last_mut()
+            && merge
+            && last.note == ev.note
+            && ((last.time_ms + last.duration_ms) - ev.time_ms).abs() <= EPSILON_MS
+        {
+            n += 1;
+            let new_end = (last.time_ms + last.duration_ms).max(ev.time_ms + ev.duration_ms);
+            last.duration_ms = new_end - last.time_ms;
+            continue;
+        }
+
+        merged.push(ev);
+    }
+
+    if merge && n > 0 {
+        warn!(
+            "Merged {} consecutive timeline event(s) during monophonic reduction..!",
+            n
+        );
+    }
+
+    // Regardless of `merge`, the game blurs two same-pitch notes into one unless there's a real
+    // gap between them, so trim the earlier note to make room.
+    let mut separated = 0;
+    for i in 1..merged.len() {
+        let (next_midi, next_start) = (merged[i].note.midi, merged[i].time_ms);
+        let last = &mut merged[i - 1];
+
+        if last.note.midi == next_midi
+            && next_start - (last.time_ms + last.duration_ms) < EPSILON_MS
+        {
+            separated += 1;
+            last.duration_ms = (next_start - EPSILON_MS - last.time_ms).max(0.0);
+        }
+    }
+
+    if separated > 0 {
+        debug!(
+            "Trimmed {} same-pitch event(s) to keep them distinct from the next..!",
+            separated
+        );
+    }
+
+    // Trimming can leave an event too short to play, so only cull once it's done.
+    merged
+        .into_iter()
+        .filter(|event| {
+            if event.duration_ms < EPSILON_MS {
+                warn!(
+                    "Culling final event with a duration below the allowed epsilon..! [{:.3}ms]",
+                    event.duration_ms
+                );
+                return false;
+            }
+            true
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::parse_scale;
+    use midly::num::{u4, u7, u15, u24, u28};
+    use midly::{Format, Header, TrackEvent};
+
+    const TEST_TICKS_PER_QUARTER: u16 = 480;
+
+    /// The on-disk song some tests also check against, when it's available.
+    const TWINKLE_PATH: &str = "./resources/songs/Twinkle_Twinkle_Little_Star.mid";
+
+    /// Twinkle Twinkle Little Star in A, one pitch per quarter note.
+    const TWINKLE_MELODY: [u8; 42] = [
+        69, 69, 76, 76, 78, 78, 76, 74, 74, 73, 73, 71, 71, 69, // Twinkle twinkle...
+        76, 76, 74, 74, 73, 73, 71, 76, 76, 74, 74, 73, 73, 71, // Up above the world...
+        69, 69, 76, 76, 78, 78, 76, 74, 74, 73, 73, 71, 71, 69, // Twinkle twinkle...
+    ];
+
+    /// Serializes `(delta_ticks, event)` tracks into a format 1 SMF at 120bpm.
+    fn build_smf(tracks: Vec<Vec<(u32, TrackEventKind<'static>)>>) -> Vec<u8> {
+        build_smf_with_timing(Timing::Metrical(u15::new(TEST_TICKS_PER_QUARTER)), tracks)
+    }
+
+    /// [`build_smf`] with the given timing in its header.
+    fn build_smf_with_timing(
+        timing: Timing,
+        tracks: Vec<Vec<(u32, TrackEventKind<'static>)>>,
+    ) -> Vec<u8> {
+        let header = Header::new(Format::Parallel, timing);
+
+        let mut smf = Smf::new(header);
+        for track in tracks {
+            let mut events = track
+                .into_iter()
+                .map(|(delta, kind)| TrackEvent {
+                    delta: u28::new(delta),
+                    kind,
+                })
+                .collect::<Vec<_>>();
+
+            events.push(TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            });
+            smf.tracks.push(events);
+        }
+
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes)
+            .expect("Synthetic MIDI should serialize..!");
+        bytes
+    }
+
+    /// A single track melody on channel 0, playing each pitch for `note_ticks` back to back.
+    fn build_test_smf(pitches: &[u8], note_ticks: u32) -> Vec<u8> {
+        let track = pitches
+            .iter()
+            .flat_map(|&key| [(0, note_on(0, key, 100)), (note_ticks, note_off(0, key))])
+            .collect::<Vec<_>>();
+
+        build_smf(vec![track])
+    }
+
+    fn twinkle_smf() -> Vec<u8> {
+        build_test_smf(&TWINKLE_MELODY, TEST_TICKS_PER_QUARTER as u32)
+    }
+
+    fn note_on(channel: u8, key: u8, vel: u8) -> TrackEventKind<'static> {
+        TrackEventKind::Midi {
+            channel: u4::new(channel),
+            message: MidiMessage::NoteOn {
+                key: u7::new(key),
+                vel: u7::new(vel),
+            },
+        }
+    }
+
+    fn note_off(channel: u8, key: u8) -> TrackEventKind<'static> {
+        note_off_with_velocity(channel, key, 0)
+    }
+
+    fn note_off_with_velocity(channel: u8, key: u8, vel: u8) -> TrackEventKind<'static> {
+        TrackEventKind::Midi {
+            channel: u4::new(channel),
+            message: MidiMessage::NoteOff {
+                key: u7::new(key),
+                vel: u7::new(vel),
+            },
+        }
+    }
+
+    fn channel_aftertouch(channel: u8, pressure: u8) -> TrackEventKind<'static> {
+        TrackEventKind::Midi {
+            channel: u4::new(channel),
+            message: MidiMessage::ChannelAftertouch {
+                vel: u7::new(pressure),
+            },
+        }
+    }
+
+    fn program_change(channel: u8, program: u8) -> TrackEventKind<'static> {
+        TrackEventKind::Midi {
+            channel: u4::new(channel),
+            message: MidiMessage::ProgramChange {
+                program: u7::new(program),
+            },
+        }
+    }
+
+    fn import_bytes(bytes: &[u8], options: &ImportOptions) -> Result<Song> {
+        midi_bytes_to_song(bytes, Path::new("synthetic.mid"), options)
+    }
+
+    fn approx_eq(a: f64, b: f64) -> bool {
+        (a - b).abs() <= EPSILON_MS
+    }
+
+    fn create_event(midi: u8, velocity: u8, start: f64, dur: f64) -> Event {
+        Event {
+            note: Note { midi, velocity },
+            time_ms: start,
+            duration_ms: dur,
+            override_keys: None,
+        }
+    }
+
+    #[test]
+    fn midi_file_import() {
+        env_logger::try_init().unwrap_or(());
+
+        if !Path::new(TWINKLE_PATH).exists() {
+            warn!(
+                "{} is missing, skipping the on-disk import..!",
+                TWINKLE_PATH
+            );
+            return;
+        }
+
+        let song = import_midi_file(TWINKLE_PATH, 0, PolyPolicy::Highest, false, Some((69, 93)));
+
+        if song.is_err() {
+            warn!("{:?}", song);
+        }
+
+        let events = song.expect("Twinkle should import..!").events;
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|e| (69..=93).contains(&e.note.midi)));
+        assert!(
+            events
+                .windows(2)
+                .all(|w| w[0].time_ms + w[0].duration_ms <= w[1].time_ms + EPSILON_MS)
+        );
+
+        let info = midi_file_info(TWINKLE_PATH).expect("Twinkle should parse..!");
+        assert_eq!(info.tracks.len(), 1);
+        assert_eq!(info.ticks_per_quarter, Some(480));
+        assert_eq!(info.tracks[0].notes, events.len());
+    }
+
+    #[test]
+    fn synthetic_file_info() {
+        env_logger::try_init().unwrap_or(());
+
+        let info = midi_bytes_info(&twinkle_smf()).expect("Synthetic MIDI should parse..!");
+
+        assert_eq!(info.format, 1);
+        assert_eq!(info.tracks.len(), 1);
+        assert_eq!(info.ticks_per_quarter, Some(TEST_TICKS_PER_QUARTER));
+        assert_eq!(info.tracks[0].notes, TWINKLE_MELODY.len());
+        assert_eq!(info.tracks[0].pitch_range, Some((69, 78)));
+    }
+
+    #[test]
+    fn midi_semitone_transpose() {
+        env_logger::try_init().unwrap_or(());
+
+        let bytes = twinkle_smf();
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            ..Default::default()
+        };
+        let song_default = import_bytes(&bytes, &options);
+        let song_transposed = import_bytes(
+            &bytes,
+            &ImportOptions {
+                transpose_semitones: 2,
+                ..options.clone()
+            },
+        );
+
+        if song_default.is_err() {
+            warn!("{:?}", song_default);
+        }
+        if song_transposed.is_err() {
+            warn!("{:?}", song_transposed);
+        }
+
+        assert!(song_default.is_ok());
+        assert!(song_transposed.is_ok());
+        let events_default = song_default.unwrap().events;
+        let events_transposed = song_transposed.unwrap().events;
+
+        assert!(!events_default.is_empty());
+        assert_eq!(events_default.len(), events_transposed.len());
+
+        // Every note moves up a whole step & keeps its timing.
+        for (default, transposed) in events_default.iter().zip(events_transposed.iter()) {
+            assert_eq!(transposed.note.midi, default.note.midi + 2);
+            assert!(approx_eq(transposed.time_ms, default.time_ms));
+        }
+    }
+
+    #[test]
+    fn midi_octave_transpose() {
+        env_logger::try_init().unwrap_or(());
+
+        let range = 45..=69;
+        let options = ImportOptions {
+            clip_to_range: Some((*range.start(), *range.end())),
+            ..Default::default()
+        };
+
+        let song = import_bytes(&twinkle_smf(), &options);
+
+        if song.is_err() {
+            warn!("{:?}", song);
+        }
+
+        assert!(song.is_ok());
+        let events = song.unwrap().events;
+
+        // Folding moves notes by octaves instead of dropping them.
+        let unfolded = import_bytes(&twinkle_smf(), &ImportOptions::default())
+            .expect("Synthetic MIDI should import..!");
+        assert_eq!(events.len(), unfolded.events.len());
+        assert!(
+            events
+                .iter()
+                .map(|e| e.note.midi)
+                .all(|midi| range.contains(&midi))
+        );
+    }
+
+    #[test]
+    fn highest_policy_overlap() {
+        env_logger::try_init().unwrap_or(());
+
+        let input = vec![
+            create_event(69, 255, 0.0, 1000.0),
+            create_event(77, 255, 500.0, 1000.0),
+        ];
+
+        let out = reduce_to_monophonic(input, PolyPolicy::Highest, false);
+        assert_eq!(out.len(), 2);
+
+        assert_eq!(out[0].note.midi, 69);
+        assert!(approx_eq(out[0].time_ms, 0.0));
+        assert!(approx_eq(out[0].duration_ms, 500.0));
+
+        assert_eq!(out[1].note.midi, 77);
+        assert!(approx_eq(out[1].time_ms, 500.0));
+        assert!(approx_eq(out[1].duration_ms, 1000.0));
+    }
+
+    #[test]
+    fn lowest_policy_overlap() {
+        env_logger::try_init().unwrap_or(());
+
+        let input = vec![
+            create_event(77, 255, 0.0, 1000.0),
+            create_event(69, 255, 500.0, 1000.0),
+        ];
+
+        let out = reduce_to_monophonic(input, PolyPolicy::Lowest, false);
+        assert_eq!(out.len(), 2);
+
+        assert_eq!(out[0].note.midi, 77);
+        assert!(approx_eq(out[0].time_ms, 0.0));
+        assert!(approx_eq(out[0].duration_ms, 500.0));
+
+        assert_eq!(out[1].note.midi, 69);
+        assert!(approx_eq(out[1].time_ms, 500.0));
+        assert!(approx_eq(out[1].duration_ms, 1000.0));
+    }
+
+    #[test]
+    fn loudest_policy_overlap() {
+        env_logger::try_init().unwrap_or(());
+
+        let input = vec![
+            create_event(77, 128, 0.0, 1000.0),
+            create_event(69, 255, 500.0, 1000.0),
+        ];
+
+        let out = reduce_to_monophonic(input, PolyPolicy::Loudest, false);
+        assert_eq!(out.len(), 2);
+
+        assert_eq!(out[0].note.midi, 77);
+        assert!(approx_eq(out[0].time_ms, 0.0));
+        assert!(approx_eq(out[0].duration_ms, 500.0));
+
+        assert_eq!(out[1].note.midi, 69);
+        assert!(approx_eq(out[1].time_ms, 500.0));
+        assert!(approx_eq(out[1].duration_ms, 1000.0));
+    }
+
+    #[test]
+    fn emit_velocity_of_trimmed_note() {
+        env_logger::try_init().unwrap_or(());
+
+        // A soft held note with a louder one sounding under it, then cut short by an even louder
+        // note above it, which it resumes after.
+        let input = vec![
+            create_event(69, 60, 0.0, 3000.0),
+            create_event(64, 90, 500.0, 500.0),
+            create_event(76, 120, 2000.0, 500.0),
+        ];
+
+        let velocities = |emit_velocity| {
+            reduce_to_monophonic_with(input.clone(), PolyPolicy::Highest, false, emit_velocity)
+                .iter()
+                .map(|e| (e.note.midi, e.time_ms, e.note.velocity))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            velocities(EmitVelocity::Onset),
+            vec![(69, 0.0, 60), (76, 2000.0, 120), (69, 2500.0, 60)]
+        );
+        assert_eq!(
+            velocities(EmitVelocity::Peak),
+            vec![(69, 0.0, 90), (76, 2000.0, 120), (69, 2500.0, 60)]
+        );
+        // Held for two half-lives when it's cut short, then three by the time it ends, while the
+        // louder note only sounds for half of one.
+        assert_eq!(
+            velocities(EmitVelocity::Decay),
+            vec![(69, 0.0, 15), (76, 2000.0, 85), (69, 2500.0, 8)]
+        );
+    }
+
+    #[test]
+    fn aftertouch_raises_loudest() {
+        env_logger::try_init().unwrap_or(());
+
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![
+            vec![(0, note_on(0, 77, 100)), (2 * q, note_off(0, 77))],
+            vec![
+                (0, note_on(1, 81, 40)),
+                (q / 2, channel_aftertouch(1, 120)),
+                (3 * q / 2, note_off(1, 81)),
+            ],
+        ]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            policy: PolyPolicy::Loudest,
+            ..Default::default()
+        };
+
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 1);
+        assert_eq!(song.events[0].note.midi, 77);
+
+        let with_aftertouch = ImportOptions {
+            use_aftertouch: true,
+            ..options
+        };
+
+        let song = import_bytes(&bytes, &with_aftertouch).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 1);
+        assert_eq!(song.events[0].note.midi, 81);
+        assert_eq!(song.events[0].note.velocity, 120);
+    }
+
+    #[test]
+    fn channel_gain_raises_loudest() {
+        env_logger::try_init().unwrap_or(());
+
+        // A quiet melody on channel 1 over a louder accompaniment on channel 0.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![
+            vec![(0, note_on(0, 77, 90)), (2 * q, note_off(0, 77))],
+            vec![(0, note_on(1, 81, 50)), (2 * q, note_off(1, 81))],
+        ]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            policy: PolyPolicy::Loudest,
+            ..Default::default()
+        };
+
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 1);
+        assert_eq!(song.events[0].note.midi, 77);
+
+        let gained = ImportOptions {
+            channel_gain: HashMap::from([(1, 2.0)]),
+            ..options
+        };
+
+        let song = import_bytes(&bytes, &gained).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 1);
+        assert_eq!(song.events[0].note.midi, 81);
+        assert_eq!(song.events[0].note.velocity, 100);
+
+        // Gains past the top of the range clamp to 127.
+        let boosted = ImportOptions {
+            channel_gain: HashMap::from([(1, 4.0)]),
+            ..gained
+        };
+
+        let song = import_bytes(&bytes, &boosted).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events[0].note.velocity, 127);
+    }
+
+    #[test]
+    fn release_velocity_shortens_notes() {
+        env_logger::try_init().unwrap_or(());
+
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (q, note_off_with_velocity(0, 69, 40)),
+            (q, note_on(0, 71, 100)),
+            (q, note_off_with_velocity(0, 71, 127)),
+        ]]);
+
+        assert!(approx_eq(release_articulation(40), 1.0));
+        assert!(approx_eq(
+            release_articulation(127),
+            MIN_RELEASE_ARTICULATION
+        ));
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            use_release_velocity: true,
+            ..Default::default()
+        };
+
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 2);
+        assert!(approx_eq(song.events[0].duration_ms, 500.0));
+        assert!(approx_eq(song.events[1].duration_ms, 250.0));
+        assert!(approx_eq(song.events[1].time_ms, 1000.0));
+    }
+
+    #[test]
+    fn reduce_built_song() {
+        env_logger::try_init().unwrap_or(());
+
+        let song = Song {
+            metadata: Metadata {
+                title: Some(String::from("Chords")),
+                tempo_bpm: Some(90.0),
+                markers: Vec::new(),
+            },
+            events: vec![
+                create_event(69, 100, 0.0, 1000.0),
+                create_event(73, 100, 0.0, 500.0),
+                create_event(76, 100, 250.0, 500.0),
+            ],
+        };
+
+        assert!(reduce_song(song.clone(), PolyPolicy::Densest, false).is_err());
+
+        let reduced =
+            reduce_song(song, PolyPolicy::Highest, false).expect("Highest should reduce..!");
+        assert_eq!(reduced.metadata.title.as_deref(), Some("Chords"));
+        assert_eq!(reduced.metadata.tempo_bpm, Some(90.0));
+
+        let pitches = reduced
+            .events
+            .iter()
+            .map(|e| e.note.midi)
+            .collect::<Vec<_>>();
+        assert_eq!(pitches, vec![73, 76, 69]);
+        assert!(
+            reduced
+                .events
+                .windows(2)
+                .all(|w| w[0].time_ms + w[0].duration_ms <= w[1].time_ms + EPSILON_MS)
+        );
+    }
+
+    #[test]
+    fn reduce_song_around_rests() {
+        env_logger::try_init().unwrap_or(());
+
+        let song = Song {
+            metadata: Metadata::default(),
+            events: vec![
+                create_event(69, 100, 0.0, 500.0),
+                create_event(73, 100, 0.0, 250.0),
+                Event::rest(500.0, 500.0),
+                create_event(76, 100, 1000.0, 500.0),
+            ],
+        };
+
+        let reduced =
+            reduce_song(song, PolyPolicy::Highest, false).expect("Highest should reduce..!");
+        let events = reduced
+            .events
+            .iter()
+            .map(|e| (e.note.midi, e.time_ms, e.duration_ms))
+            .collect::<Vec<_>>();
+
+        // The rest never outranks a note, & still fills the gap before the last one.
+        assert_eq!(
+            events,
+            vec![
+                (73, 0.0, 250.0),
+                (69, 250.0, 250.0),
+                (REST_MIDI, 500.0, 500.0),
+                (76, 1000.0, 500.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn roll_only_large_chords() {
+        env_logger::try_init().unwrap_or(());
+
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (0, note_on(0, 73, 100)),
+            (2 * q, note_off(0, 69)),
+            (0, note_off(0, 73)),
+            (0, note_on(0, 69, 100)),
+            (0, note_on(0, 73, 100)),
+            (0, note_on(0, 76, 100)),
+            (2 * q, note_off(0, 69)),
+            (0, note_off(0, 73)),
+            (0, note_off(0, 76)),
+        ]]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            arpeggiate_min_notes: 3,
+            ..Default::default()
+        };
+
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![73, 69, 73, 76]);
+
+        // The dyad collapses to its highest note, while the triad is rolled from the bottom up.
+        assert!(approx_eq(song.events[0].duration_ms, 1000.0));
+        assert!(approx_eq(song.events[1].time_ms, 1000.0));
+        assert!(approx_eq(song.events[2].time_ms, 1000.0 + ARPEGGIO_STEP_MS));
+        assert!(approx_eq(
+            song.events[3].time_ms,
+            1000.0 + 2.0 * ARPEGGIO_STEP_MS
+        ));
+        assert!(approx_eq(
+            song.events[3].time_ms + song.events[3].duration_ms,
+            2000.0
+        ));
+    }
+
+    #[test]
+    fn seeded_note_drops() {
+        env_logger::try_init().unwrap_or(());
+
+        let song = import_bytes(&twinkle_smf(), &ImportOptions::default())
+            .expect("Synthetic MIDI should import..!");
+        let notes = |song: &Song| {
+            song.events
+                .iter()
+                .map(|e| (e.note, e.time_ms, e.duration_ms))
+                .collect::<Vec<_>>()
+        };
+
+        let first = drop_notes(song.clone(), 0.25, 7);
+        let second = drop_notes(song.clone(), 0.25, 7);
+        assert_eq!(notes(&first), notes(&second));
+        assert!(first.events.len() < song.events.len());
+
+        // Survivors keep their timing.
+        let original = notes(&song);
+        assert!(notes(&first).iter().all(|kept| original.contains(kept)));
+
+        let untouched = drop_notes(song.clone(), 0.0, 7);
+        assert_eq!(notes(&untouched), original);
+    }
+
+    #[test]
+    fn fold_far_notes_into_narrow_range() {
+        env_logger::try_init().unwrap_or(());
+
+        // 127 is 9 octaves above its G in 19..=21, while 126 has no octave in that range at all.
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 127, 100)),
+            (480, note_off(0, 127)),
+            (0, note_on(0, 126, 100)),
+            (480, note_off(0, 126)),
+        ]]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((19, 21)),
+            ..Default::default()
+        };
+
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![19]);
+
+        assert_eq!(fold_into_range(0, Some((69, 93))), 72);
+    }
+
+    #[test]
+    fn snap_chromatic_run_to_pentatonic() {
+        env_logger::try_init().unwrap_or(());
+
+        // Every semitone from A4 up to A6, an eighth at 120bpm each.
+        let chromatic = (69..=93).collect::<Vec<u8>>();
+        let bytes = build_test_smf(&chromatic, TEST_TICKS_PER_QUARTER as u32 / 2);
+
+        let scale = parse_scale("a-minor-pentatonic").unwrap();
+        assert_eq!(scale, parse_scale("9, 0, 2, 4, 7").unwrap());
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            scale_constraint: Some(scale.clone()),
+            ..Default::default()
+        };
+
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert!(!song.events.is_empty());
+        for event in song.events.iter() {
+            assert!(scale.contains(&(event.note.midi % 12)));
+            assert!((69..=93).contains(&event.note.midi));
+        }
+
+        // Ties between two scale tones snap down, & the run's lowest note has nowhere lower to go.
+        assert_eq!(snap_to_scale(70, &scale, Some((69, 93))), 69);
+        assert_eq!(snap_to_scale(73, &scale, Some((69, 93))), 72);
+        assert_eq!(snap_to_scale(68, &scale, Some((69, 93))), 69);
+    }
+
+    #[test]
+    fn gliss_fills_octave_leap() {
+        env_logger::try_init().unwrap_or(());
+
+        // A4 up to A5 & back down a step, each note held for a quarter at 120bpm.
+        let bytes = build_test_smf(&[69, 81, 79], TEST_TICKS_PER_QUARTER as u32);
+        let options = ImportOptions {
+            clip_to_range: Some(FLUTE_RANGE),
+            gliss_threshold_semitones: Some(7),
+            ..Default::default()
+        };
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches.first(), Some(&69));
+        assert_eq!(&pitches[pitches.len() - 2..], &[81, 79]);
+
+        // Rising connecting notes fill the leap in the last moments of the A4.
+        let run = &song.events[1..song.events.len() - 2];
+        assert!(!run.is_empty());
+        assert!(
+            run.windows(2)
+                .all(|pair| pair[0].note.midi < pair[1].note.midi)
+        );
+        assert!(run.iter().all(|e| (70..81).contains(&e.note.midi)));
+        assert!(
+            run.iter()
+                .all(|e| e.time_ms >= 500.0 - GLISS_BUDGET_MS - EPSILON_MS)
+        );
+
+        let a5 = &song.events[song.events.len() - 2];
+        let last_fill = &run[run.len() - 1];
+        assert!(approx_eq(
+            last_fill.time_ms + last_fill.duration_ms,
+            a5.time_ms
+        ));
+        assert!(approx_eq(a5.time_ms, 500.0));
+
+        // The step back down is too small for a glissando.
+        let without = import_bytes(
+            &bytes,
+            &ImportOptions {
+                clip_to_range: Some(FLUTE_RANGE),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(song.events.len(), without.events.len() + run.len());
+    }
+
+    #[test]
+    fn import_markers() {
+        env_logger::try_init().unwrap_or(());
+
+        // Four quarter notes at 120bpm, with the verse marked at the start & the chorus on beat 3.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let mut track = vec![(0, TrackEventKind::Meta(MetaMessage::Marker(b"Verse")))];
+        for (i, key) in [69u8, 71, 73, 74].into_iter().enumerate() {
+            if i == 2 {
+                track.push((0, TrackEventKind::Meta(MetaMessage::CuePoint(b"Chorus"))));
+            }
+            track.push((0, note_on(0, key, 100)));
+            track.push((q, note_off(0, key)));
+        }
+
+        let song = import_bytes(&build_smf(vec![track]), &ImportOptions::default())
+            .expect("Synthetic MIDI should import..!");
+
+        let markers = &song.metadata.markers;
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0].1, "Verse");
+        assert!(approx_eq(markers[0].0, 0.0));
+        assert_eq!(markers[1].1, "Chorus");
+        assert!(approx_eq(markers[1].0, 1000.0));
+    }
+
+    #[test]
+    fn merge_two_files() {
+        env_logger::try_init().unwrap_or(());
+
+        // The melody at 120bpm, resting for the second & third beats.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let melody = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (q, note_off(0, 69)),
+            (2 * q, note_on(0, 71, 100)),
+            (q, note_off(0, 71)),
+        ]]);
+
+        // The countermelody at 60bpm, so a quarter note lasts a second, starting half a second in.
+        let countermelody = build_smf(vec![vec![
+            (
+                0,
+                TrackEventKind::Meta(MetaMessage::Tempo(u24::new(1_000_000))),
+            ),
+            (0, note_on(0, 76, 100)),
+            (q, note_off(0, 76)),
+        ]]);
+
+        let (song, _) = merge_midi_bytes(
+            &[(&melody, 0.0), (&countermelody, 500.0)],
+            None,
+            &ImportOptions::default(),
+        )
+        .expect("Synthetic MIDI should merge..!");
+
+        let notes = song
+            .events
+            .iter()
+            .map(|e| (e.note.midi, e.time_ms, e.duration_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(notes.len(), 3);
+
+        let expected = [(69, 0.0, 500.0), (76, 500.0, 1000.0), (71, 1500.0, 500.0)];
+        for ((midi, time_ms, duration_ms), (want_midi, want_time, want_duration)) in
+            notes.into_iter().zip(expected)
+        {
+            assert_eq!(midi, want_midi);
+            assert!(approx_eq(time_ms, want_time));
+            assert!(approx_eq(duration_ms, want_duration));
+        }
+    }
+
+    #[test]
+    fn import_first_bars() {
+        env_logger::try_init().unwrap_or(());
+
+        // Three bars of quarter notes in 4/4, with the last note of bar 2 tied over the bar line.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let mut track = vec![(
+            0,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8)),
+        )];
+        for i in 0..11u8 {
+            let hold = if i == 7 { 2 * q } else { q };
+            track.push((0, note_on(0, 69 + i, 100)));
+            track.push((hold, note_off(0, 69 + i)));
+        }
+
+        let options = ImportOptions {
+            max_bars: Some(2),
+            ..Default::default()
+        };
+
+        let song = import_bytes(&build_smf(vec![track]), &options)
+            .expect("Synthetic MIDI should import..!");
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, (69..77).collect::<Vec<_>>());
+
+        // Two bars of 4/4 at 120bpm last 4 seconds, so the tied note is clipped at the bar line.
+        let last = song.events.last().unwrap();
+        assert!(approx_eq(last.time_ms, 3500.0));
+        assert!(approx_eq(last.time_ms + last.duration_ms, 4000.0));
+
+        // A bar of 3/4 followed by 4/4.
+        let tempo_map = TempoMap {
+            segments: Vec::new(),
+            ticks_per_quarter: TEST_TICKS_PER_QUARTER as u64,
+            ticks_per_second: None,
+            time_signatures: vec![(0, 3, 2), (3 * q as u64, 4, 2)],
+            markers: Vec::new(),
+        };
+        assert_eq!(tempo_map.ticks_for_bars(1), 3 * q as u64);
+        assert_eq!(tempo_map.ticks_for_bars(2), 7 * q as u64);
+    }
+
+    #[test]
+    fn list_dropped_and_folded_notes() {
+        env_logger::try_init().unwrap_or(());
+
+        // Middle C has no octave within A4..=B4, while A5 folds down onto A4.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (q, note_off(0, 69)),
+            (0, note_on(0, 60, 100)),
+            (q, note_off(0, 60)),
+            (0, note_on(0, 81, 100)),
+            (q, note_off(0, 81)),
+            (0, note_on(0, 127, 100)),
+            (q, note_off(0, 127)),
+        ]]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 71)),
+            ..Default::default()
+        };
+        let (song, diagnostics) =
+            midi_bytes_to_song_with_diagnostics(&bytes, Path::new("synthetic.mid"), &options)
+                .expect("Synthetic MIDI should import..!");
+
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![69, 69]);
+
+        let changes = diagnostics
+            .changes
+            .iter()
+            .map(|change| (change.midi, change.time_ms, change.action, change.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (60, 500.0, ChangeAction::Dropped, ChangeReason::OutOfRange),
+                (
+                    81,
+                    1000.0,
+                    ChangeAction::Moved(69),
+                    ChangeReason::OctaveFolded
+                ),
+                (127, 1500.0, ChangeAction::Dropped, ChangeReason::OutOfRange),
+            ]
+        );
+    }
+
+    #[test]
+    fn list_changes_by_start_time() {
+        env_logger::try_init().unwrap_or(());
+
+        // Middle C is held over the whole of a G9, so it's released last but started first.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 60, 100)),
+            (q, note_on(0, 127, 100)),
+            (q, note_off(0, 127)),
+            (q, note_off(0, 60)),
+            (0, note_on(0, 69, 100)),
+            (q, note_off(0, 69)),
+        ]]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 71)),
+            ..Default::default()
+        };
+        let (_, diagnostics) =
+            midi_bytes_to_song_with_diagnostics(&bytes, Path::new("synthetic.mid"), &options)
+                .expect("Synthetic MIDI should import..!");
+
+        let changes = diagnostics
+            .changes
+            .iter()
+            .map(|change| (change.midi, change.time_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, vec![(60, 0.0), (127, 500.0)]);
+    }
+
+    #[test]
+    fn sysex_keeps_note_timing() {
+        env_logger::try_init().unwrap_or(());
+
+        // A GM System On before, between & during the notes, each with a delta of its own.
+        const GM_ON: &[u8] = &[0x7E, 0x7F, 0x09, 0x01, 0xF7];
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, TrackEventKind::SysEx(GM_ON)),
+            (q / 2, TrackEventKind::SysEx(GM_ON)),
+            (q / 2, note_on(0, 69, 100)),
+            (q / 4, TrackEventKind::Escape(&[0xF8])),
+            (3 * q / 4, note_off(0, 69)),
+            (0, note_on(0, 71, 100)),
+            (q, note_off(0, 71)),
+            (q, TrackEventKind::SysEx(GM_ON)),
+        ]]);
+
+        let song = import_bytes(&bytes, &ImportOptions::default())
+            .expect("MIDI with SysEx should import..!");
+        let notes = song
+            .events
+            .iter()
+            .map(|e| (e.note.midi, e.time_ms, e.duration_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(notes, vec![(69, 500.0, 500.0), (71, 1000.0, 500.0)]);
+    }
+
+    #[test]
+    fn report_fold_collisions() {
+        env_logger::try_init().unwrap_or(());
+
+        // A3 & A4 sound together, and a range of a single octave folds both onto A4.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 57, 100)),
+            (0, note_on(0, 69, 100)),
+            (q, note_off(0, 57)),
+            (0, note_off(0, 69)),
+            (0, note_on(0, 60, 100)),
+            (q, note_off(0, 60)),
+        ]]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 80)),
+            ..Default::default()
+        };
+        let (song, diagnostics) =
+            midi_bytes_to_song_with_diagnostics(&bytes, Path::new("synthetic.mid"), &options)
+                .expect("Synthetic MIDI should import..!");
+
+        assert_eq!(diagnostics.fold_collisions, 1);
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![69, 72]);
+
+        // Without folding nothing collides.
+        let (_, diagnostics) = midi_bytes_to_song_with_diagnostics(
+            &bytes,
+            Path::new("synthetic.mid"),
+            &ImportOptions::default(),
+        )
+        .expect("Synthetic MIDI should import..!");
+        assert_eq!(
+            diagnostics,
+            ImportDiagnostics {
+                max_concurrent_notes: 2,
+                polyphonic_fraction: 0.5,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn measure_dense_polyphony() {
+        env_logger::try_init().unwrap_or(());
 
-    let mut result: Vec<Event> = Vec::new();
-    let mut current_note: Option<u8> = None;
-    let mut current_start: Option<f64> = None;
-    let mut active: BTreeMap<u8, f64> = BTreeMap::new();
-    let mut note_velocity_lookup: HashMap<u8, u8> = HashMap::new();
+        // A held A4 under a C major triad for its first half, then a lone E5 after a rest.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (0, note_on(0, 72, 100)),
+            (0, note_on(0, 76, 100)),
+            (0, note_on(0, 79, 100)),
+            (q, note_off(0, 72)),
+            (0, note_off(0, 76)),
+            (q / 2, note_off(0, 79)),
+            (q / 2, note_off(0, 69)),
+            (q, note_on(0, 76, 100)),
+            (q, note_off(0, 76)),
+        ]]);
+
+        let (song, diagnostics) = midi_bytes_to_song_with_diagnostics(
+            &bytes,
+            Path::new("synthetic.mid"),
+            &ImportOptions::default(),
+        )
+        .expect("Synthetic MIDI should import..!");
 
-    let mut reduced = false;
-    for pt in points.into_iter() {
-        if pt.is_start {
-            note_velocity_lookup.insert(pt.midi, pt.velocity);
-            active.insert(pt.midi, pt.time_ms + pt.duration_ms);
-        } else {
-            active.remove(&pt.midi);
-            note_velocity_lookup.remove(&pt.midi);
-        }
+        // Four notes for 500ms & two for 250ms, out of 2000ms.
+        assert_eq!(diagnostics.max_concurrent_notes, 4);
+        assert!((diagnostics.polyphonic_fraction - 0.375).abs() < 1e-9);
+        assert!(
+            song.events
+                .windows(2)
+                .all(|w| w[0].time_ms + w[0].duration_ms <= w[1].time_ms)
+        );
+    }
 
-        let chosen: Option<u8> = match policy {
-            PolyPolicy::Highest => active.keys().next_back().copied(),
-            PolyPolicy::Lowest => active.keys().next().copied(),
-            PolyPolicy::Loudest => active
-                .keys()
-                .filter_map(|note| note_velocity_lookup.get(note).map(|&vel| (vel, *note)))
-                .max_by_key(|(vel, _)| *vel)
-                .map(|(_, note)| note),
-            PolyPolicy::Densest => {
-                todo!("Not yet implemented..!");
-            }
-        };
+    #[test]
+    fn extreme_transpose_fails_up_front() {
+        env_logger::try_init().unwrap_or(());
 
-        if active.len() > 1 && !reduced {
-            reduced = true;
-            warn!(
-                "MIDI contains multiple overlapping events, so reducing to monophony according to the chosen policy [{:?}]...",
-                policy
-            );
-        }
+        let options = ImportOptions {
+            transpose_semitones: 100,
+            ..Default::default()
+        };
+        let why = import_bytes(&twinkle_smf(), &options)
+            .expect_err("Transposing Twinkle by +100 should fail..!")
+            .to_string();
+
+        assert!(why.contains("+100 semitones"), "{}", why);
+        assert!(why.contains("0..=127"), "{}", why);
+
+        // Folding into a clip range rescues a large but foldable shift.
+        let options = ImportOptions {
+            transpose_semitones: 36,
+            clip_to_range: Some((69, 93)),
+            ..Default::default()
+        };
+        assert!(import_bytes(&twinkle_smf(), &options).is_ok());
+    }
 
-        if chosen != current_note {
-            if let (Some(cn), Some(cs)) = (current_note, current_start)
-                && pt.time_ms > cs + EPSILON_MS
-            {
-                result.push(Event {
-                    note: Note {
-                        midi: cn,
-                        velocity: pt.velocity,
-                    },
-                    time_ms: cs,
-                    duration_ms: pt.time_ms - cs,
-                });
-            }
+    #[test]
+    fn densest_policy_overlap() {
+        env_logger::try_init().unwrap_or(());
 
-            if let Some(ch) = chosen {
-                current_note = Some(ch);
-                current_start = Some(pt.time_ms);
-            } else {
-                current_note = None;
-                current_start = None;
-            }
-        }
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![
+            vec![
+                (0, note_on(0, 81, 100)),
+                (2 * q, note_off(0, 81)),
+                (0, note_on(0, 83, 100)),
+                (2 * q, note_off(0, 83)),
+            ],
+            vec![
+                (0, note_on(1, 72, 100)),
+                (0, note_on(1, 69, 100)),
+                (q, note_off(1, 72)),
+                (0, note_off(1, 69)),
+                (0, note_on(1, 71, 100)),
+                (q, note_off(1, 71)),
+                (0, note_on(1, 74, 100)),
+                (q, note_off(1, 74)),
+                (0, note_on(1, 76, 100)),
+                (q, note_off(1, 76)),
+            ],
+        ]);
+
+        // Tracks are merged by default, but Densest still only listens to the denser second
+        // track & reduces its opening chord to the top note.
+        let options = ImportOptions {
+            policy: PolyPolicy::Densest,
+            clip_to_range: Some((69, 93)),
+            ..Default::default()
+        };
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![72, 71, 74, 76]);
     }
 
-    let mut n = 0;
-    let mut merged: Vec<Event> = Vec::new();
-    for ev in result.into_iter() {
-        if let Some(last) = merged.last_mut()
-            && merge
-            && last.note == ev.note
-            && ((last.time_ms + last.duration_ms) - ev.time_ms).abs() <= EPSILON_MS
-        {
-            n += 1;
-            let new_end = (last.time_ms + last.duration_ms).max(ev.time_ms + ev.duration_ms);
-            last.duration_ms = new_end - last.time_ms;
-            continue;
-        }
+    #[test]
+    fn coincident_boundary_no_sliver() {
+        env_logger::try_init().unwrap_or(());
 
-        merged.push(ev);
+        // 72 ends at the exact moment 76 begins while 60 sustains underneath, and 76's onset
+        // lands a hair before 72's release after the float round trip.
+        let input = vec![
+            create_event(60, 255, 0.0, 1000.0),
+            create_event(72, 255, 0.0, 500.000_000_1),
+            create_event(76, 255, 500.0, 500.0),
+        ];
+
+        let out = reduce_to_monophonic(input, PolyPolicy::Highest, false);
+        assert_eq!(out.len(), 2);
+        assert!(out.iter().all(|e| e.duration_ms > EPSILON_MS));
+
+        assert_eq!(out[0].note.midi, 72);
+        assert!(approx_eq(out[0].duration_ms, 500.0));
+        assert_eq!(out[1].note.midi, 76);
+        assert!(approx_eq(out[1].time_ms, 500.0));
     }
 
-    if merge && n > 0 {
-        warn!(
-            "Merged {} consecutive timeline event(s) during monophonic reduction..!",
-            n
-        );
+    #[test]
+    fn optimal_transpose_prefers_single_octave() {
+        env_logger::try_init().unwrap_or(());
+
+        // Both +12 and +13 fit [80..=87], but +12 flips the octave modifier on every note while
+        // +13 keeps the whole line in the upper octave.
+        let pitches = [68, 74, 68, 74];
+        assert_eq!(optimal_transpose_for_mappings(&pitches, (80, 87)), 13);
     }
 
-    merged
-}
+    #[test]
+    fn program_filter_keeps_selected_programs() {
+        env_logger::try_init().unwrap_or(());
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![
+            vec![
+                (0, program_change(0, 73)),
+                (0, note_on(0, 69, 100)),
+                (q, note_off(0, 69)),
+                (q, note_on(0, 71, 100)),
+                (q, note_off(0, 71)),
+            ],
+            vec![
+                (0, program_change(1, 40)),
+                (q, note_on(1, 76, 100)),
+                (q, note_off(1, 76)),
+                (q, note_on(1, 77, 100)),
+                (q, note_off(1, 77)),
+            ],
+        ]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            program_filter: Some(vec![73]),
+            ..Default::default()
+        };
 
-    fn approx_eq(a: f64, b: f64) -> bool {
-        (a - b).abs() <= EPSILON_MS
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![69, 71]);
+
+        let unfiltered = import_bytes(&bytes, &ImportOptions::default()).unwrap();
+        assert_eq!(unfiltered.events.len(), 4);
     }
 
-    fn create_event(midi: u8, velocity: u8, start: f64, dur: f64) -> Event {
-        Event {
-            note: Note { midi, velocity },
-            time_ms: start,
-            duration_ms: dur,
-        }
+    #[test]
+    fn dedupe_stacked_note_ons() {
+        env_logger::try_init().unwrap_or(());
+
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (q / 2, note_on(0, 69, 100)),
+            (q / 2, note_off(0, 69)),
+            (q, note_off(0, 69)),
+        ]]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            dedupe_note_ons: true,
+            ..Default::default()
+        };
+
+        let (song, diagnostics) =
+            midi_bytes_to_song_with_diagnostics(&bytes, Path::new("synthetic.mid"), &options)
+                .expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 1);
+        assert!(approx_eq(song.events[0].time_ms, 0.0));
+        assert!(approx_eq(song.events[0].duration_ms, 1000.0));
+
+        // Nothing was left overlapping, so there was nothing to warn about reducing.
+        assert_eq!(diagnostics.max_concurrent_notes, 1);
+        assert_eq!(diagnostics.polyphonic_fraction, 0.0);
+
+        let stacked = ImportOptions {
+            dedupe_note_ons: false,
+            ..options
+        };
+        let (_, diagnostics) =
+            midi_bytes_to_song_with_diagnostics(&bytes, Path::new("synthetic.mid"), &stacked)
+                .expect("Synthetic MIDI should import..!");
+        assert_eq!(diagnostics.max_concurrent_notes, 2);
     }
 
     #[test]
-    fn midi_file_import() {
+    fn lenient_noteoff_on_another_channel() {
         env_logger::try_init().unwrap_or(());
 
-        let song = import_midi_file(
-            "./resources/songs/Twinkle_Twinkle_Little_Star.mid",
-            0,
-            PolyPolicy::Highest,
-            false,
-            Some((69, 93)),
-        );
+        // A4 opens on channels 0 & 2, then is released on channels 1 & 2.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (q / 2, note_on(2, 69, 100)),
+            (q / 2, note_off(1, 69)),
+            (q / 2, note_off(2, 69)),
+        ]]);
+
+        let options = ImportOptions {
+            reduce: false,
+            ..Default::default()
+        };
+        let notes = |options: &ImportOptions| {
+            import_bytes(&bytes, options)
+                .expect("Synthetic MIDI should import..!")
+                .events
+                .iter()
+                .map(|e| (e.time_ms, e.duration_ms))
+                .collect::<Vec<_>>()
+        };
 
-        if song.is_err() {
-            warn!("{:?}", song);
-        }
+        // The stray NoteOff is ignored, leaving channel 0's note open until the end.
+        assert_eq!(notes(&options), vec![(0.0, 750.0), (250.0, 500.0)]);
 
-        assert!(song.is_ok());
-        assert_eq!(song.unwrap().events.len(), 42);
+        // Leniently, it closes the oldest open A4 instead, on channel 0.
+        let lenient = ImportOptions {
+            lenient_channel_matching: true,
+            ..options
+        };
+        assert_eq!(notes(&lenient), vec![(0.0, 500.0), (250.0, 500.0)]);
     }
 
     #[test]
-    fn midi_semitone_transpose() {
+    fn fill_gap_with_rest() {
         env_logger::try_init().unwrap_or(());
 
-        let transpose = 2;
-        let song_default = import_midi_file(
-            "./resources/songs/Twinkle_Twinkle_Little_Star.mid",
-            0,
-            PolyPolicy::Highest,
-            false,
-            Some((69, 93)),
+        // A4 & B4 with a quarter rest between them.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (q, note_off(0, 69)),
+            (q, note_on(0, 71, 100)),
+            (q, note_off(0, 71)),
+        ]]);
+
+        let notes = |options: &ImportOptions| {
+            import_bytes(&bytes, options)
+                .expect("Synthetic MIDI should import..!")
+                .events
+                .iter()
+                .map(|e| (e.is_rest(), e.time_ms, e.duration_ms))
+                .collect::<Vec<_>>()
+        };
+
+        let options = ImportOptions::default();
+        assert_eq!(
+            notes(&options),
+            vec![(false, 0.0, 500.0), (false, 1000.0, 500.0)]
         );
-        let song_transposed = import_midi_file(
-            "./resources/songs/Twinkle_Twinkle_Little_Star.mid",
-            transpose,
-            PolyPolicy::Highest,
-            false,
-            Some((69, 93)),
+
+        let with_rests = ImportOptions {
+            insert_rests: true,
+            ..options
+        };
+        assert_eq!(
+            notes(&with_rests),
+            vec![
+                (false, 0.0, 500.0),
+                (true, 500.0, 500.0),
+                (false, 1000.0, 500.0)
+            ]
         );
+    }
 
-        if song_default.is_err() {
-            warn!("{:?}", song_default);
-        }
-        if song_transposed.is_err() {
-            warn!("{:?}", song_transposed);
+    #[test]
+    fn cap_autoclosed_note() {
+        env_logger::try_init().unwrap_or(());
+
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let mut track = vec![(0, note_on(0, 69, 100))];
+        for _ in 0..16 {
+            track.push((q, note_on(0, 81, 100)));
+            track.push((q, note_off(0, 81)));
         }
+        let bytes = build_smf(vec![track]);
 
-        assert!(song_default.is_ok());
-        assert!(song_transposed.is_ok());
-        let events_default = song_default.unwrap().events;
-        let events_transposed = song_transposed.unwrap().events;
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            policy: PolyPolicy::Lowest,
+            ..Default::default()
+        };
 
-        assert_eq!(events_default.len(), 42);
-        assert_eq!(events_transposed.len(), 42);
+        // Left unclosed, the first note drowns out everything after it.
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 1);
+        assert!(approx_eq(song.events[0].duration_ms, 16_000.0));
 
-        assert_eq!(events_default.first().unwrap().note.midi, 69);
-        assert_eq!(events_default.last().unwrap().note.midi, 69);
+        let capped = ImportOptions {
+            max_autoclose_ms: Some(500.0),
+            ..options
+        };
 
-        assert_eq!(events_transposed.first().unwrap().note.midi, 71);
-        assert_eq!(events_transposed.last().unwrap().note.midi, 71);
+        let song = import_bytes(&bytes, &capped).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events[0].note.midi, 69);
+        assert!(approx_eq(song.events[0].duration_ms, 500.0));
+        assert_eq!(song.events.len(), 17);
     }
 
     #[test]
-    fn midi_octave_transpose() {
+    fn tempo_override_without_tempo_metas() {
         env_logger::try_init().unwrap_or(());
 
-        let range = 45..=69;
-        let transpose = (*range.start(), *range.end());
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (0, note_on(0, 69, 100)),
+            (q, note_off(0, 69)),
+            (q, note_on(0, 71, 100)),
+            (q, note_off(0, 71)),
+        ]]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            tempo_override_bpm: Some(150.0),
+            ..Default::default()
+        };
 
-        let song = import_midi_file(
-            "./resources/songs/Twinkle_Twinkle_Little_Star.mid",
-            0,
-            PolyPolicy::Highest,
-            false,
-            Some(transpose),
-        );
+        // A quarter note lasts 400ms at 150 BPM.
+        let song = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 2);
+        assert!(approx_eq(song.events[0].duration_ms, 400.0));
+        assert!(approx_eq(song.events[1].time_ms, 800.0));
+        assert!(approx_eq(song.events[1].duration_ms, 400.0));
+        assert!(approx_eq(song.metadata.tempo_bpm.unwrap(), 150.0));
+
+        let invalid = ImportOptions {
+            tempo_override_bpm: Some(0.0),
+            ..options
+        };
+        assert!(import_bytes(&bytes, &invalid).is_err());
+    }
 
-        if song.is_err() {
-            warn!("{:?}", song);
-        }
+    #[test]
+    fn import_without_reducing() {
+        env_logger::try_init().unwrap_or(());
 
-        assert!(song.is_ok());
-        let events = song.unwrap().events;
+        // A held A major chord, then a lone B.
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![
+            vec![(0, note_on(0, 69, 100)), (q, note_off(0, 69))],
+            vec![(0, note_on(1, 76, 100)), (q, note_off(1, 76))],
+            vec![
+                (0, note_on(2, 73, 100)),
+                (q, note_off(2, 73)),
+                (0, note_on(2, 71, 100)),
+                (q, note_off(2, 71)),
+            ],
+        ]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            ..Default::default()
+        };
+        let unreduced = ImportOptions {
+            reduce: false,
+            ..options.clone()
+        };
 
-        assert_eq!(events.len(), 42);
-        assert!(
-            events
+        let notes = |song: Song| {
+            song.events
                 .iter()
-                .map(|e| e.note.midi)
-                .all(|midi| range.contains(&midi))
+                .map(|e| (e.note.midi, e.time_ms))
+                .collect::<Vec<_>>()
+        };
+
+        let reduced = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(notes(reduced), vec![(76, 0.0), (71, 500.0)]);
+
+        let song = import_bytes(&bytes, &unreduced).expect("Synthetic MIDI should import..!");
+        assert_eq!(
+            notes(song),
+            vec![(69, 0.0), (73, 0.0), (76, 0.0), (71, 500.0)]
         );
     }
 
     #[test]
-    fn highest_policy_overlap() {
+    fn starting_tempo_replaces_default() {
         env_logger::try_init().unwrap_or(());
 
-        let input = vec![
-            create_event(69, 255, 0.0, 1000.0),
-            create_event(77, 255, 500.0, 1000.0),
-        ];
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let tempo = |bpm: f64| {
+            TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+                (MICROSECONDS_PER_MINUTE / bpm).round() as u32,
+            )))
+        };
+        let notes = |tempo_tick: u32| {
+            build_smf(vec![vec![
+                (0, note_on(0, 69, 100)),
+                (tempo_tick, tempo(90.0)),
+                (q - tempo_tick, note_off(0, 69)),
+                (0, note_on(0, 71, 100)),
+                (q, note_off(0, 71)),
+            ]])
+        };
 
-        let out = reduce_to_monophonic(input, PolyPolicy::Highest, false);
-        assert_eq!(out.len(), 2);
+        // A quarter note lasts 666.67ms at 90 BPM.
+        let song = import_bytes(&notes(0), &ImportOptions::default())
+            .expect("Synthetic MIDI should import..!");
+        assert!(approx_eq(song.metadata.tempo_bpm.unwrap(), 90.0));
+        assert!(approx_eq(song.events[1].time_ms, 60_000.0 / 90.0));
+
+        // Set halfway through the first note, the file starts at the default tempo instead.
+        let late = notes(q / 2);
+        let song = import_bytes(&late, &ImportOptions::default())
+            .expect("Synthetic MIDI should import..!");
+        assert_eq!(song.metadata.tempo_bpm, Some(120.0));
+        assert!(approx_eq(song.events[1].time_ms, 250.0 + 30_000.0 / 90.0));
+
+        let options = ImportOptions {
+            default_tempo_bpm: Some(60.0),
+            ..Default::default()
+        };
+        let song = import_bytes(&late, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.metadata.tempo_bpm, Some(60.0));
+        assert!(approx_eq(song.events[1].time_ms, 500.0 + 30_000.0 / 90.0));
+    }
 
-        assert_eq!(out[0].note.midi, 69);
-        assert!(approx_eq(out[0].time_ms, 0.0));
-        assert!(approx_eq(out[0].duration_ms, 500.0));
+    #[test]
+    fn single_tempo_reports_its_bpm() {
+        env_logger::try_init().unwrap_or(());
 
-        assert_eq!(out[1].note.midi, 77);
-        assert!(approx_eq(out[1].time_ms, 500.0));
-        assert!(approx_eq(out[1].duration_ms, 1000.0));
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![vec![
+            (
+                0,
+                TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+                    (MICROSECONDS_PER_MINUTE / 140.0).round() as u32,
+                ))),
+            ),
+            (0, note_on(0, 69, 100)),
+            (q, note_off(0, 69)),
+        ]]);
+
+        let song = import_bytes(&bytes, &ImportOptions::default())
+            .expect("Synthetic MIDI should import..!");
+        assert!(approx_eq(song.metadata.tempo_bpm.unwrap(), 140.0));
+        assert!(approx_eq(song.events[0].duration_ms, 60_000.0 / 140.0));
     }
 
     #[test]
-    fn lowest_policy_overlap() {
+    fn import_smpte_timecode() {
         env_logger::try_init().unwrap_or(());
 
-        let input = vec![
-            create_event(77, 255, 0.0, 1000.0),
-            create_event(69, 255, 500.0, 1000.0),
-        ];
+        // 25 fps with 40 subframes each counts a tick every millisecond, whatever the tempo says.
+        let track = [69, 71, 73, 74]
+            .into_iter()
+            .flat_map(|key| [(0, note_on(0, key, 100)), (500, note_off(0, key))])
+            .collect::<Vec<_>>();
+        let slow = (
+            0,
+            TrackEventKind::Meta(MetaMessage::Tempo(u24::new(1_000_000))),
+        );
+        let bytes = build_smf_with_timing(
+            Timing::Timecode(midly::Fps::Fps25, 40),
+            vec![std::iter::once(slow).chain(track).collect()],
+        );
 
-        let out = reduce_to_monophonic(input, PolyPolicy::Lowest, false);
-        assert_eq!(out.len(), 2);
+        let song = import_bytes(&bytes, &ImportOptions::default())
+            .expect("Timecode MIDI should import..!");
+        assert_eq!(song.events.len(), 4);
 
-        assert_eq!(out[0].note.midi, 77);
-        assert!(approx_eq(out[0].time_ms, 0.0));
-        assert!(approx_eq(out[0].duration_ms, 500.0));
+        let first = &song.events[0];
+        assert!(approx_eq(first.time_ms, 0.0));
+        assert!(approx_eq(first.duration_ms, 500.0));
 
-        assert_eq!(out[1].note.midi, 69);
-        assert!(approx_eq(out[1].time_ms, 500.0));
-        assert!(approx_eq(out[1].duration_ms, 1000.0));
+        let last = song.events.last().unwrap();
+        assert_eq!(last.note.midi, 74);
+        assert!(approx_eq(last.time_ms, 1500.0));
+        assert!(approx_eq(last.time_ms + last.duration_ms, 2000.0));
     }
 
     #[test]
-    fn loudest_policy_overlap() {
+    fn ignore_tiny_tempo_changes() {
         env_logger::try_init().unwrap_or(());
 
-        let input = vec![
-            create_event(77, 128, 0.0, 1000.0),
-            create_event(69, 255, 500.0, 1000.0),
-        ];
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let tempo = |bpm: f64| {
+            TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+                (MICROSECONDS_PER_MINUTE / bpm).round() as u32,
+            )))
+        };
 
-        let out = reduce_to_monophonic(input, PolyPolicy::Loudest, false);
-        assert_eq!(out.len(), 2);
+        // Eight quarter notes at 120bpm nudged a couple BPM either way before each one after the
+        // first, then a real change down to 60bpm for a ninth.
+        let mut track = vec![(0, note_on(0, 69, 100)), (q, note_off(0, 69))];
+        for i in 1..8 {
+            let bpm = if i % 2 == 0 { 118.0 } else { 122.0 };
+            let key = 69 + i as u8;
+            track.extend([
+                (0, tempo(bpm)),
+                (0, note_on(0, key, 100)),
+                (q, note_off(0, key)),
+            ]);
+        }
+        track.extend([
+            (0, tempo(60.0)),
+            (0, note_on(0, 81, 100)),
+            (q, note_off(0, 81)),
+        ]);
+        let bytes = build_smf(vec![track]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            ..Default::default()
+        };
+        let smoothed = ImportOptions {
+            tempo_change_threshold_bpm: Some(3.0),
+            ..options.clone()
+        };
 
-        assert_eq!(out[0].note.midi, 77);
-        assert!(approx_eq(out[0].time_ms, 0.0));
-        assert!(approx_eq(out[0].duration_ms, 500.0));
+        let jittery = import_bytes(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert!(
+            jittery
+                .events
+                .iter()
+                .enumerate()
+                .any(|(i, e)| !approx_eq(e.time_ms, i as f64 * 500.0))
+        );
 
-        assert_eq!(out[1].note.midi, 69);
-        assert!(approx_eq(out[1].time_ms, 500.0));
-        assert!(approx_eq(out[1].duration_ms, 1000.0));
+        let song = import_bytes(&bytes, &smoothed).expect("Synthetic MIDI should import..!");
+        assert_eq!(song.events.len(), 9);
+        for (i, event) in song.events[..8].iter().enumerate() {
+            assert!(approx_eq(event.time_ms, i as f64 * 500.0));
+            assert!(approx_eq(event.duration_ms, 500.0));
+        }
+        assert!(approx_eq(song.events[8].time_ms, 4000.0));
+        assert!(approx_eq(song.events[8].duration_ms, 1000.0));
     }
 
     #[test]
-    fn densest_policy_overlap() {
-        todo!("Take events exclusively from the midi track with the highest note density.")
+    fn split_songs_per_channel() {
+        env_logger::try_init().unwrap_or(());
+
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![
+            vec![
+                (0, note_on(0, 69, 100)),
+                (q, note_off(0, 69)),
+                (0, note_on(0, 71, 100)),
+                (q, note_off(0, 71)),
+                (0, note_on(0, 73, 100)),
+                (q, note_off(0, 73)),
+            ],
+            vec![
+                // Overlaps channel 0 entirely, so a single song would lose one of the lines.
+                (0, note_on(3, 81, 100)),
+                (q, note_off(3, 81)),
+                (0, note_on(3, 83, 100)),
+                (q, note_off(3, 83)),
+            ],
+        ]);
+
+        let options = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            ..Default::default()
+        };
+
+        let songs = import_per_channel(&bytes, &options).expect("Synthetic MIDI should import..!");
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[&0].events.len(), 3);
+        assert_eq!(songs[&3].events.len(), 2);
+        assert!(songs[&3].events.iter().all(|e| e.note.midi >= 81));
+    }
+
+    #[test]
+    fn merge_or_split_tracks() {
+        env_logger::try_init().unwrap_or(());
+
+        let q = TEST_TICKS_PER_QUARTER as u32;
+        let bytes = build_smf(vec![
+            vec![
+                (0, note_on(0, 81, 100)),
+                (2 * q, note_off(0, 81)),
+                (0, note_on(0, 83, 100)),
+                (2 * q, note_off(0, 83)),
+            ],
+            vec![
+                (0, note_on(1, 69, 100)),
+                (q, note_off(1, 69)),
+                (0, note_on(1, 71, 100)),
+                (q, note_off(1, 71)),
+                (0, note_on(1, 72, 100)),
+                (q, note_off(1, 72)),
+                (0, note_on(1, 74, 100)),
+                (q, note_off(1, 74)),
+            ],
+        ]);
+
+        let merged = ImportOptions {
+            clip_to_range: Some((69, 93)),
+            ..Default::default()
+        };
+
+        // Combined, the sparser but higher track wins every overlap.
+        let song = import_bytes(&bytes, &merged).expect("Synthetic MIDI should import..!");
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![81, 83]);
+
+        let split = ImportOptions {
+            merge_tracks: false,
+            ..merged
+        };
+
+        // Reduced apart, only the denser second track is kept.
+        let song = import_bytes(&bytes, &split).expect("Synthetic MIDI should import..!");
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        assert_eq!(pitches, vec![69, 71, 72, 74]);
+        assert!(approx_eq(song.events[3].time_ms, 1500.0));
     }
 
     #[test]
@@ -693,6 +3633,40 @@ mod test {
         assert!((out[0].duration_ms - 1001.0).abs() <= EPSILON_MS);
     }
 
+    #[test]
+    fn separate_same_pitch_without_merge() {
+        env_logger::try_init().unwrap_or(());
+
+        let input = vec![
+            create_event(72, 255, 0.0, 500.0),
+            create_event(72, 255, 501.0, 500.0),
+        ];
+
+        let out = reduce_to_monophonic(input, PolyPolicy::Highest, false);
+        assert_eq!(out.len(), 2);
+
+        let gap_ms = out[1].time_ms - (out[0].time_ms + out[0].duration_ms);
+        assert!(gap_ms >= EPSILON_MS);
+        assert!(approx_eq(out[1].time_ms, 501.0));
+        assert!(approx_eq(out[1].duration_ms, 500.0));
+    }
+
+    #[test]
+    fn cull_same_pitch_trimmed_below_epsilon() {
+        env_logger::try_init().unwrap_or(());
+
+        // Making room for the second note leaves the first one only 1.5ms long.
+        let input = vec![
+            create_event(72, 255, 0.0, 3.0),
+            create_event(72, 255, 3.5, 500.0),
+        ];
+
+        let out = reduce_to_monophonic(input, PolyPolicy::Highest, false);
+        assert_eq!(out.len(), 1);
+        assert!(approx_eq(out[0].time_ms, 3.5));
+        assert!(out.iter().all(|e| e.duration_ms >= EPSILON_MS));
+    }
+
     #[test]
     fn cull_insufficient_length() {
         env_logger::try_init().unwrap_or(());
@@ -0,0 +1,50 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// A stretch of the song played with its own articulation instead of the engine's.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ArticulationRange {
+    pub start_ms: f64,
+    pub end_ms: f64,
+
+    /// How much of each note to hold, like the engine's articulation.
+    pub factor: f64,
+}
+
+/// Articulations for named sections of a song, e.g. staccato for the intro & tenuto for the chorus.
+/// Events outside every range keep the engine's articulation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArticulationSchedule {
+    pub ranges: Vec<ArticulationRange>,
+}
+
+impl ArticulationSchedule {
+    pub fn new(ranges: Vec<ArticulationRange>) -> Self {
+        Self { ranges }
+    }
+
+    /// The articulation for an event starting at `time_ms`, from the last range covering it.
+    /// Ranges include their start but not their end.
+    pub fn factor_at(&self, time_ms: f64) -> Option<f64> {
+        self.ranges
+            .iter()
+            .rfind(|range| range.start_ms <= time_ms && time_ms < range.end_ms)
+            .map(|range| range.factor.clamp(0.0, 1.0))
+    }
+}
+
+/// Reads an articulation schedule from a JSON file like
+/// `{ "ranges": [{ "start_ms": 0.0, "end_ms": 8000.0, "factor": 0.5 }] }`.
+pub fn load_articulation_schedule<P: AsRef<Path>>(path: P) -> Result<ArticulationSchedule> {
+    let json = fs::read_to_string(path.as_ref()).map_err(|e| {
+        anyhow!(
+            "Failed to read articulation schedule from {}: {}",
+            path.as_ref().display(),
+            e
+        )
+    })?;
+
+    Ok(serde_json::from_str(&json)?)
+}
@@ -1,3 +1,4 @@
+use crate::engine::can_poll_keys;
 use clap::Parser;
 use std::path::PathBuf;
 
@@ -7,13 +8,30 @@ use std::path::PathBuf;
     about = "Play a MIDI file on the Animal Well flute!"
 )]
 pub struct Args {
-    /// Path to the target MIDI file.
-    pub midi: PathBuf,
+    /// Path to the target MIDI file, or `-` to read it from stdin. Several files are played back to back as a playlist.
+    #[arg(required_unless_present_any = ["list_articulations", "show_bindings", "test_scale", "replay"])]
+    pub midi: Vec<PathBuf>,
 
     /// Transpose in semitones (positive or negative).
     #[arg(short, long, default_value_t = 0)]
     pub transpose: i32,
 
+    /// Play the song at this BPM, ignoring any tempo changes in the MIDI file.
+    #[arg(long)]
+    pub tempo: Option<f64>,
+
+    /// The BPM to assume until the MIDI file's first tempo change, 120 if omitted.
+    #[arg(long = "default-tempo")]
+    pub default_tempo: Option<f64>,
+
+    /// Ignores tempo changes within this many BPM of the current tempo, to smooth out files with lots of tiny ones.
+    #[arg(long = "tempo-threshold")]
+    pub tempo_threshold: Option<f64>,
+
+    /// A JSON file of `{ "ranges": [{ "start_ms", "end_ms", "factor" }] }` giving sections of the song their own articulation.
+    #[arg(long = "articulation-schedule")]
+    pub articulation_schedule: Option<PathBuf>,
+
     /// The articulation style to use for the song. Supports presets Tenuto|Portato|Staccato|Staccatissimo.
     /// Pass 'Custom' along with the flag `--hold-percentage <0.0..=1.0>` to use a custom value.
     #[arg(short, long, default_value = "portato")]
@@ -23,14 +41,80 @@ pub struct Args {
     #[arg(long = "hold-percentage")]
     pub custom_articulation: Option<f64>,
 
+    /// Print the articulation presets & how much of each note they hold for, then exit.
+    #[arg(long = "list-articulations", default_value_t = false)]
+    pub list_articulations: bool,
+
+    /// Print the playback controls & the keys they're bound to, then exit.
+    #[arg(long = "show-bindings", default_value_t = false)]
+    pub show_bindings: bool,
+
+    /// Plays every mapped note up & then back down in game, holding each for 250ms (or `--test-scale=<ms>`), to check the key mappings.
+    #[arg(long = "test-scale", num_args = 0..=1, require_equals = true, default_missing_value = "250")]
+    pub test_scale: Option<f64>,
+
+    /// How many times `--test-scale` plays the scale.
+    #[arg(long = "test-scale-repeats", default_value_t = 1)]
+    pub test_scale_repeats: usize,
+
+    /// Replays a CSV written by `--record`, pressing each note when it was actually sent during that run.
+    #[arg(long = "replay")]
+    pub replay: Option<PathBuf>,
+
     /// Dry run (print first dry_run_max events and exit).
     #[arg(short, long, default_value_t = false)]
     pub dry_run: bool,
 
+    /// Dry run like `--dry-run`, failing with a non-zero exit status if any event has no keys to press.
+    #[arg(long = "strict-dry-run", default_value_t = false)]
+    pub strict_dry_run: bool,
+
+    /// Print the key batches & sleeps sent for the first dry_run_max notes without sending them, then exit.
+    #[arg(long = "preview-keys", default_value_t = false)]
+    pub preview_keys: bool,
+
+    /// Print how many notes fall below, within & above the flute's range (before any octave folding) and exit.
+    #[arg(long, default_value_t = false)]
+    pub analyze: bool,
+
+    /// Print how many notes each transpose from -12 to +12 semitones keeps within the flute's range and exit.
+    #[arg(long = "transpose-report", default_value_t = false)]
+    pub transpose_report: bool,
+
+    /// Prints the MIDI file's header & a summary of each track without importing it, then exits.
+    #[arg(long = "midi-info", visible_alias = "info", default_value_t = false)]
+    pub midi_info: bool,
+
+    /// Writes each MIDI channel as its own reduced song to `<dir>/channel_N.json` and exits.
+    #[arg(long = "split-export")]
+    pub split_export: Option<PathBuf>,
+
+    /// Prints the reduced song as a tab to play by hand (direction, modifiers & beats per note), then exits.
+    #[arg(long, default_value_t = false)]
+    pub tab: bool,
+
+    /// Writes an AutoHotkey script that plays the imported song to this path and exits.
+    #[arg(long = "export-ahk")]
+    pub export_ahk: Option<PathBuf>,
+
+    /// Plays the reduced song through the system audio instead of the game, then exits.
+    #[cfg(feature = "audio")]
+    #[arg(long = "preview-audio", default_value_t = false)]
+    pub preview_audio: bool,
+
     /// Maximum events to print in dry run.
     #[arg(long, default_value_t = 80)]
     pub dry_run_max: usize,
 
+    /// How log lines are printed: text|json (one JSON object per line, for tools reading the output).
+    #[arg(long = "log-format", default_value = "text")]
+    pub log_format: String,
+
+    /// Snaps every note to the nearest pitch in this scale, given as a root & name like `a-minor-pentatonic`
+    /// or `c-major`, or as a list of pitch classes from 0 (C) to 11 (B) like `0,2,4,7,9`.
+    #[arg(long)]
+    pub scale: Option<String>,
+
     /// Polyphony reduction policy: highest|lowest|loudest|first|last.
     #[arg(short, long, default_value = "highest")]
     pub policy: String,
@@ -43,7 +127,295 @@ pub struct Args {
     #[arg(long = "delay-start", default_value_t = 0)]
     pub delay_start: u64,
 
+    /// Picks the transpose that fits the most notes in range while toggling the octave/semitone modifiers the least.
+    /// Overrides `--transpose`.
+    #[arg(long = "optimize-transpose", default_value_t = false)]
+    pub optimize_transpose: bool,
+
+    /// Taps the play key once on its own before the first note, in case the game misses the first input.
+    #[arg(long, default_value_t = false)]
+    pub warmup: bool,
+
+    /// Only re-checks that ANIMAL WELL still has focus once this many milliseconds have passed since the last check.
+    #[arg(long = "window-check-interval", default_value_t = 0)]
+    pub window_check_interval: u64,
+
+    /// Only plays while the window of this process ID has focus, to pick one of several running instances of the game.
+    #[arg(long = "window-pid")]
+    pub window_pid: Option<u32>,
+
+    /// Holds the song's last note like a fermata until Ctrl-C is pressed, instead of releasing it after its duration.
+    #[arg(long = "hold-final", default_value_t = false)]
+    pub hold_final: bool,
+
+    /// Writes the scheduled vs actual emission time of every played note to this file when playback ends.
+    #[arg(long = "record")]
+    pub record: Option<PathBuf>,
+
+    /// Appends a progress checkpoint like `120/450 events, 00:02:15 elapsed` to this file during playback.
+    #[arg(long = "progress-file")]
+    pub progress_file: Option<PathBuf>,
+
+    /// How many seconds apart `--progress-file` checkpoints are written.
+    #[arg(long = "progress-interval", default_value_t = 5.0)]
+    pub progress_interval: f64,
+
+    /// Re-triggers the play key every N milliseconds while a long note is held, to keep it sounding.
+    #[arg(long = "max-sustain")]
+    pub max_sustain: Option<f64>,
+
+    /// The key the game's "play note" control is bound to, e.g. `numpad5` (the default), `space` or `z`.
+    #[arg(long = "play-key")]
+    pub play_key: Option<String>,
+
+    /// Starts each key press this many ms early so the play key lands on the note's time (1ms for the
+    /// note keys to settle, or `--play-key-offset=<ms>` to add any SendInput latency on top).
+    #[arg(long = "play-key-offset", num_args = 0..=1, require_equals = true, default_missing_value = "1")]
+    pub play_key_offset: Option<f64>,
+
+    /// How long (in ms) the game takes to sound a note after the play key goes down on this system, to press every note that much early.
+    #[arg(long = "key-latency")]
+    pub key_latency: Option<f64>,
+
+    /// A key to tap along with during playback, e.g. `t`, to set the tempo for the rest of the song like a conductor.
+    #[arg(long = "tap-key")]
+    pub tap_key: Option<String>,
+
+    /// A key that releases every key & stops playback at once, e.g. `p` if the song goes wrong in game. Off by default, since the game reads the keyboard too.
+    #[arg(long = "panic-key")]
+    pub panic_key: Option<String>,
+
+    /// A key that pauses playback, releasing every key, & resumes it when pressed again, e.g. `o`. Off by default, since the game reads the keyboard too.
+    #[arg(long = "pause-key")]
+    pub pause_key: Option<String>,
+
+    /// Leaves the playback thread at its normal priority, so it doesn't starve other processes, e.g. while streaming.
+    #[arg(long = "no-priority-boost", default_value_t = false)]
+    pub no_priority_boost: bool,
+
+    /// Plays every note for its full hold even when running late, instead of shortening holds to catch back up with the song.
+    #[arg(long = "no-catch-up", default_value_t = false)]
+    pub no_catch_up: bool,
+
+    /// How many ms before each key press or release to stop sleeping & busy-wait instead, for tighter timing (0 only sleeps, at most 20).
+    #[arg(long = "final-spin", default_value_t = 2.0)]
+    pub final_spin: f64,
+
+    /// How the octave & semitone modifiers are entered: hold (held with each note) or tap (toggled on & off, for game versions that latch them).
+    #[arg(long = "modifier-style", default_value = "hold")]
+    pub modifier_style: String,
+
+    /// The key the game's octave modifier is bound to, `1` by default.
+    #[arg(long = "octave-key")]
+    pub octave_key: Option<String>,
+
+    /// The key the game's semitone modifier is bound to, `3` by default.
+    #[arg(long = "semitone-key")]
+    pub semitone_key: Option<String>,
+
+    /// Emulates vibrato on long notes by pulsing the play key N times per second.
+    #[arg(long = "vibrato-rate")]
+    pub vibrato_rate: Option<f64>,
+
+    /// Another MIDI file to merge into the song before reducing it to one line, e.g. a countermelody. Repeatable.
+    #[arg(long = "add")]
+    pub add: Vec<PathBuf>,
+
+    /// Starts the matching `--add` file this many milliseconds into the song. Repeatable, in the same order.
+    #[arg(long = "add-offset")]
+    pub add_offset: Vec<f64>,
+
+    /// Fill melodic leaps of more than N semitones with a quick run of connecting notes, like a glissando.
+    #[arg(long = "gliss")]
+    pub gliss: Option<u8>,
+
+    /// Only import notes played under these (0-based) General MIDI programs, e.g. `73,74` for flute & recorder.
+    #[arg(long, value_delimiter = ',')]
+    pub programs: Option<Vec<u8>>,
+
+    /// Treat a repeated NoteOn for a key that is already sounding on the same channel as part of the same note.
+    #[arg(long = "dedupe-note-ons", default_value_t = false)]
+    pub dedupe_note_ons: bool,
+
+    /// Let a NoteOff close an open note of the same key on another channel when none is open on its own, for files that mix them up.
+    #[arg(long = "lenient-noteoff", default_value_t = false)]
+    pub lenient_noteoff: bool,
+
+    /// Let channel pressure (aftertouch) set the loudness of the notes sounding on its channel, e.g. for the Loudest policy.
+    #[arg(long = "use-aftertouch", default_value_t = false)]
+    pub use_aftertouch: bool,
+
+    /// Multiplies the velocity of every note on a (0-based) MIDI channel, given as `<channel>:<gain>` like `0:1.5`,
+    /// e.g. to let the loudest policy pick a quiet melody channel. Repeatable.
+    #[arg(long = "channel-gain")]
+    pub channel_gain: Vec<String>,
+
+    /// Shorten notes that were released sharply (NoteOff velocity above 64), down to half their value at 127.
+    #[arg(long = "use-release-velocity", default_value_t = false)]
+    pub use_release_velocity: bool,
+
+    /// Roll chords into quick arpeggios when at least this many notes sound at once, instead of reducing them with the policy (0 never rolls).
+    #[arg(long = "arpeggiate-min-notes", default_value_t = 0)]
+    pub arpeggiate_min_notes: usize,
+
+    /// The chance (0 to 1) each note is skipped at random, for a looser, more human rendition.
+    #[arg(long = "drop-prob", default_value_t = 0.0)]
+    pub drop_prob: f64,
+
+    /// Seeds which notes `--drop-prob` skips, so the same seed always skips the same notes.
+    #[arg(long = "drop-seed", default_value_t = 0)]
+    pub drop_seed: u64,
+
+    /// The shortest (in ms) an articulated note is held so the game still registers it, defaulting to the articulation preset's own floor.
+    #[arg(long = "min-hold")]
+    pub min_hold: Option<f64>,
+
+    /// The shortest (in ms) the play key is held for any note, holding even notes shorter than this for it, up to the start of the next note.
+    #[arg(long = "min-playable-hold")]
+    pub min_playable_hold: Option<f64>,
+
+    /// Only import the first N bars of the song, following its time signatures, e.g. to quickly test the opening.
+    #[arg(long = "bars")]
+    pub bars: Option<u32>,
+
+    /// Caps the duration (in ms) of notes whose NoteOff is missing, which otherwise sound until the end of the song.
+    #[arg(long = "max-autoclose")]
+    pub max_autoclose: Option<f64>,
+
+    /// The velocity of notes cut short by the monophonic reduction: onset (as struck), peak (the loudest
+    /// note sounding alongside it) or decay (fading with how long it has been held).
+    #[arg(long = "emit-velocity", default_value = "onset")]
+    pub emit_velocity: String,
+
     /// Whether to merge consecutive midi events for the same pitch when reducing the tracks to monophony.
     #[arg(short, long, default_value_t = false)]
     pub merge_midi: bool,
+
+    /// Fill the gaps between notes with explicit rest events, e.g. for `--dry-run` or a saved song.
+    #[arg(long = "insert-rests", default_value_t = false)]
+    pub insert_rests: bool,
+
+    /// Keep every overlapping note instead of reducing the song to one line. Only works with `--split-export`, `--dry-run` or `--tab`.
+    #[arg(long = "no-reduce", default_value_t = false)]
+    pub no_reduce: bool,
+
+    /// Reduce each midi track to monophony on its own & only play the densest one, instead of combining every track first.
+    #[arg(long = "no-merge-tracks", default_value_t = false)]
+    pub no_merge_tracks: bool,
+}
+
+/// The playback controls & the keys they're bound to, as `(action, key)` pairs. Every hotkey is
+/// listed, as `off` when it isn't bound & `unavailable` where the keyboard can't be read, see
+/// [`can_poll_keys`].
+pub fn active_bindings(args: &Args) -> Vec<(String, String)> {
+    let hotkey = |key: &Option<String>| match key {
+        None => String::from("off"),
+        Some(_) if !can_poll_keys() => String::from("unavailable"),
+        Some(key) => key.to_lowercase(),
+    };
+
+    vec![
+        (String::from("stop"), String::from("ctrl+c")),
+        (String::from("panic"), hotkey(&args.panic_key)),
+        (String::from("pause"), hotkey(&args.pause_key)),
+        (String::from("tap tempo"), hotkey(&args.tap_key)),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_several_midi_paths() {
+        let args = Args::try_parse_from(["FLUTE_WELL", "first.mid", "-", "--transpose", "2"])
+            .expect("Two MIDI paths should parse..!");
+        assert_eq!(
+            args.midi,
+            vec![PathBuf::from("first.mid"), PathBuf::from("-")]
+        );
+        assert_eq!(args.transpose, 2);
+
+        assert!(Args::try_parse_from(["FLUTE_WELL"]).is_err());
+        assert!(
+            Args::try_parse_from(["FLUTE_WELL", "--list-articulations"])
+                .is_ok_and(|args| args.midi.is_empty())
+        );
+    }
+
+    #[test]
+    fn test_scale_leaves_the_midi_path() {
+        let args = Args::try_parse_from(["FLUTE_WELL", "--test-scale", "song.mid"])
+            .expect("--test-scale before a path should parse..!");
+        assert_eq!(args.test_scale, Some(250.0));
+        assert_eq!(args.midi, vec![PathBuf::from("song.mid")]);
+
+        let args = Args::try_parse_from(["FLUTE_WELL", "--test-scale=400"])
+            .expect("--test-scale with a hold should parse..!");
+        assert_eq!(args.test_scale, Some(400.0));
+    }
+
+    #[test]
+    fn play_key_offset_leaves_the_midi_path() {
+        let args = Args::try_parse_from(["FLUTE_WELL", "--play-key-offset", "song.mid"])
+            .expect("--play-key-offset before a path should parse..!");
+        assert_eq!(args.play_key_offset, Some(1.0));
+        assert_eq!(args.midi, vec![PathBuf::from("song.mid")]);
+
+        let args = Args::try_parse_from(["FLUTE_WELL", "--play-key-offset=3.5", "song.mid"])
+            .expect("--play-key-offset with an offset should parse..!");
+        assert_eq!(args.play_key_offset, Some(3.5));
+    }
+
+    #[test]
+    fn bindings_follow_the_flags() {
+        let binding = |action: &str, key: &str| (action.to_string(), key.to_string());
+
+        let args = Args::try_parse_from(["FLUTE_WELL", "--show-bindings"])
+            .expect("--show-bindings alone should parse..!");
+        assert_eq!(
+            active_bindings(&args),
+            vec![
+                binding("stop", "ctrl+c"),
+                binding("panic", "off"),
+                binding("pause", "off"),
+                binding("tap tempo", "off"),
+            ]
+        );
+
+        let args = Args::try_parse_from([
+            "FLUTE_WELL",
+            "--show-bindings",
+            "--panic-key",
+            "Enter",
+            "--tap-key",
+            "t",
+            "--pause-key",
+            "O",
+        ])
+        .expect("Custom bindings should parse..!");
+        let bindings = active_bindings(&args);
+        assert_eq!(bindings[0], binding("stop", "ctrl+c"));
+
+        #[cfg(target_os = "windows")]
+        assert_eq!(
+            bindings[1..],
+            [
+                binding("panic", "enter"),
+                binding("pause", "o"),
+                binding("tap tempo", "t"),
+            ]
+        );
+
+        // Hotkeys that could never fire are shown as such.
+        #[cfg(not(target_os = "windows"))]
+        assert_eq!(
+            bindings[1..],
+            [
+                binding("panic", "unavailable"),
+                binding("pause", "unavailable"),
+                binding("tap tempo", "unavailable"),
+            ]
+        );
+    }
 }
@@ -0,0 +1,59 @@
+//! Stand-ins for the Windows virtual-key codes the mappings are written in, so the mappings (and
+//! everything built on them) compile on other platforms too. The codes match Windows' own.
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct VIRTUAL_KEY(pub u16);
+
+pub const VK_RETURN: VIRTUAL_KEY = VIRTUAL_KEY(0x0D);
+pub const VK_ESCAPE: VIRTUAL_KEY = VIRTUAL_KEY(0x1B);
+pub const VK_SPACE: VIRTUAL_KEY = VIRTUAL_KEY(0x20);
+
+pub const VK_0: VIRTUAL_KEY = VIRTUAL_KEY(0x30);
+pub const VK_1: VIRTUAL_KEY = VIRTUAL_KEY(0x31);
+pub const VK_2: VIRTUAL_KEY = VIRTUAL_KEY(0x32);
+pub const VK_3: VIRTUAL_KEY = VIRTUAL_KEY(0x33);
+pub const VK_4: VIRTUAL_KEY = VIRTUAL_KEY(0x34);
+pub const VK_5: VIRTUAL_KEY = VIRTUAL_KEY(0x35);
+pub const VK_6: VIRTUAL_KEY = VIRTUAL_KEY(0x36);
+pub const VK_7: VIRTUAL_KEY = VIRTUAL_KEY(0x37);
+pub const VK_8: VIRTUAL_KEY = VIRTUAL_KEY(0x38);
+pub const VK_9: VIRTUAL_KEY = VIRTUAL_KEY(0x39);
+
+pub const VK_A: VIRTUAL_KEY = VIRTUAL_KEY(0x41);
+pub const VK_B: VIRTUAL_KEY = VIRTUAL_KEY(0x42);
+pub const VK_C: VIRTUAL_KEY = VIRTUAL_KEY(0x43);
+pub const VK_D: VIRTUAL_KEY = VIRTUAL_KEY(0x44);
+pub const VK_E: VIRTUAL_KEY = VIRTUAL_KEY(0x45);
+pub const VK_F: VIRTUAL_KEY = VIRTUAL_KEY(0x46);
+pub const VK_G: VIRTUAL_KEY = VIRTUAL_KEY(0x47);
+pub const VK_H: VIRTUAL_KEY = VIRTUAL_KEY(0x48);
+pub const VK_I: VIRTUAL_KEY = VIRTUAL_KEY(0x49);
+pub const VK_J: VIRTUAL_KEY = VIRTUAL_KEY(0x4A);
+pub const VK_K: VIRTUAL_KEY = VIRTUAL_KEY(0x4B);
+pub const VK_L: VIRTUAL_KEY = VIRTUAL_KEY(0x4C);
+pub const VK_M: VIRTUAL_KEY = VIRTUAL_KEY(0x4D);
+pub const VK_N: VIRTUAL_KEY = VIRTUAL_KEY(0x4E);
+pub const VK_O: VIRTUAL_KEY = VIRTUAL_KEY(0x4F);
+pub const VK_P: VIRTUAL_KEY = VIRTUAL_KEY(0x50);
+pub const VK_Q: VIRTUAL_KEY = VIRTUAL_KEY(0x51);
+pub const VK_R: VIRTUAL_KEY = VIRTUAL_KEY(0x52);
+pub const VK_S: VIRTUAL_KEY = VIRTUAL_KEY(0x53);
+pub const VK_T: VIRTUAL_KEY = VIRTUAL_KEY(0x54);
+pub const VK_U: VIRTUAL_KEY = VIRTUAL_KEY(0x55);
+pub const VK_V: VIRTUAL_KEY = VIRTUAL_KEY(0x56);
+pub const VK_W: VIRTUAL_KEY = VIRTUAL_KEY(0x57);
+pub const VK_X: VIRTUAL_KEY = VIRTUAL_KEY(0x58);
+pub const VK_Y: VIRTUAL_KEY = VIRTUAL_KEY(0x59);
+pub const VK_Z: VIRTUAL_KEY = VIRTUAL_KEY(0x5A);
+
+pub const VK_NUMPAD0: VIRTUAL_KEY = VIRTUAL_KEY(0x60);
+pub const VK_NUMPAD1: VIRTUAL_KEY = VIRTUAL_KEY(0x61);
+pub const VK_NUMPAD2: VIRTUAL_KEY = VIRTUAL_KEY(0x62);
+pub const VK_NUMPAD3: VIRTUAL_KEY = VIRTUAL_KEY(0x63);
+pub const VK_NUMPAD4: VIRTUAL_KEY = VIRTUAL_KEY(0x64);
+pub const VK_NUMPAD5: VIRTUAL_KEY = VIRTUAL_KEY(0x65);
+pub const VK_NUMPAD6: VIRTUAL_KEY = VIRTUAL_KEY(0x66);
+pub const VK_NUMPAD7: VIRTUAL_KEY = VIRTUAL_KEY(0x67);
+pub const VK_NUMPAD8: VIRTUAL_KEY = VIRTUAL_KEY(0x68);
+pub const VK_NUMPAD9: VIRTUAL_KEY = VIRTUAL_KEY(0x69);
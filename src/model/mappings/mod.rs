@@ -1,11 +1,24 @@
-#[cfg(target_os = "windows")]
+#[cfg(not(target_os = "windows"))]
+pub(crate) mod keys;
 mod windows;
 
+#[cfg(not(target_os = "windows"))]
+pub use keys::VIRTUAL_KEY;
 #[cfg(target_os = "windows")]
+pub use ::windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
+
 pub use windows::Input as Input;
-#[cfg(target_os = "windows")]
 pub use windows::PLAY_KEY as PLAY_KEY;
-#[cfg(target_os = "windows")]
+pub use windows::{OCTAVE_MODIFIER, SEMITONE_MODIFIER};
 pub use windows::MAPPINGS as MAPPINGS;
-#[cfg(target_os = "windows")]
-pub use windows::input_for_midi;
+pub use windows::{input_for_keys, input_for_midi};
+pub use windows::{KEY_CHOICES, key_by_name, key_name};
+pub use windows::{
+    DIR_1_RIGHT, DIR_2_DOWNRIGHT, DIR_3_DOWN, DIR_4_DOWNLEFT, DIR_5_LEFT, DIR_6_UPLEFT, DIR_7_UP,
+    DIR_8_UPRIGHT,
+};
+pub use windows::{
+    DEFAULT_MODIFIER_KEYS, FLUTE_RANGE, ModifierKeys, OwnedInput, active_mappings,
+    build_mappings, build_mappings_with, input_for_midi_in, mapping_range, mappings_with_modifiers,
+    modifier_keys, set_modifier_keys,
+};
@@ -1,3 +1,7 @@
+#[cfg(not(target_os = "windows"))]
+use super::keys::*;
+use std::sync::{LazyLock, Mutex, RwLock};
+#[cfg(target_os = "windows")]
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
 
 /// A combination of keypresses that represent a note
@@ -19,187 +23,385 @@ pub const PLAY_KEY: VIRTUAL_KEY = VK_NUMPAD5;
 pub const OCTAVE_MODIFIER: VIRTUAL_KEY = VK_1;
 pub const SEMITONE_MODIFIER: VIRTUAL_KEY = VK_3;
 
-pub const MAPPINGS: &[(u8, Input)] = &[
-    (
-        69,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_1_RIGHT],
-            note_label: "A4 (69)",
-        },
-    ),
-    (
-        70,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_1_RIGHT, SEMITONE_MODIFIER],
-            note_label: "A#4 (70)",
-        },
-    ),
-    (
-        71,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_2_DOWNRIGHT],
-            note_label: "B4 (71)",
-        },
-    ),
-    (
-        72,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_2_DOWNRIGHT, SEMITONE_MODIFIER],
-            note_label: "C5 (72)",
-        },
-    ),
-    (
-        73,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_3_DOWN],
-            note_label: "C#5 (73)",
-        },
-    ),
-    (
-        74,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_3_DOWN, SEMITONE_MODIFIER],
-            note_label: "D5 (74)",
-        },
-    ),
-    (
-        75,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_4_DOWNLEFT, SEMITONE_MODIFIER],
-            note_label: "D#5 (75)",
-        },
-    ),
-    (
-        76,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_5_LEFT],
-            note_label: "E5 (76)",
-        },
-    ),
-    (
-        77,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_5_LEFT, SEMITONE_MODIFIER],
-            note_label: "F5 (77)",
-        },
-    ),
-    (
-        78,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_6_UPLEFT],
-            note_label: "F#5 (78)",
-        },
-    ),
-    (
-        79,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_6_UPLEFT, SEMITONE_MODIFIER],
-            note_label: "G5 (79)",
-        },
-    ),
-    (
-        80,
-        Input {
-            keys: &[OCTAVE_MODIFIER, DIR_7_UP],
-            note_label: "G#5 (80)",
-        },
-    ),
-    (
-        81,
-        Input {
-            keys: &[DIR_1_RIGHT],
-            note_label: "A5 (81)",
-        },
-    ),
-    (
-        82,
-        Input {
-            keys: &[DIR_1_RIGHT, SEMITONE_MODIFIER],
-            note_label: "A#5 (82)",
-        },
-    ),
-    (
-        83,
-        Input {
-            keys: &[DIR_2_DOWNRIGHT],
-            note_label: "B5 (83)",
-        },
-    ),
-    (
-        84,
-        Input {
-            keys: &[DIR_2_DOWNRIGHT, SEMITONE_MODIFIER],
-            note_label: "C6 (84)",
-        },
-    ),
-    (
-        85,
-        Input {
-            keys: &[DIR_3_DOWN],
-            note_label: "C#6 (85)",
-        },
-    ),
-    (
-        86,
-        Input {
-            keys: &[DIR_3_DOWN, SEMITONE_MODIFIER],
-            note_label: "D6 (86)",
-        },
-    ),
-    (
-        87,
-        Input {
-            keys: &[DIR_4_DOWNLEFT, SEMITONE_MODIFIER],
-            note_label: "D#6 (87)",
-        },
-    ),
-    (
-        88,
-        Input {
-            keys: &[DIR_5_LEFT],
-            note_label: "E6 (88)",
-        },
-    ),
-    (
-        89,
-        Input {
-            keys: &[DIR_5_LEFT, SEMITONE_MODIFIER],
-            note_label: "F6 (89)",
-        },
-    ),
-    (
-        90,
-        Input {
-            keys: &[DIR_6_UPLEFT],
-            note_label: "F#6 (90)",
-        },
-    ),
-    (
-        91,
-        Input {
-            keys: &[DIR_6_UPLEFT, SEMITONE_MODIFIER],
-            note_label: "G6 (91)",
-        },
-    ),
-    (
-        92,
-        Input {
-            keys: &[DIR_7_UP],
-            note_label: "G#6 (92)",
-        },
-    ),
-    (
-        93,
-        Input {
-            keys: &[DIR_8_UPRIGHT],
-            note_label: "A6 (93)",
-        },
-    ),
+/// Keys the play action, the modifiers & the playback controls can be bound to, by the names the CLI accepts.
+pub const KEY_CHOICES: &[(&str, VIRTUAL_KEY)] = &[
+    ("0", VK_0),
+    ("1", VK_1),
+    ("2", VK_2),
+    ("3", VK_3),
+    ("4", VK_4),
+    ("5", VK_5),
+    ("6", VK_6),
+    ("7", VK_7),
+    ("8", VK_8),
+    ("9", VK_9),
+    ("numpad0", VK_NUMPAD0),
+    ("numpad1", VK_NUMPAD1),
+    ("numpad2", VK_NUMPAD2),
+    ("numpad3", VK_NUMPAD3),
+    ("numpad4", VK_NUMPAD4),
+    ("numpad5", VK_NUMPAD5),
+    ("numpad6", VK_NUMPAD6),
+    ("numpad7", VK_NUMPAD7),
+    ("numpad8", VK_NUMPAD8),
+    ("numpad9", VK_NUMPAD9),
+    ("a", VK_A),
+    ("b", VK_B),
+    ("c", VK_C),
+    ("d", VK_D),
+    ("e", VK_E),
+    ("f", VK_F),
+    ("g", VK_G),
+    ("h", VK_H),
+    ("i", VK_I),
+    ("j", VK_J),
+    ("k", VK_K),
+    ("l", VK_L),
+    ("m", VK_M),
+    ("n", VK_N),
+    ("o", VK_O),
+    ("p", VK_P),
+    ("q", VK_Q),
+    ("r", VK_R),
+    ("s", VK_S),
+    ("t", VK_T),
+    ("u", VK_U),
+    ("v", VK_V),
+    ("w", VK_W),
+    ("x", VK_X),
+    ("y", VK_Y),
+    ("z", VK_Z),
+    ("space", VK_SPACE),
+    ("enter", VK_RETURN),
+    ("escape", VK_ESCAPE),
+];
+
+/// Looks up a rebindable key by (case-insensitive) name, see [`KEY_CHOICES`].
+pub fn key_by_name(name: &str) -> Option<&'static VIRTUAL_KEY> {
+    KEY_CHOICES
+        .iter()
+        .find(|(choice, _)| choice.eq_ignore_ascii_case(name))
+        .map(|(_, key)| key)
+}
+
+/// The name [`KEY_CHOICES`] gives `key`, if it has one.
+pub fn key_name(key: VIRTUAL_KEY) -> Option<&'static str> {
+    KEY_CHOICES
+        .iter()
+        .find(|(_, choice)| *choice == key)
+        .map(|(name, _)| *name)
+}
+
+/// The lowest & highest notes the flute can play.
+pub const FLUTE_RANGE: (u8, u8) = (69, 93);
+
+/// Notes up to & including this one hold the octave modifier.
+const HIGHEST_OCTAVE_MODIFIED: u8 = 80;
+
+/// The direction for each pitch class counted up from A, and whether the semitone modifier is held
+/// with it. The directions play an A major scale (with D only ever reached as a raised C#), so
+/// every other note is the direction below it raised by the semitone modifier.
+const PITCH_CLASS_SCHEME: [(VIRTUAL_KEY, bool); 12] = [
+    (DIR_1_RIGHT, false),     // A
+    (DIR_1_RIGHT, true),      // A#
+    (DIR_2_DOWNRIGHT, false), // B
+    (DIR_2_DOWNRIGHT, true),  // C
+    (DIR_3_DOWN, false),      // C#
+    (DIR_3_DOWN, true),       // D
+    (DIR_4_DOWNLEFT, true),   // D#
+    (DIR_5_LEFT, false),      // E
+    (DIR_5_LEFT, true),       // F
+    (DIR_6_UPLEFT, false),    // F#
+    (DIR_6_UPLEFT, true),     // G
+    (DIR_7_UP, false),        // G#
+];
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
 ];
 
+/// An [`Input`] that owns its keys & label, as built by [`build_mappings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedInput {
+    pub note_label: String,
+    pub keys: Vec<VIRTUAL_KEY>,
+}
+
+impl OwnedInput {
+    /// Leaks the keys & label, so the input can be shared as `&'static` for the rest of the run.
+    fn leak(self) -> Input {
+        Input {
+            note_label: Box::leak(self.note_label.into_boxed_str()),
+            keys: Box::leak(self.keys.into_boxed_slice()),
+        }
+    }
+}
+
+/// Builds the key combo for every note in [`FLUTE_RANGE`] with the default modifier keys.
+pub fn build_mappings() -> Vec<(u8, OwnedInput)> {
+    build_mappings_with(DEFAULT_MODIFIER_KEYS)
+}
+
+/// Builds the key combo for every note in [`FLUTE_RANGE`]: the lower octave holds the octave
+/// modifier, each pitch class picks its direction from [`PITCH_CLASS_SCHEME`], and the top A
+/// has a direction of its own.
+pub fn build_mappings_with(modifiers: ModifierKeys) -> Vec<(u8, OwnedInput)> {
+    let (lowest, highest) = FLUTE_RANGE;
+
+    (lowest..=highest)
+        .map(|midi| {
+            let mut keys = Vec::new();
+
+            if midi == highest {
+                keys.push(DIR_8_UPRIGHT);
+            } else {
+                let (direction, raised) = PITCH_CLASS_SCHEME[((midi - lowest) % 12) as usize];
+
+                if midi <= HIGHEST_OCTAVE_MODIFIED {
+                    keys.push(modifiers.octave);
+                }
+                keys.push(direction);
+                if raised {
+                    keys.push(modifiers.semitone);
+                }
+            }
+
+            let note_label = format!(
+                "{}{} ({})",
+                NOTE_NAMES[(midi % 12) as usize],
+                (midi / 12) as i32 - 1,
+                midi
+            );
+
+            (midi, OwnedInput { note_label, keys })
+        })
+        .collect()
+}
+
+fn leak_mappings(mappings: Vec<(u8, OwnedInput)>) -> &'static [(u8, Input)] {
+    let mappings = mappings
+        .into_iter()
+        .map(|(midi, input)| (midi, input.leak()))
+        .collect::<Vec<_>>();
+
+    Box::leak(mappings.into_boxed_slice())
+}
+
+/// The key combo for every playable note with the default modifier keys.
+pub static MAPPINGS: LazyLock<&'static [(u8, Input)]> =
+    LazyLock::new(|| leak_mappings(build_mappings()));
+
+/// The lowest & highest notes [`MAPPINGS`] has a combo for, e.g. to fold imported songs into.
+pub fn mapping_range() -> (u8, u8) {
+    range_of(*MAPPINGS).unwrap_or(FLUTE_RANGE)
+}
+
+/// The lowest & highest notes in `mappings`, or `None` when it's empty.
+fn range_of<T>(mappings: &[(u8, T)]) -> Option<(u8, u8)> {
+    let lowest = mappings.iter().map(|(midi, _)| *midi).min()?;
+    let highest = mappings.iter().map(|(midi, _)| *midi).max()?;
+
+    Some((lowest, highest))
+}
+
+/// The keys bound to the flute's octave & semitone modifiers in game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModifierKeys {
+    pub octave: VIRTUAL_KEY,
+    pub semitone: VIRTUAL_KEY,
+}
+
+pub const DEFAULT_MODIFIER_KEYS: ModifierKeys = ModifierKeys {
+    octave: OCTAVE_MODIFIER,
+    semitone: SEMITONE_MODIFIER,
+};
+
+impl Default for ModifierKeys {
+    fn default() -> Self {
+        DEFAULT_MODIFIER_KEYS
+    }
+}
+
+type ActiveMappings = (ModifierKeys, &'static [(u8, Input)]);
+
+/// The modifiers in use & the mappings built from them, which [`input_for_midi`] resolves against.
+/// Unset until the modifiers are rebound, meaning the defaults & [`MAPPINGS`].
+static ACTIVE_MAPPINGS: RwLock<Option<ActiveMappings>> = RwLock::new(None);
+
+/// Builds the mappings for the given modifier keys. Tables other than [`MAPPINGS`] are leaked so
+/// they can hand out `&'static Input`s just like it, so build them once rather than per song.
+pub fn mappings_with_modifiers(modifiers: ModifierKeys) -> &'static [(u8, Input)] {
+    if modifiers == DEFAULT_MODIFIER_KEYS {
+        return *MAPPINGS;
+    }
+
+    leak_mappings(build_mappings_with(modifiers))
+}
+
+/// Makes every later [`input_for_midi`] lookup use the given modifier keys.
+pub fn set_modifier_keys(modifiers: ModifierKeys) {
+    let mappings = mappings_with_modifiers(modifiers);
+
+    if let Ok(mut active) = ACTIVE_MAPPINGS.write() {
+        *active = Some((modifiers, mappings));
+    }
+}
+
+pub fn modifier_keys() -> ModifierKeys {
+    ACTIVE_MAPPINGS
+        .read()
+        .ok()
+        .and_then(|active| active.map(|(modifiers, _)| modifiers))
+        .unwrap_or(DEFAULT_MODIFIER_KEYS)
+}
+
+/// The mappings for the modifier keys currently in use, see [`set_modifier_keys`].
+pub fn active_mappings() -> &'static [(u8, Input)] {
+    ACTIVE_MAPPINGS
+        .read()
+        .ok()
+        .and_then(|active| active.map(|(_, mappings)| mappings))
+        .unwrap_or(*MAPPINGS)
+}
+
 pub fn input_for_midi(midi: u8) -> Option<&'static Input> {
-    MAPPINGS
+    input_for_midi_in(active_mappings(), midi)
+}
+
+pub fn input_for_midi_in(mappings: &'static [(u8, Input)], midi: u8) -> Option<&'static Input> {
+    mappings
         .iter()
         .find(|(m, _)| *m == midi)
         .map(|(_, input)| input)
 }
+
+/// Every custom combo handed out by [`input_for_keys`], so each one is only leaked once.
+static CUSTOM_INPUTS: Mutex<Vec<&'static Input>> = Mutex::new(Vec::new());
+
+/// The input for an arbitrary combo of virtual key codes, for events that bypass the mappings.
+pub fn input_for_keys(codes: &[u16]) -> &'static Input {
+    let keys = codes
+        .iter()
+        .map(|&code| VIRTUAL_KEY(code))
+        .collect::<Vec<_>>();
+    let mut custom = CUSTOM_INPUTS.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(input) = custom.iter().find(|input| input.keys == keys.as_slice()) {
+        return input;
+    }
+
+    let input: &'static Input = Box::leak(Box::new(
+        OwnedInput {
+            note_label: format!("custom {:?}", codes),
+            keys,
+        }
+        .leak(),
+    ));
+    custom.push(input);
+
+    input
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rebind_modifier_keys() {
+        let modifiers = ModifierKeys {
+            octave: VK_Q,
+            semitone: VK_E,
+        };
+        let mappings = mappings_with_modifiers(modifiers);
+
+        let a4 = input_for_midi_in(mappings, 69).expect("Midi values should be in range..!");
+        assert_eq!(a4.keys, &[VK_Q, DIR_1_RIGHT]);
+        assert_eq!(a4.note_label, "A4 (69)");
+
+        let a_sharp4 = input_for_midi_in(mappings, 70).expect("Midi values should be in range..!");
+        assert_eq!(a_sharp4.keys, &[VK_Q, DIR_1_RIGHT, VK_E]);
+
+        // Notes without modifiers are left alone, as is the shared default table.
+        let a5 = input_for_midi_in(mappings, 81).expect("Midi values should be in range..!");
+        assert_eq!(a5.keys, &[DIR_1_RIGHT]);
+        assert_eq!(MAPPINGS[0].1.keys, &[OCTAVE_MODIFIER, DIR_1_RIGHT]);
+    }
+
+    #[test]
+    fn top_a_uses_upright() {
+        // A6 is its own direction rather than a raised G#, & A5 is A4 without the octave modifier.
+        let a6 = input_for_midi_in(*MAPPINGS, 93).expect("Midi values should be in range..!");
+        assert_eq!(a6.keys, &[DIR_8_UPRIGHT]);
+        assert_eq!(a6.note_label, "A6 (93)");
+
+        let a5 = input_for_midi_in(*MAPPINGS, 81).expect("Midi values should be in range..!");
+        assert_eq!(a5.keys, &[DIR_1_RIGHT]);
+    }
+
+    #[test]
+    fn mapping_range_follows_table() {
+        assert_eq!(mapping_range(), (69, 93));
+
+        let mut mappings = build_mappings();
+        let a4 = mappings[0].1.clone();
+        mappings.push((96, a4.clone()));
+        assert_eq!(range_of(&mappings), Some((69, 96)));
+
+        mappings.insert(0, (60, a4));
+        assert_eq!(range_of(&mappings), Some((60, 96)));
+        assert_eq!(range_of::<OwnedInput>(&[]), None);
+    }
+
+    #[test]
+    fn generated_mappings_follow_rules() {
+        let mappings = build_mappings();
+        let (lowest, highest) = FLUTE_RANGE;
+
+        // One combo per note in range, without gaps or repeats.
+        let midis = mappings.iter().map(|(midi, _)| *midi).collect::<Vec<_>>();
+        assert_eq!(midis, (lowest..=highest).collect::<Vec<_>>());
+        for (i, (_, input)) in mappings.iter().enumerate() {
+            assert!(
+                mappings[i + 1..]
+                    .iter()
+                    .all(|(_, other)| other.keys != input.keys)
+            );
+        }
+
+        let keys_for = |midi: u8| {
+            mappings
+                .iter()
+                .find(|(m, _)| *m == midi)
+                .map(|(_, input)| input.keys.clone())
+                .expect("Midi values should be in range..!")
+        };
+
+        assert_eq!(keys_for(69), vec![OCTAVE_MODIFIER, DIR_1_RIGHT]);
+        assert_eq!(
+            keys_for(74),
+            vec![OCTAVE_MODIFIER, DIR_3_DOWN, SEMITONE_MODIFIER]
+        );
+        assert_eq!(
+            keys_for(75),
+            vec![OCTAVE_MODIFIER, DIR_4_DOWNLEFT, SEMITONE_MODIFIER]
+        );
+        assert_eq!(keys_for(80), vec![OCTAVE_MODIFIER, DIR_7_UP]);
+        assert_eq!(keys_for(81), vec![DIR_1_RIGHT]);
+        assert_eq!(keys_for(88), vec![DIR_5_LEFT]);
+        assert_eq!(keys_for(91), vec![DIR_6_UPLEFT, SEMITONE_MODIFIER]);
+        assert_eq!(keys_for(93), vec![DIR_8_UPRIGHT]);
+
+        // Only the lower octave holds the octave modifier, & only raised notes the semitone one.
+        let raised = [70, 72, 74, 75, 77, 79, 82, 84, 86, 87, 89, 91];
+        for (midi, input) in mappings.iter() {
+            assert_eq!(input.keys.contains(&OCTAVE_MODIFIER), *midi <= 80);
+            assert_eq!(
+                input.keys.contains(&SEMITONE_MODIFIER),
+                raised.contains(midi)
+            );
+        }
+
+        assert_eq!(mappings[0].1.note_label, "A4 (69)");
+        assert_eq!(mappings[6].1.note_label, "D#5 (75)");
+    }
+}
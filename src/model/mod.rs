@@ -1,3 +1,4 @@
+pub mod articulation;
 pub mod config;
 pub mod mappings;
 pub mod song;
@@ -1,24 +1,196 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use anyhow::{Result, anyhow};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// The pitch of a rest, see [`Event::rest`]. It lies outside MIDI's 0..=127, so no note has it.
+pub const REST_MIDI: u8 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Note {
     pub midi: u8,
     pub velocity: u8,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
     pub note: Note,
     pub time_ms: f64,
     pub duration_ms: f64,
+
+    /// Virtual key codes to press for this event instead of the mapped combo for its note, e.g. to
+    /// trigger inputs the flute mappings don't cover. Reducing a song to monophony drops these.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub override_keys: Option<Vec<u16>>,
+}
+
+impl Event {
+    /// A silent event lasting `duration_ms` from `time_ms`, which the player sleeps through.
+    pub fn rest(time_ms: f64, duration_ms: f64) -> Self {
+        Self {
+            note: Note {
+                midi: REST_MIDI,
+                velocity: 0,
+            },
+            time_ms,
+            duration_ms,
+            override_keys: None,
+        }
+    }
+
+    pub fn is_rest(&self) -> bool {
+        self.note.midi == REST_MIDI
+    }
+
+    /// Orders events by start time, then by pitch, so events starting on the same millisecond
+    /// always come out lowest note first.
+    pub fn cmp_time_pitch(&self, other: &Event) -> Ordering {
+        self.time_ms
+            .total_cmp(&other.time_ms)
+            .then_with(|| self.note.midi.cmp(&other.note.midi))
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Metadata {
     pub title: Option<String>,
     pub tempo_bpm: Option<f64>,
+
+    /// Named points in the song as `(time_ms, label)`, from the MIDI file's marker & cue point
+    /// metas, e.g. to find where a section or repeat starts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub markers: Vec<(f64, String)>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Song {
     pub metadata: Metadata,
     pub events: Vec<Event>,
 }
+
+/// Sorts `events` by [`Event::cmp_time_pitch`]. The sort is stable, so events sharing both a
+/// time & a pitch keep their original order.
+pub fn sort_events(events: &mut [Event]) {
+    events.sort_by(Event::cmp_time_pitch);
+}
+
+/// Writes a song to `path` as pretty-printed JSON, gzipped if `path` ends in `.json.gz`.
+pub fn save_song<P: AsRef<Path>>(song: &Song, path: P) -> Result<()> {
+    if is_gzip_path(path.as_ref()) {
+        return save_song_gz(song, path);
+    }
+
+    let json = serde_json::to_string_pretty(song)?;
+
+    fs::write(path.as_ref(), json)
+        .map_err(|e| anyhow!("Failed to write song to {}: {}", path.as_ref().display(), e))
+}
+
+/// Reads a song previously written by [`save_song`].
+pub fn load_song<P: AsRef<Path>>(path: P) -> Result<Song> {
+    if is_gzip_path(path.as_ref()) {
+        return load_song_gz(path);
+    }
+
+    let json = fs::read_to_string(path.as_ref()).map_err(|e| {
+        anyhow!(
+            "Failed to read song from {}: {}",
+            path.as_ref().display(),
+            e
+        )
+    })?;
+
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Writes a song to `path` as gzipped JSON.
+pub fn save_song_gz<P: AsRef<Path>>(song: &Song, path: P) -> Result<()> {
+    let json = serde_json::to_vec(song)?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json)?;
+    let bytes = encoder.finish()?;
+
+    fs::write(path.as_ref(), bytes)
+        .map_err(|e| anyhow!("Failed to write song to {}: {}", path.as_ref().display(), e))
+}
+
+/// Reads a song previously written by [`save_song_gz`].
+pub fn load_song_gz<P: AsRef<Path>>(path: P) -> Result<Song> {
+    let bytes = fs::read(path.as_ref()).map_err(|e| {
+        anyhow!(
+            "Failed to read song from {}: {}",
+            path.as_ref().display(),
+            e
+        )
+    })?;
+
+    let mut json = String::new();
+    GzDecoder::new(bytes.as_slice()).read_to_string(&mut json)?;
+
+    Ok(serde_json::from_str(&json)?)
+}
+
+fn is_gzip_path(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".json.gz"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gzip_round_trip() {
+        let song = Song {
+            metadata: Metadata {
+                title: Some(String::from("Scales")),
+                tempo_bpm: Some(120.0),
+                markers: Vec::new(),
+            },
+            events: (0..200)
+                .map(|i| Event {
+                    note: Note {
+                        midi: 69 + (i % 25) as u8,
+                        velocity: 100,
+                    },
+                    time_ms: i as f64 * 250.0,
+                    duration_ms: 200.0,
+                    override_keys: None,
+                })
+                .collect(),
+        };
+
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let plain_path = dir.join(format!("flute_well_round_trip_{pid}.json"));
+        let gz_path = dir.join(format!("flute_well_round_trip_{pid}.json.gz"));
+
+        assert!(save_song(&song, &plain_path).is_ok());
+        assert!(save_song(&song, &gz_path).is_ok());
+
+        let plain_len = fs::metadata(&plain_path).map(|m| m.len()).unwrap();
+        let gz_len = fs::metadata(&gz_path).map(|m| m.len()).unwrap();
+        let loaded = load_song(&gz_path);
+
+        let _ = fs::remove_file(&plain_path);
+        let _ = fs::remove_file(&gz_path);
+
+        assert!(gz_len < plain_len);
+
+        let loaded = loaded.expect("Gzipped song should load..!");
+        assert_eq!(loaded.metadata.title, song.metadata.title);
+        assert_eq!(loaded.events.len(), song.events.len());
+        for (a, b) in loaded.events.iter().zip(song.events.iter()) {
+            assert_eq!(a.note, b.note);
+            assert_eq!(a.time_ms, b.time_ms);
+            assert_eq!(a.duration_ms, b.duration_ms);
+        }
+    }
+}
@@ -1,24 +1,110 @@
-use crate::engine::InputEngine;
-use crate::model::mappings::{Input, input_for_midi};
+use crate::clock::{Clock, SystemClock, VirtualClock};
+use crate::engine::{CHANGED_KEY_RELEASE_MS, InputEngine, RETRIGGER_GAP_MS, shares_changed_keys};
+use crate::model::articulation::ArticulationSchedule;
+use crate::model::mappings::{Input, active_mappings, input_for_keys, input_for_midi};
 use crate::model::song::Song;
-use anyhow::bail;
-use log::{debug, info, warn};
-use spin_sleep::{SpinSleeper, SpinStrategy};
-use std::sync::mpsc::Sender;
+use crate::window::{
+    ActiveWindowWatcher, FixedWindowWatcher, WINDOW_POLL_INTERVAL, WindowTarget, WindowWatcher,
+    wait_for_window_with,
+};
+use anyhow::{anyhow, bail};
+use log::{debug, error, info, warn};
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
+/// How long to wait after the warm-up tap before starting the song.
+const WARMUP_SETTLE_MS: u64 = 50;
+
+/// The shortest a note is held while playback is catching up on overdue events.
+const MIN_CATCH_UP_HOLD_MS: f64 = 10.0;
+
+/// How long before each action the worker stops sleeping & spins on the clock instead, by default.
+pub const DEFAULT_FINAL_SPIN: Duration = Duration::from_millis(2);
+
+/// The longest final approach spin allowed, so a misconfigured one can't hog a core for long.
+const MAX_FINAL_SPIN: Duration = Duration::from_millis(20);
+
+/// Taps further apart than this start a new tempo instead of adding to the last one.
+const TAP_RESET: Duration = Duration::from_secs(2);
+
+/// How many of the latest taps are averaged into the tapped tempo.
+const MAX_TAPS: usize = 4;
+
+/// The tempo schedules without one of their own are taken to be at, the MIDI default.
+const DEFAULT_SCHEDULE_BPM: f64 = 120.0;
+
+/// How often progress checkpoints are written by default, see [`Player::with_progress_file`].
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long stopping waits for the worker to wind down before leaving it behind.
+const STOP_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the worker waits for the target window to have focus before giving up on playback.
+const WINDOW_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
 enum ControlMsg {
     Stop,
+
+    /// Plays the rest of the schedule at this multiple of its own timing, see [`Player::tap_tempo`].
+    Retime(f64),
+
+    /// Releases every key & holds the schedule where it is until [`ControlMsg::Resume`].
+    Pause,
+    Resume,
+}
+
+/// What playback does when the game window loses focus partway through a song.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FocusLostBehavior {
+    /// Release every key & wait up to 30 seconds for focus to return, then carry on with the
+    /// original schedule, or end playback if it never does.
+    #[default]
+    WaitAndResume,
+
+    /// Release every key & end playback without panicking.
+    StopPlayback,
+
+    /// Release every key & freeze the timeline until focus returns, so the song picks back up at
+    /// the same musical position.
+    PauseTimeline,
 }
 
+/// A song event resolved to the key combo that plays it.
 #[derive(Debug, Clone)]
 pub struct ScheduledEvent {
-    time_ms: f64,
-    duration_ms: f64,
-    input: &'static Input,
+    pub time_ms: f64,
+    pub duration_ms: f64,
+    pub input: &'static Input,
+}
+
+/// When a scheduled event was actually sent to the engine, relative to the start of playback.
+#[derive(Debug, Clone)]
+pub struct EmissionRecord {
+    pub scheduled_ms: f64,
+    pub emitted_ms: f64,
+    pub duration_ms: f64,
+    pub input: &'static Input,
+}
+
+/// How a song would fare if loaded, see [`Player::validate_song`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Events with a key combo on the flute.
+    pub mappable: usize,
+
+    /// Events that would be skipped since their note has no mapping.
+    pub unmappable: usize,
+
+    /// How long playback would run, from the first mappable event (or zero) to the last one's end.
+    pub duration_ms: f64,
 }
 
 #[derive(Debug)]
@@ -26,9 +112,27 @@ pub struct Player<E: InputEngine> {
     delay: u64,
     verbose: bool,
     engine: Arc<E>,
+    watcher: Arc<dyn WindowWatcher>,
+    window_target: WindowTarget,
+    clock: Arc<dyn Clock>,
+    record_path: Option<PathBuf>,
+    progress_file: Option<PathBuf>,
+    progress_interval: Duration,
+    crossfade_ms: f64,
+    focus_lost: FocusLostBehavior,
+    warmup: bool,
+    window_check_interval: Duration,
+    hold_final_note: bool,
+    articulation_schedule: Option<ArticulationSchedule>,
+    play_key_offset_ms: f64,
+    catch_up: bool,
+    final_spin: Duration,
     schedule: Mutex<Vec<ScheduledEvent>>,
+    schedule_bpm: Mutex<f64>,
+    taps: Mutex<Vec<Instant>>,
     control_tx: Mutex<Option<Sender<ControlMsg>>>,
     worker_handle: Mutex<Option<JoinHandle<()>>>,
+    busy: Arc<AtomicBool>,
 }
 
 impl<E: InputEngine + 'static> Player<E> {
@@ -37,39 +141,160 @@ impl<E: InputEngine + 'static> Player<E> {
             delay,
             verbose,
             engine: Arc::new(engine),
+            watcher: Arc::new(ActiveWindowWatcher),
+            window_target: WindowTarget::default(),
+            clock: Arc::new(SystemClock::default()),
+            record_path: None,
+            progress_file: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            crossfade_ms: 0.0,
+            focus_lost: FocusLostBehavior::default(),
+            warmup: false,
+            window_check_interval: Duration::ZERO,
+            hold_final_note: false,
+            articulation_schedule: None,
+            play_key_offset_ms: 0.0,
+            catch_up: true,
+            final_spin: DEFAULT_FINAL_SPIN,
             schedule: Mutex::new(Vec::new()),
+            schedule_bpm: Mutex::new(DEFAULT_SCHEDULE_BPM),
+            taps: Mutex::new(Vec::new()),
             control_tx: Mutex::new(None),
             worker_handle: Mutex::new(None),
+            busy: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    pub fn load_song(&self, song: Song) -> anyhow::Result<()> {
-        let mut events: Vec<ScheduledEvent> = Vec::new();
+    /// A player without a start delay that sends input through `engine`, asks `watcher` which
+    /// window has focus & keeps time with `clock`, so the whole pipeline can be driven headlessly.
+    pub fn new_for_test<W, C>(engine: E, watcher: W, clock: C) -> Self
+    where
+        W: WindowWatcher + 'static,
+        C: Clock + 'static,
+    {
+        Self::new(engine, false, 0)
+            .with_watcher(watcher)
+            .with_clock(clock)
+    }
 
-        for e in song.events.into_iter() {
-            let midi = e.note.midi;
-            let input = input_for_midi(midi);
+    /// Replaces the source of truth for which window currently has focus.
+    pub fn with_watcher<W: WindowWatcher + 'static>(mut self, watcher: W) -> Self {
+        self.watcher = Arc::new(watcher);
+        self
+    }
 
-            if let Some(input) = input {
-                events.push(ScheduledEvent {
-                    time_ms: e.time_ms,
-                    duration_ms: e.duration_ms,
-                    input,
-                });
-            } else {
-                warn!(
-                    "No mapping for MIDI {}: skipping event at {}ms..!",
-                    midi, e.time_ms
-                );
-                continue;
-            }
+    /// Only sends input while `target` has focus, e.g. [`WindowTarget::Pid`] to play on one of
+    /// several running instances of the game.
+    pub fn with_window_target(mut self, target: WindowTarget) -> Self {
+        self.window_target = target;
+        self
+    }
+
+    /// Replaces the source of time used to schedule events, e.g. with a `MockClock` to test
+    /// scheduling without waiting in real time.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Plays on a virtual clock that never waits, with the game window always in focus, so songs
+    /// go through the same timeline as real playback in an instant, e.g. to preview their keys.
+    pub fn with_virtual_time(self) -> Self {
+        self.with_watcher(FixedWindowWatcher::game())
+            .with_clock(VirtualClock::new())
+    }
+
+    /// Writes the scheduled & actual emission time of every played event to `path` once playback
+    /// finishes or is stopped.
+    pub fn with_recording<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.record_path = Some(path.into());
+        self
+    }
+
+    /// Appends a checkpoint like `120/450 events, 00:02:15 elapsed` to `path` every `interval` of
+    /// playback, to keep an eye on a long performance running in the background.
+    pub fn with_progress_file<P: Into<PathBuf>>(mut self, path: P, interval: Duration) -> Self {
+        self.progress_file = Some(path.into());
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Starts each playlist entry this many milliseconds before the previous one's final event ends.
+    pub fn with_crossfade(mut self, crossfade_ms: f64) -> Self {
+        self.crossfade_ms = crossfade_ms.max(0.0);
+        self
+    }
+
+    /// Chooses what happens when the game window loses focus during playback.
+    pub fn with_focus_lost_behavior(mut self, behavior: FocusLostBehavior) -> Self {
+        self.focus_lost = behavior;
+        self
+    }
+
+    /// Taps the play key once on its own before the first note, so the game has registered keyboard
+    /// input by the time the song starts.
+    pub fn with_warmup(mut self, warmup: bool) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Only re-checks which window has focus before an event once this long has passed since the
+    /// last successful check, instead of before every event.
+    pub fn with_window_check_interval(mut self, interval: Duration) -> Self {
+        self.window_check_interval = interval;
+        self
+    }
+
+    /// Holds the last note of the song like a fermata, keeping its keys down until playback is
+    /// stopped rather than releasing them after its duration.
+    pub fn with_hold_final_note(mut self, hold_final_note: bool) -> Self {
+        self.hold_final_note = hold_final_note;
+        self
+    }
+
+    /// Plays events within the schedule's ranges with their articulation instead of the engine's.
+    pub fn with_articulation_schedule(mut self, schedule: ArticulationSchedule) -> Self {
+        self.articulation_schedule = Some(schedule);
+        self
+    }
+
+    /// Starts every key press this many milliseconds early, so that the play key lands on the
+    /// event's time instead of after the note keys. That's [`PLAY_KEY_SETTLE_MS`](crate::engine::PLAY_KEY_SETTLE_MS)
+    /// plus however long sending the note keys takes.
+    pub fn with_play_key_offset(mut self, offset_ms: f64) -> Self {
+        self.play_key_offset_ms = offset_ms.max(0.0);
+        self
+    }
+
+    /// Whether to shorten holds that would run into the next event's time, accounting for how long
+    /// releasing a note takes, so late events catch back up with the schedule instead of pushing
+    /// every later one back. On by default.
+    pub fn with_catch_up(mut self, catch_up: bool) -> Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    /// How long before each press or release to stop sleeping & busy-wait on the clock, since a
+    /// sleep can wake up a few ms late. Capped at 20ms, & zero only ever sleeps.
+    pub fn with_final_spin(mut self, spin: Duration) -> Self {
+        self.final_spin = spin.min(MAX_FINAL_SPIN);
+        self
+    }
+
+    /// Whether a playback worker is still running, in which case no other song can be loaded.
+    pub fn is_busy(&self) -> bool {
+        self.busy.load(Ordering::SeqCst)
+    }
+
+    /// Replaces the loaded song. Fails while playback is in progress, since the running worker
+    /// would keep playing the old schedule.
+    pub fn load_song(&self, song: Song) -> anyhow::Result<()> {
+        if self.is_busy() {
+            bail!("Playback in progress, stop it before loading another song..!");
         }
 
-        events.sort_by(|a, b| {
-            a.time_ms
-                .partial_cmp(&b.time_ms)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        let events = schedule_for_song(&song);
+        self.set_schedule_bpm(song.metadata.tempo_bpm);
 
         let Ok(mut schedule_lock) = self.schedule.lock() else {
             bail!("Failed to lock the schedule..!");
@@ -85,205 +310,265 @@ impl<E: InputEngine + 'static> Player<E> {
         Ok(())
     }
 
-    pub fn play(&self, join: bool) -> anyhow::Result<()> {
-        {
-            let Ok(guard) = self.worker_handle.lock() else {
-                bail!("Failed to lock worker handle..!")
+    /// Loads several songs back to back as a single schedule, so the active window is only checked
+    /// once and each song can begin during the tail of the previous one.
+    pub fn load_playlist(&self, songs: Vec<Song>) -> anyhow::Result<()> {
+        if self.is_busy() {
+            bail!("Playback in progress, stop it before loading another playlist..!");
+        }
+
+        let song_count = songs.len();
+        self.set_schedule_bpm(songs.iter().find_map(|song| song.metadata.tempo_bpm));
+
+        let mut events: Vec<ScheduledEvent> = Vec::new();
+        let mut offset_ms = 0.0;
+
+        for song in songs.into_iter() {
+            let scheduled = schedule_for_song(&song);
+            let Some(end_ms) = scheduled
+                .iter()
+                .map(|e| e.time_ms + e.duration_ms)
+                .reduce(f64::max)
+            else {
+                continue;
             };
 
-            if guard.is_some() {
-                bail!("Playback already running..!")
-            }
+            events.extend(scheduled.into_iter().map(|mut e| {
+                e.time_ms += offset_ms;
+                e
+            }));
+
+            offset_ms = (offset_ms + end_ms - self.crossfade_ms).max(offset_ms);
         }
 
-        let Ok(schedule) = self.schedule.lock() else {
-            bail!("Failed to lock schedule..!")
+        events.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms));
+
+        let Ok(mut schedule_lock) = self.schedule.lock() else {
+            bail!("Failed to lock the schedule..!");
         };
+        *schedule_lock = events;
 
-        let schedule = schedule.clone();
+        info!(
+            "Loaded playlist of {} songs with {} scheduled events..!",
+            song_count,
+            schedule_lock.len()
+        );
 
-        if schedule.is_empty() {
-            bail!("No song loaded..!")
+        Ok(())
+    }
+
+    /// Loads events read by [`load_recording`] to be played at the times they were recorded,
+    /// bypassing the song's own schedule.
+    pub fn load_recorded(&self, mut events: Vec<ScheduledEvent>) -> anyhow::Result<()> {
+        if self.is_busy() {
+            bail!("Playback in progress, stop it before loading a recording..!");
         }
 
-        let engine = Arc::clone(&self.engine);
-        let (tx, rx) = mpsc::channel::<ControlMsg>();
+        // The recorded times are when each press began, which already includes the offset & the
+        // key latency.
+        let lead_ms = self.play_key_offset_ms + self.engine.key_registration_latency_ms();
+        for event in events.iter_mut() {
+            event.time_ms += lead_ms;
+        }
+        events.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms));
+        self.set_schedule_bpm(None);
 
-        {
-            let Ok(mut ctl) = self.control_tx.lock() else {
-                bail!("Failed to lock control_tx..!")
-            };
+        let Ok(mut schedule_lock) = self.schedule.lock() else {
+            bail!("Failed to lock the schedule..!");
+        };
+        *schedule_lock = events;
 
-            *ctl = Some(tx);
-        }
+        info!("Loaded recording with {} events..!", schedule_lock.len());
 
-        let delay = self.delay;
-        let verbose = self.verbose;
-        let handle = thread::spawn(move || {
-            let ctrl_rx = rx;
+        Ok(())
+    }
 
-            #[cfg(target_os = "windows")]
-            {
-                use windows::Win32::System::Threading::{
-                    GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_HIGHEST,
-                };
-                unsafe {
-                    let h = GetCurrentThread();
-                    let ok = SetThreadPriority(h, THREAD_PRIORITY_HIGHEST);
-
-                    if ok.is_ok() {
-                        debug!("Playback thread priority set to HIGHEST..!");
-                    } else {
-                        warn!("Failed to set playback thread priority..!");
-                    }
-                }
-            }
+    /// Remembers the tempo taps are measured against, 120 BPM when the schedule has none.
+    fn set_schedule_bpm(&self, bpm: Option<f64>) {
+        if let Ok(mut schedule_bpm) = self.schedule_bpm.lock() {
+            *schedule_bpm = bpm
+                .filter(|bpm| bpm.is_finite() && *bpm > 0.0)
+                .unwrap_or(DEFAULT_SCHEDULE_BPM);
+        }
+    }
 
-            let mut stamp = Instant::now();
-            info!("Waiting at most 30 SECONDS for the active window to be ANIMAL WELL..!");
+    /// Taps out the tempo for the rest of the song, like a conductor. Once there are two taps less
+    /// than 2 seconds apart, the average interval of the latest few sets the tempo, & a running
+    /// worker plays whatever it hasn't yet at that tempo instead of the loaded song's own.
+    /// Returns the tapped BPM, if there is one yet.
+    pub fn tap_tempo(&self) -> Option<f64> {
+        let now = self.clock.now();
+        let Ok(mut taps) = self.taps.lock() else {
+            return None;
+        };
 
-            loop {
-                if ctrl_rx.try_recv().is_ok() {
-                    warn!("Playback stopped during active window check..!");
-                    return;
-                }
+        if taps
+            .last()
+            .is_some_and(|last| now.saturating_duration_since(*last) > TAP_RESET)
+        {
+            taps.clear();
+        }
+        taps.push(now);
+        if taps.len() > MAX_TAPS {
+            taps.remove(0);
+        }
 
-                let active_window = active_win_pos_rs::get_active_window();
+        let (first, last) = (taps.first()?, taps.last()?);
+        let interval_ms = last.saturating_duration_since(*first).as_secs_f64() * 1000.0
+            / (taps.len() - 1).max(1) as f64;
+        if interval_ms <= 0.0 {
+            return None;
+        }
 
-                if active_window.is_err() {
-                    continue;
-                }
+        let bpm = 60_000.0 / interval_ms;
+        let schedule_bpm = self
+            .schedule_bpm
+            .lock()
+            .map(|bpm| *bpm)
+            .unwrap_or(DEFAULT_SCHEDULE_BPM);
 
-                let title = active_window.expect("Active window should be Ok..!").title;
+        if let Ok(tx) = self.control_tx.lock()
+            && let Some(tx) = tx.as_ref()
+        {
+            let _ = tx.send(ControlMsg::Retime(schedule_bpm / bpm));
+        }
 
-                debug!("Active window: \"{}\"", title);
-                if title == "ANIMAL WELL" {
-                    break;
-                } else {
-                    let elapsed = stamp.elapsed();
-                    if elapsed > Duration::from_secs(30) {
-                        panic!("Active window title was never ANIMAL WELL..!")
-                    }
-                }
+        info!("Tapped tempo: {:.1} bpm..!", bpm);
+        Some(bpm)
+    }
 
-                spin_sleep::sleep(Duration::from_millis(50));
-            }
+    /// Replays a recorded run exactly as it was emitted, see [`Player::load_recorded`].
+    pub fn play_recording(&self, events: Vec<ScheduledEvent>, join: bool) -> anyhow::Result<()> {
+        self.load_recorded(events)?;
+        self.play(join)
+    }
 
-            let mut was_ok = true;
-            info!(
-                "Active window is ANIMAL WELL, starting playback {}..!",
-                if delay > 0 {
-                    format!("in {} seconds", delay)
-                } else {
-                    "now".to_owned()
-                }
-            );
+    /// Checks how `song` maps onto the flute without loading it, checking the active window or
+    /// pressing any keys.
+    pub fn validate_song(&self, song: &Song) -> ValidationReport {
+        let events = schedule_for_song(song);
 
-            let sleeper = SpinSleeper::new(100_000).with_spin_strategy(SpinStrategy::YieldThread);
+        let notes = song.events.iter().filter(|e| !e.is_rest()).count();
 
-            if delay > 0 {
-                sleeper.sleep(Duration::from_secs(delay));
-            }
+        ValidationReport {
+            mappable: events.len(),
+            unmappable: notes - events.len(),
+            duration_ms: schedule_span_ms(&events),
+        }
+    }
 
-            let start = Instant::now();
-            const MAX_SLEEP_CHUNK_S: f64 = 0.050;
+    /// How many events the loaded schedule holds, after notes without a mapping were dropped.
+    /// Zero when nothing is loaded or the schedule can't be locked.
+    pub fn schedule_len(&self) -> usize {
+        self.schedule
+            .lock()
+            .map(|schedule| schedule.len())
+            .unwrap_or(0)
+    }
 
-            for event in schedule.into_iter() {
-                if ctrl_rx.try_recv().is_ok() {
-                    engine.all_keys_up().expect("Error cancelling input..!");
-                    warn!(
-                        "Playback stopped via control message after {} seconds..!",
-                        start.elapsed().as_secs()
-                    );
-                    return;
-                }
+    /// How long the loaded schedule runs for, from zero (or its earliest event, if that's before
+    /// zero) to the end of its last event. Zero when nothing is loaded or the schedule can't be
+    /// locked.
+    pub fn schedule_duration_ms(&self) -> f64 {
+        self.schedule
+            .lock()
+            .map(|schedule| schedule_span_ms(&schedule))
+            .unwrap_or(0.0)
+    }
 
-                let target = if event.time_ms < 0.0 {
-                    start
-                } else {
-                    start + Duration::from_secs_f64(event.time_ms / 1000.0)
-                };
+    pub fn engine(&self) -> &E {
+        &self.engine
+    }
 
-                loop {
-                    if ctrl_rx.try_recv().is_ok() {
-                        engine.all_keys_up().expect("Error cancelling input..!");
-                        warn!("Playback stopped during wait..!");
-                        return;
-                    }
+    /// Changes the articulation of the engine, including while a song is playing.
+    pub fn set_articulation(&self, articulation: f64) {
+        self.engine.set_articulation(articulation);
+    }
 
-                    let now = Instant::now();
-                    if now >= target {
-                        break;
-                    }
-                    let remaining = (target - now).as_secs_f64();
+    pub fn play(&self, join: bool) -> anyhow::Result<()> {
+        {
+            let Ok(mut guard) = self.worker_handle.lock() else {
+                bail!("Failed to lock worker handle..!")
+            };
 
-                    let chunk = if remaining > MAX_SLEEP_CHUNK_S {
-                        MAX_SLEEP_CHUNK_S
-                    } else {
-                        remaining
-                    };
+            // A worker that already ran to completion shouldn't block a replay.
+            if guard.as_ref().is_some_and(|handle| handle.is_finished())
+                && let Some(handle) = guard.take()
+            {
+                let _ = handle.join();
+                debug!("Reaped finished playback thread..!");
+            }
 
-                    sleeper.sleep(Duration::from_secs_f64(chunk));
-                }
+            if guard.is_some() {
+                bail!("Playback already running..!")
+            }
+        }
 
-                loop {
-                    if ctrl_rx.try_recv().is_ok() {
-                        engine.all_keys_up().expect("Error cancelling input..!");
-                        warn!("Playback stopped during active window check..!");
-                        return;
-                    }
+        let Ok(schedule) = self.schedule.lock() else {
+            bail!("Failed to lock schedule..!")
+        };
 
-                    let active_window = active_win_pos_rs::get_active_window();
+        let schedule = schedule.clone();
 
-                    if active_window.is_err() {
-                        continue;
-                    }
+        if schedule.is_empty() {
+            bail!("No song loaded..!")
+        }
 
-                    let title = active_window.expect("Active window should be Ok..!").title;
+        let engine = Arc::clone(&self.engine);
+        let (tx, rx) = mpsc::channel::<ControlMsg>();
 
-                    if title == "ANIMAL WELL" {
-                        was_ok = true;
-                        break;
-                    } else {
-                        if was_ok {
-                            stamp = Instant::now();
-                            engine.all_keys_up().expect("Error cancelling input..!");
-                        }
-                        let elapsed = stamp.elapsed();
-                        if elapsed > Duration::from_secs(30) {
-                            panic!("Active window title was never ANIMAL WELL..!")
-                        }
-                    }
+        {
+            let Ok(mut ctl) = self.control_tx.lock() else {
+                bail!("Failed to lock control_tx..!")
+            };
 
-                    spin_sleep::sleep(Duration::from_millis(50));
-                }
+            *ctl = Some(tx);
+        }
 
-                let emit_time = Instant::now();
-                let emitted_at_ms = emit_time.duration_since(start).as_secs_f64() * 1000.0;
+        let mut worker = PlaybackWorker {
+            engine,
+            watcher: Arc::clone(&self.watcher),
+            window_target: self.window_target.clone(),
+            clock: Arc::clone(&self.clock),
+            ctrl_rx: rx,
+            delay: self.delay,
+            verbose: self.verbose,
+            focus_lost: self.focus_lost,
+            warmup: self.warmup,
+            window_check_interval: self.window_check_interval,
+            hold_final_note: self.hold_final_note,
+            articulation_schedule: self.articulation_schedule.clone(),
+            play_key_offset_ms: self.play_key_offset_ms,
+            catch_up: self.catch_up,
+            final_spin: self.final_spin,
+            progress_file: self.progress_file.clone(),
+            progress_interval: self.progress_interval,
+            emissions: Vec::new(),
+        };
 
-                if verbose {
-                    let info = format!("Sending inputs for {} ", event.input.note_label);
-                    info!(
-                        "{:30} | at {:>13.3}ms | scheduled for: {:>13.3}ms | duration: {:>9.3}ms",
-                        info, emitted_at_ms, event.time_ms, event.duration_ms
-                    );
-                }
+        let record_path = self.record_path.clone();
+        let busy = Arc::clone(&self.busy);
+        busy.store(true, Ordering::SeqCst);
 
-                if let Err(why) =
-                    engine.key_press(event.input, event.duration_ms, engine.get_articulation())
-                {
-                    warn!(
-                        "Input error for {} at {:.3}ms | why: {:?}",
-                        event.input.note_label, emitted_at_ms, why
-                    );
+        let handle = thread::spawn(move || {
+            let _busy = BusyGuard(busy);
+            worker.run(schedule);
+
+            if let Some(path) = record_path {
+                match write_recording(&path, &worker.emissions) {
+                    Ok(()) => info!("Saved playback recording to {}..!", path.display()),
+                    Err(why) => warn!("Failed to save playback recording: {:?}", why),
                 }
             }
-
-            info!("Playback thread finished all events..!");
         });
 
         if join {
             handle.join().unwrap();
+
+            let Ok(mut ctl) = self.control_tx.lock() else {
+                bail!("Failed to lock control_tx..!")
+            };
+            *ctl = None;
         } else {
             let Ok(mut wh) = self.worker_handle.lock() else {
                 bail!("Failed to lock worker handle..!")
@@ -313,61 +598,1280 @@ impl<E: InputEngine + 'static> Player<E> {
             bail!("Failed to lock worker_handle..!")
         };
 
-        if let Some(handle) = lock.take() {
-            let _ = handle.join();
-            debug!("Playback thread joined..!");
+        if let Some(handle) = lock.take()
+            && join_with_timeout(handle, STOP_JOIN_TIMEOUT)
+        {
             info!("Stopped playback thread..!");
         }
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use log::warn;
-    use crate::util::ensure_active_window;
-    use crate::{import_midi_file, DefaultInputEngine, Event, Metadata, Note, Player, PolyPolicy, Song};
+    /// Releases every key & freezes playback where it is, keeping the worker around for
+    /// [`Player::resume`].
+    pub fn pause(&self) -> anyhow::Result<()> {
+        self.send_control(ControlMsg::Pause)
+    }
 
-    #[test]
-    fn mimic_cuckoo_clock() {
-        env_logger::try_init().unwrap_or(());
+    /// Carries on from where [`Player::pause`] left off, keeping the rest of the song's timing.
+    pub fn resume(&self) -> anyhow::Result<()> {
+        self.send_control(ControlMsg::Resume)
+    }
 
-        let art = 0.75;
-        let engine = DefaultInputEngine::new(art);
+    fn send_control(&self, msg: ControlMsg) -> anyhow::Result<()> {
+        let Ok(lock) = self.control_tx.lock() else {
+            bail!("Failed to lock control_tx..!")
+        };
 
-        const E6: u8 = 88;
-        const CS6: u8 = 85;
-        const REST_MS: f64 = 1000.0;
-        const DURATION_MS: f64 = 200.0;
-        const NOTE_GROUPS: &[&[u8]] = &[
-            &[E6, E6, CS6],
-            &[E6, E6, CS6],
-            &[E6, E6, CS6],
-            &[E6, E6, CS6],
-            &[E6, E6],
-        ];
+        match lock.as_ref() {
+            Some(tx) if tx.send(msg).is_ok() => Ok(()),
+            _ => bail!("No worker is running playback..!"),
+        }
+    }
 
-        let mut time_ms = 0.0;
-        let mut raw_events: Vec<(u8, f64)> = Vec::new();
-        for (n, group) in NOTE_GROUPS.iter().enumerate() {
-            if n > 0 {
-                time_ms += REST_MS;
-            }
+    /// Stops any running playback and forgets the previous worker, so the loaded song can be
+    /// played again from the top.
+    pub fn reset(&self) -> anyhow::Result<()> {
+        let tx = {
+            let Ok(mut lock) = self.control_tx.lock() else {
+                bail!("Failed to lock control_tx..!")
+            };
+            lock.take()
+        };
 
-            for &midi in *group {
-                raw_events.push((midi, time_ms));
-                time_ms += DURATION_MS;
-            }
+        if let Some(tx) = tx {
+            let _ = tx.send(ControlMsg::Stop);
         }
 
-        let song = Song {
-            metadata: Metadata {
-                title: Some(String::from("Cuckoo Clock")),
-                tempo_bpm: None
-            },
-            events: raw_events
-                .iter()
+        let handle = {
+            let Ok(mut lock) = self.worker_handle.lock() else {
+                bail!("Failed to lock worker_handle..!")
+            };
+            lock.take()
+        };
+
+        if let Some(handle) = handle {
+            join_with_timeout(handle, STOP_JOIN_TIMEOUT);
+        }
+
+        Ok(())
+    }
+}
+
+/// Clears the player's busy flag when the worker is done, even if it panicked along the way.
+struct BusyGuard(Arc<AtomicBool>);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Joins the worker if it finishes within `timeout`, otherwise leaves it running detached so the
+/// caller is never wedged behind it. Returns whether the worker was joined.
+fn join_with_timeout(handle: JoinHandle<()>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            warn!(
+                "Playback thread didn't stop within {:.1} seconds, leaving it behind..!",
+                timeout.as_secs_f64()
+            );
+            return false;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let _ = handle.join();
+    debug!("Playback thread joined..!");
+    true
+}
+
+/// The time from zero (or the earliest event, if that's before zero) to the end of the last event.
+fn schedule_span_ms(events: &[ScheduledEvent]) -> f64 {
+    let start_ms = events.iter().map(|e| e.time_ms).fold(0.0, f64::min);
+    let end_ms = events
+        .iter()
+        .map(|e| e.time_ms + e.duration_ms)
+        .fold(start_ms, f64::max);
+
+    end_ms - start_ms
+}
+
+/// Resolves every event of `song` to its key combo in time order, skipping rests & notes that have
+/// no mapping on the flute. Events with `override_keys` use those keys instead of their mapping.
+pub fn schedule_for_song(song: &Song) -> Vec<ScheduledEvent> {
+    let mut events: Vec<ScheduledEvent> = Vec::new();
+
+    // Events starting together are played lowest note first, whatever order the song lists them in.
+    let mut ordered = song.events.iter().collect::<Vec<_>>();
+    ordered.sort_by(|a, b| a.cmp_time_pitch(b));
+
+    for e in ordered.into_iter().filter(|e| !e.is_rest()) {
+        let midi = e.note.midi;
+        let input = match &e.override_keys {
+            Some(codes) => Some(input_for_keys(codes)),
+            None => input_for_midi(midi),
+        };
+
+        if let Some(input) = input {
+            events.push(ScheduledEvent {
+                time_ms: e.time_ms,
+                duration_ms: e.duration_ms,
+                input,
+            });
+        } else {
+            warn!(
+                "No mapping for MIDI {}: skipping event at {}ms..!",
+                midi, e.time_ms
+            );
+            continue;
+        }
+    }
+
+    events
+}
+
+/// Something the playback worker does at a point on its timeline.
+#[derive(Debug, Clone, Copy)]
+enum TimelineAction {
+    /// Releases the note being held, see [`InputEngine::note_off`].
+    Release { held: Input, input: &'static Input },
+
+    /// Re-triggers the play key of the note being held, for the max sustain & vibrato.
+    Retrigger,
+
+    /// Presses the scheduled event at this index.
+    Press(usize),
+
+    /// Appends a checkpoint to the progress file, see [`Player::with_progress_file`].
+    Progress,
+}
+
+impl TimelineAction {
+    /// Actions due at the same time run in this order, so a note is released before the next one
+    /// is pressed.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Release { .. } => 0,
+            Self::Retrigger => 1,
+            Self::Press(_) => 2,
+            Self::Progress => 3,
+        }
+    }
+}
+
+/// A [`TimelineAction`] due `at_ms` after playback started. `seq` keeps actions of the same rank
+/// due at the same time in the order they were queued.
+#[derive(Debug)]
+struct TimedAction {
+    at_ms: f64,
+    seq: usize,
+    action: TimelineAction,
+}
+
+impl Ord for TimedAction {
+    // Reversed, so the max-heap in [`Timeline`] pops the earliest action first.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .at_ms
+            .total_cmp(&self.at_ms)
+            .then_with(|| other.action.rank().cmp(&self.action.rank()))
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for TimedAction {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TimedAction {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for TimedAction {}
+
+/// The playback worker's queue of key actions, ordered by when they're due.
+#[derive(Debug, Default)]
+struct Timeline {
+    actions: BinaryHeap<TimedAction>,
+    queued: usize,
+}
+
+impl Timeline {
+    fn push(&mut self, at_ms: f64, action: TimelineAction) {
+        self.actions.push(TimedAction {
+            at_ms,
+            seq: self.queued,
+            action,
+        });
+        self.queued += 1;
+    }
+
+    fn pop(&mut self) -> Option<TimedAction> {
+        self.actions.pop()
+    }
+
+    /// Whether any key is still to be pressed or released.
+    fn has_input(&self) -> bool {
+        self.actions.iter().any(|timed| {
+            matches!(
+                timed.action,
+                TimelineAction::Press(_) | TimelineAction::Release { .. }
+            )
+        })
+    }
+}
+
+/// Maps times on the schedule onto playback's own clock, which runs `factor` times as long from
+/// the last retime on.
+#[derive(Debug, Clone, Copy)]
+struct TimeMap {
+    schedule_ms: f64,
+    playback_ms: f64,
+    factor: f64,
+}
+
+impl Default for TimeMap {
+    fn default() -> Self {
+        Self {
+            schedule_ms: 0.0,
+            playback_ms: 0.0,
+            factor: 1.0,
+        }
+    }
+}
+
+impl TimeMap {
+    fn playback_at(&self, schedule_ms: f64) -> f64 {
+        self.playback_ms + (schedule_ms - self.schedule_ms) * self.factor
+    }
+
+    fn schedule_at(&self, playback_ms: f64) -> f64 {
+        self.schedule_ms + (playback_ms - self.playback_ms) / self.factor
+    }
+
+    /// Plays everything after `playback_ms` at `factor` times the schedule's timing.
+    fn retime(&mut self, playback_ms: f64, factor: f64) {
+        self.schedule_ms = self.schedule_at(playback_ms);
+        self.playback_ms = playback_ms;
+        self.factor = factor;
+    }
+}
+
+struct PlaybackWorker<E: InputEngine> {
+    engine: Arc<E>,
+    watcher: Arc<dyn WindowWatcher>,
+    window_target: WindowTarget,
+    clock: Arc<dyn Clock>,
+    ctrl_rx: Receiver<ControlMsg>,
+    delay: u64,
+    verbose: bool,
+    focus_lost: FocusLostBehavior,
+    warmup: bool,
+    window_check_interval: Duration,
+    hold_final_note: bool,
+    articulation_schedule: Option<ArticulationSchedule>,
+    play_key_offset_ms: f64,
+    catch_up: bool,
+    final_spin: Duration,
+    progress_file: Option<PathBuf>,
+    progress_interval: Duration,
+    emissions: Vec<EmissionRecord>,
+}
+
+impl<E: InputEngine> PlaybackWorker<E> {
+    fn run(&mut self, schedule: Vec<ScheduledEvent>) {
+        let engine = Arc::clone(&self.engine);
+        let delay = self.delay;
+        let verbose = self.verbose;
+
+        #[cfg(target_os = "windows")]
+        if engine.elevate_thread_priority() {
+            use windows::Win32::System::Threading::{
+                GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_HIGHEST,
+            };
+            unsafe {
+                let h = GetCurrentThread();
+                let ok = SetThreadPriority(h, THREAD_PRIORITY_HIGHEST);
+
+                if ok.is_ok() {
+                    debug!("Playback thread priority set to HIGHEST..!");
+                } else {
+                    warn!("Failed to set playback thread priority..!");
+                }
+            }
+        }
+
+        let clock = Arc::clone(&self.clock);
+        info!(
+            "Waiting at most 30 SECONDS for the active window to be {}..!",
+            self.window_target
+        );
+
+        // Maps the schedule's times onto playback's, which a tapped tempo can speed up or slow down.
+        let mut time_map = TimeMap::default();
+
+        let mut stopped = false;
+        let mut waiting_since = clock.now();
+        let waited = wait_for_window_with(
+            self.watcher.as_ref(),
+            clock.as_ref(),
+            &self.window_target,
+            WINDOW_WAIT_TIMEOUT,
+            || {
+                stopped = self.poll_control(&mut time_map, &mut waiting_since);
+                stopped
+            },
+        );
+
+        if stopped {
+            warn!("Playback stopped during active window check..!");
+            return;
+        } else if let Err(why) = waited {
+            error!("{}", why);
+            return;
+        }
+
+        let mut stamp = clock.now();
+        let mut was_ok = true;
+        let mut window_checked = clock.now();
+        info!(
+            "Active window is {}, starting playback {}..!",
+            self.window_target,
+            if delay > 0 {
+                format!("in {} seconds", delay)
+            } else {
+                "now".to_owned()
+            }
+        );
+
+        if delay > 0 {
+            clock.sleep(Duration::from_secs(delay));
+        }
+
+        if self.warmup {
+            let play_input = Input {
+                keys: std::slice::from_ref(engine.play_key()),
+                note_label: "play_key",
+            };
+
+            debug!("Warming up input with a lone play key tap..!");
+            let warmup = engine.key_down(&play_input).and_then(|_| {
+                engine.sleep(Duration::from_millis(1));
+                engine.key_up(&play_input)
+            });
+
+            if let Err(why) = warmup {
+                warn!("Warm-up input failed: {:?}", why);
+            }
+
+            engine.sleep(Duration::from_millis(WARMUP_SETTLE_MS));
+        }
+
+        let mut start = clock.now();
+        let mut released: Option<(&Input, Instant)> = None;
+
+        // When the note being held is due to be released, if any. The flute only plays one note at
+        // a time, so no other note is pressed until then.
+        let mut held_until_ms: Option<f64> = None;
+
+        // How long the last release took to send, so the next one can be sent that much earlier.
+        let mut release_overhead_ms = 0.0;
+
+        // Events scheduled before zero (e.g. pulled early by calibration, the play key offset or the
+        // key latency) shift the whole timeline so that the earliest one plays as soon as playback
+        // starts, keeping their spacing intact.
+        let offset_ms = self.play_key_offset_ms + engine.key_registration_latency_ms();
+        let lead_in_ms = schedule
+            .first()
+            .map_or(0.0, |event| (offset_ms - event.time_ms).max(0.0));
+        let target_ms = |event: &ScheduledEvent| event.time_ms + lead_in_ms - offset_ms;
+        const MAX_SLEEP_CHUNK_S: f64 = 0.050;
+
+        // Each press schedules its own release (& any re-triggers) on the same timeline, so a long
+        // hold never keeps the worker from dispatching whatever comes next.
+        let mut timeline = Timeline::default();
+        for (i, event) in schedule.iter().enumerate() {
+            timeline.push(target_ms(event), TimelineAction::Press(i));
+        }
+
+        // Each checkpoint queues the next for as long as there's a key left to press or release,
+        // & a final one is written once playback completes.
+        let progress_interval_ms = self.progress_interval.as_secs_f64() * 1000.0;
+        let mut next_checkpoint_ms = progress_interval_ms;
+        if self.progress_file.is_some() && progress_interval_ms > 0.0 {
+            timeline.push(next_checkpoint_ms, TimelineAction::Progress);
+        }
+
+        let playback_ms = |start: Instant| clock.elapsed_since(start).as_secs_f64() * 1000.0;
+
+        while let Some(TimedAction { at_ms, action, .. }) = timeline.pop() {
+            if self.poll_control(&mut time_map, &mut start) {
+                engine.all_keys_up().expect("Error cancelling input..!");
+                warn!(
+                    "Playback stopped via control message after {} seconds..!",
+                    clock.elapsed_since(start).as_secs()
+                );
+                return;
+            }
+
+            // A checkpoint due after the last release is left to the final one.
+            if let TimelineAction::Progress = action
+                && !timeline.has_input()
+            {
+                continue;
+            }
+
+            if let TimelineAction::Press(_) = action
+                && let Some(until_ms) = held_until_ms
+                && until_ms > at_ms
+            {
+                timeline.push(until_ms, action);
+                continue;
+            }
+
+            let target_at = |start: Instant, time_map: &TimeMap| {
+                start + Duration::from_secs_f64(time_map.playback_at(at_ms).max(0.0) / 1000.0)
+            };
+
+            // Sleep until the final approach, leaving the rest to a spin right before acting.
+            loop {
+                if self.poll_control(&mut time_map, &mut start) {
+                    engine.all_keys_up().expect("Error cancelling input..!");
+                    warn!("Playback stopped during wait..!");
+                    return;
+                }
+
+                let target = target_at(start, &time_map);
+                let now = clock.now();
+                if now + self.final_spin >= target {
+                    break;
+                }
+                let remaining = (target - self.final_spin - now).as_secs_f64();
+
+                let chunk = if remaining > MAX_SLEEP_CHUNK_S {
+                    MAX_SLEEP_CHUNK_S
+                } else {
+                    remaining
+                };
+
+                clock.sleep(Duration::from_secs_f64(chunk));
+            }
+
+            let i = match action {
+                TimelineAction::Release { held, input } => {
+                    clock.spin_until(target_at(start, &time_map));
+
+                    let releasing_at = clock.now();
+                    if let Err(why) = engine.note_off(&held) {
+                        warn!(
+                            "Input error releasing {} | why: {:?}",
+                            input.note_label, why
+                        );
+                    }
+
+                    release_overhead_ms = clock.elapsed_since(releasing_at).as_secs_f64() * 1000.0;
+                    released = Some((input, clock.now()));
+                    held_until_ms = None;
+                    continue;
+                }
+                TimelineAction::Retrigger => {
+                    clock.spin_until(target_at(start, &time_map));
+
+                    if let Err(why) = engine.retrigger() {
+                        warn!("Input error re-triggering the play key | why: {:?}", why);
+                    }
+                    continue;
+                }
+                TimelineAction::Progress => {
+                    clock.spin_until(target_at(start, &time_map));
+                    self.write_progress(schedule.len(), clock.elapsed_since(start));
+
+                    if timeline.has_input() {
+                        next_checkpoint_ms += progress_interval_ms;
+                        timeline.push(
+                            time_map.schedule_at(next_checkpoint_ms),
+                            TimelineAction::Progress,
+                        );
+                    }
+                    continue;
+                }
+                TimelineAction::Press(i) => i,
+            };
+            let event = &schedule[i];
+
+            loop {
+                if self.poll_control(&mut time_map, &mut start) {
+                    engine.all_keys_up().expect("Error cancelling input..!");
+                    warn!("Playback stopped during active window check..!");
+                    return;
+                }
+
+                // Focus was confirmed recently enough to trust it for this event too.
+                if was_ok && clock.elapsed_since(window_checked) < self.window_check_interval {
+                    break;
+                }
+
+                // On errors, retry after the usual poll delay via the stop check above, so the
+                // worker can always be stopped even if the window can never be queried.
+                let active = match self.watcher.active_window() {
+                    Ok(active) => active,
+                    Err(why) => {
+                        debug!("{}", why);
+                        clock.sleep(WINDOW_POLL_INTERVAL);
+                        continue;
+                    }
+                };
+
+                if self.window_target.matches(&active) {
+                    window_checked = clock.now();
+
+                    if !was_ok && self.focus_lost == FocusLostBehavior::PauseTimeline {
+                        let paused = clock.elapsed_since(stamp);
+                        start += paused;
+                        info!(
+                            "Focus regained, resuming the timeline after {:.3}ms..!",
+                            paused.as_secs_f64() * 1000.0
+                        );
+                    }
+
+                    was_ok = true;
+                    break;
+                } else {
+                    if was_ok {
+                        stamp = clock.now();
+                        engine.all_keys_up().expect("Error cancelling input..!");
+
+                        if self.focus_lost == FocusLostBehavior::StopPlayback {
+                            warn!(
+                                "Active window is no longer {}, stopping playback..!",
+                                self.window_target
+                            );
+                            return;
+                        }
+                    }
+                    was_ok = false;
+                    if clock.elapsed_since(stamp) > WINDOW_WAIT_TIMEOUT {
+                        engine.all_keys_up().expect("Error cancelling input..!");
+                        error!(
+                            "Active window was never {}..! (waited {} seconds.)",
+                            self.window_target,
+                            WINDOW_WAIT_TIMEOUT.as_secs_f64()
+                        );
+                        return;
+                    }
+                }
+
+                clock.sleep(WINDOW_POLL_INTERVAL);
+            }
+
+            // Pausing the timeline for lost focus moves the target along with the start.
+            clock.spin_until(target_at(start, &time_map));
+
+            let emit_time = clock.now();
+            let emitted_at_ms = emit_time.duration_since(start).as_secs_f64() * 1000.0;
+
+            if verbose {
+                let info = format!("Sending inputs for {} ", event.input.note_label);
+                info!(
+                    "{:30} | at {:>13.3}ms | scheduled for: {:>13.3}ms | duration: {:>9.3}ms",
+                    info, emitted_at_ms, event.time_ms, event.duration_ms
+                );
+            }
+
+            self.emissions.push(EmissionRecord {
+                scheduled_ms: event.time_ms,
+                emitted_ms: emitted_at_ms,
+                duration_ms: event.duration_ms,
+                input: event.input,
+            });
+
+            // Never let a hold run into the next event's start, e.g. during a crossfade.
+            let mut hold_ms = match schedule.get(i + 1) {
+                Some(next) if next.time_ms < event.time_ms + event.duration_ms => {
+                    (next.time_ms - event.time_ms).max(1.0)
+                }
+                _ => event.duration_ms,
+            };
+
+            // Leave room for the keys that change before the next note without delaying it.
+            if let Some(next) = schedule.get(i + 1)
+                && shares_changed_keys(event.input, next.input)
+                && next.time_ms - (event.time_ms + hold_ms) < CHANGED_KEY_RELEASE_MS
+            {
+                hold_ms = (hold_ms - CHANGED_KEY_RELEASE_MS).max(1.0);
+            }
+
+            let articulation = self
+                .articulation_schedule
+                .as_ref()
+                .and_then(|sections| sections.factor_at(event.time_ms))
+                .unwrap_or_else(|| engine.get_articulation());
+
+            let pressed = released
+                .map(|(previous, released_at)| {
+                    engine.release_changed_keys(
+                        previous,
+                        clock.elapsed_since(released_at),
+                        event.input,
+                    )
+                })
+                .unwrap_or(Ok(()))
+                .and_then(|_| engine.note_on(event.input));
+            let held = pressed.unwrap_or_else(|why| {
+                warn!(
+                    "Input error for {} at {:.3}ms | why: {:?}",
+                    event.input.note_label, emitted_at_ms, why
+                );
+                *event.input
+            });
+
+            if self.hold_final_note && i + 1 == schedule.len() {
+                self.hold_until_stopped(event.input);
+                return;
+            }
+
+            let pressed_ms = playback_ms(start);
+            let hold_ms = hold_ms * time_map.factor;
+            let mut sounding_ms = engine.articulated_hold_ms(hold_ms, articulation);
+
+            // A hold floored past the note's value may use the gap before the next note, but
+            // never delay it, leaving room for any keys that change to be released.
+            if sounding_ms > hold_ms
+                && let Some(next) = schedule.get(i + 1)
+            {
+                let changed_release_ms = if shares_changed_keys(event.input, next.input) {
+                    CHANGED_KEY_RELEASE_MS
+                } else {
+                    0.0
+                };
+                let until_next_ms = time_map.playback_at(target_ms(next))
+                    - pressed_ms
+                    - release_overhead_ms
+                    - changed_release_ms;
+                sounding_ms = sounding_ms.min(until_next_ms.max(hold_ms));
+            }
+
+            // When running behind, shorten the hold so that overdue events are played back to back
+            // until playback has caught up, rather than skipped or left to drift. Releasing takes
+            // a moment of its own, so leave room for that too.
+            if let Some(next) = schedule.get(i + 1)
+                && self.catch_up
+            {
+                let until_next_ms =
+                    time_map.playback_at(target_ms(next)) - pressed_ms - release_overhead_ms;
+
+                if until_next_ms < sounding_ms {
+                    sounding_ms = until_next_ms.max(MIN_CATCH_UP_HOLD_MS.min(sounding_ms));
+                }
+            }
+
+            if let Some(retrigger_ms) = engine.retrigger_interval_ms(hold_ms) {
+                let mut retrigger_at_ms = pressed_ms;
+                let mut remaining_ms = sounding_ms;
+
+                while remaining_ms > retrigger_ms {
+                    retrigger_at_ms += retrigger_ms;
+                    timeline.push(
+                        time_map.schedule_at(retrigger_at_ms),
+                        TimelineAction::Retrigger,
+                    );
+
+                    retrigger_at_ms += RETRIGGER_GAP_MS;
+                    remaining_ms -= retrigger_ms + RETRIGGER_GAP_MS;
+                }
+            }
+
+            let release_ms = time_map.schedule_at(pressed_ms + sounding_ms);
+            timeline.push(
+                release_ms,
+                TimelineAction::Release {
+                    held,
+                    input: event.input,
+                },
+            );
+            held_until_ms = Some(release_ms);
+        }
+
+        // Leave nothing held or latched behind, e.g. a tapped modifier or a key still settling.
+        if let Err(why) = engine.all_keys_up() {
+            warn!("Failed to release every key after playback: {:?}", why);
+        }
+
+        self.write_progress(schedule.len(), clock.elapsed_since(start));
+        info!("Playback thread finished all events..!");
+    }
+
+    /// Appends a checkpoint to the progress file, if there is one.
+    fn write_progress(&self, total: usize, elapsed: Duration) {
+        if let Some(path) = &self.progress_file
+            && let Err(why) = append_progress(path, self.emissions.len(), total, elapsed)
+        {
+            warn!("Failed to write a progress checkpoint: {:?}", why);
+        }
+    }
+
+    /// Drains the control messages sent so far, retiming the rest of playback from now on. A pause
+    /// blocks until playback is resumed, then moves `start` along by however long it lasted.
+    /// Returns whether playback was asked to stop.
+    fn poll_control(&self, time_map: &mut TimeMap, start: &mut Instant) -> bool {
+        while let Ok(msg) = self.ctrl_rx.try_recv() {
+            let playback_ms = self.clock.elapsed_since(*start).as_secs_f64() * 1000.0;
+
+            match msg {
+                ControlMsg::Stop => return true,
+                ControlMsg::Retime(factor) => {
+                    time_map.retime(playback_ms, factor);
+                    debug!(
+                        "Retimed playback to {:.3}x the schedule at {:.3}ms..!",
+                        factor, playback_ms
+                    );
+                }
+                ControlMsg::Pause => {
+                    let paused_at = self.clock.now();
+                    if let Err(why) = self.engine.all_keys_up() {
+                        warn!("Failed to release every key for the pause: {:?}", why);
+                    }
+                    info!("Playback paused at {:.3}ms..!", playback_ms);
+
+                    loop {
+                        match self.ctrl_rx.recv() {
+                            Ok(ControlMsg::Resume) => break,
+                            Ok(ControlMsg::Retime(factor)) => time_map.retime(playback_ms, factor),
+                            Ok(ControlMsg::Pause) => {}
+                            Ok(ControlMsg::Stop) | Err(_) => return true,
+                        }
+                    }
+
+                    let paused = self.clock.elapsed_since(paused_at);
+                    *start += paused;
+                    info!(
+                        "Resuming playback after {:.3}ms..!",
+                        paused.as_secs_f64() * 1000.0
+                    );
+                }
+                ControlMsg::Resume => {}
+            }
+        }
+
+        false
+    }
+
+    /// Keeps the final note, already pressed for `input`, held until a stop message arrives (or
+    /// the player goes away), then releases every key. Pausing releases it until resumed.
+    fn hold_until_stopped(&self, input: &Input) {
+        info!(
+            "Holding the final note {} until playback is stopped..!",
+            input.note_label
+        );
+
+        let mut paused = false;
+        loop {
+            match self.ctrl_rx.recv() {
+                Ok(ControlMsg::Pause) if !paused => {
+                    if let Err(why) = self.engine.all_keys_up() {
+                        warn!("Failed to release every key for the pause: {:?}", why);
+                    }
+                    info!("Playback paused on the final note..!");
+                    paused = true;
+                }
+                Ok(ControlMsg::Resume) if paused => {
+                    if let Err(why) = self.engine.note_on(input) {
+                        warn!(
+                            "Input error holding the final note {} | why: {:?}",
+                            input.note_label, why
+                        );
+                    }
+                    info!("Holding the final note {} again..!", input.note_label);
+                    paused = false;
+                }
+                Ok(ControlMsg::Stop) | Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+
+        self.engine
+            .all_keys_up()
+            .expect("Error cancelling input..!");
+        info!("Released the final note..!");
+    }
+}
+
+/// Writes one CSV line per emitted event: scheduled time, actual time, duration, note & key codes.
+fn write_recording(path: &Path, emissions: &[EmissionRecord]) -> anyhow::Result<()> {
+    let mut out = String::from("scheduled_ms,emitted_ms,duration_ms,note,keys\n");
+
+    for record in emissions {
+        let keys = record
+            .input
+            .keys
+            .iter()
+            .map(|vk| vk.0.to_string())
+            .collect::<Vec<_>>()
+            .join("+");
+
+        out.push_str(&format!(
+            "{:.3},{:.3},{:.3},{},{}\n",
+            record.scheduled_ms,
+            record.emitted_ms,
+            record.duration_ms,
+            record.input.note_label,
+            keys
+        ));
+    }
+
+    fs::write(path, out)?;
+    Ok(())
+}
+
+/// Appends a line like `120/450 events, 00:02:15 elapsed` to the progress file.
+fn append_progress(
+    path: &Path,
+    played: usize,
+    total: usize,
+    elapsed: Duration,
+) -> anyhow::Result<()> {
+    let seconds = elapsed.as_secs();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    writeln!(
+        file,
+        "{}/{} events, {:02}:{:02}:{:02} elapsed",
+        played,
+        total,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )?;
+    Ok(())
+}
+
+/// Reads a recording written by [`Player::with_recording`] back as events at the times they were
+/// actually emitted, to reproduce a run with [`Player::play_recording`].
+pub fn load_recording<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<ScheduledEvent>> {
+    let contents = fs::read_to_string(path.as_ref()).map_err(|e| {
+        anyhow!(
+            "Failed to read recording from {}: {}",
+            path.as_ref().display(),
+            e
+        )
+    })?;
+
+    let mut events = Vec::new();
+    for (i, line) in contents.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let malformed = || anyhow!("Malformed recording line {}: '{}'..!", i + 1, line);
+
+        // Custom note labels may hold commas of their own, so the keys are taken from the end.
+        let mut fields = line.splitn(4, ',');
+        let (Some(_scheduled), Some(emitted), Some(duration), Some(rest)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(malformed());
+        };
+        let Some((_note, keys)) = rest.rsplit_once(',') else {
+            return Err(malformed());
+        };
+
+        let codes = keys
+            .split('+')
+            .map(|code| code.parse::<u16>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| malformed())?;
+
+        let input = active_mappings()
+            .iter()
+            .map(|(_, input)| input)
+            .find(|input| input.keys.iter().map(|vk| vk.0).eq(codes.iter().copied()))
+            .unwrap_or_else(|| input_for_keys(&codes));
+
+        events.push(ScheduledEvent {
+            time_ms: emitted.parse().map_err(|_| malformed())?,
+            duration_ms: duration.parse().map_err(|_| malformed())?,
+            input,
+        });
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::ControlMsg;
+    use crate::window::WINDOW_POLL_INTERVAL;
+    use crate::{ArticulationRange, ArticulationSchedule, input_for_midi, key_by_name, schedule_for_song, Clock, Event, Input, MockClock, FixedWindowWatcher, FocusLostBehavior, InputEngine, KeyAction, Metadata, Note, Player, RecordedInput, RecordingEngine, Song, ValidationReport, WindowWatcher, GAME_WINDOW_TITLE, PLAY_KEY, PLAY_KEY_SETTLE_MS, ModifierToggles, modifier_keys, load_recording, DEFAULT_FINAL_SPIN};
+    #[cfg(target_os = "windows")]
+    use crate::{DefaultInputEngine, PolyPolicy, import_midi_file, util::ensure_active_window};
+    #[cfg(target_os = "windows")]
+    use log::warn;
+
+    /// Reports the game window, except for `lost_for` starting at the `lost_at_call`th query.
+    #[derive(Debug)]
+    struct FlakyWatcher {
+        lost_at_call: usize,
+        lost_for: Duration,
+        calls: Mutex<(usize, Option<Instant>)>,
+    }
+
+    impl FlakyWatcher {
+        fn new(lost_at_call: usize, lost_for: Duration) -> Self {
+            Self {
+                lost_at_call,
+                lost_for,
+                calls: Mutex::new((0, None)),
+            }
+        }
+    }
+
+    /// Plays on a [`MockClock`], recording how far into the virtual timeline each note started &
+    /// stopped sounding.
+    #[derive(Debug)]
+    struct ClockedEngine {
+        clock: Arc<MockClock>,
+        articulation: AtomicU64,
+        onsets: Mutex<Vec<Duration>>,
+        releases: Mutex<Vec<Duration>>,
+        min_playable_hold_ms: f64,
+        key_latency_ms: f64,
+        /// How long each key batch takes to send, in virtual time.
+        input_delay: Duration,
+    }
+
+    impl ClockedEngine {
+        fn new(clock: Arc<MockClock>) -> Self {
+            Self {
+                clock,
+                articulation: AtomicU64::new(1.0f64.to_bits()),
+                onsets: Mutex::new(Vec::new()),
+                releases: Mutex::new(Vec::new()),
+                min_playable_hold_ms: 0.0,
+                key_latency_ms: 0.0,
+                input_delay: Duration::ZERO,
+            }
+        }
+    }
+
+    impl InputEngine for ClockedEngine {
+        fn get_articulation(&self) -> f64 {
+            f64::from_bits(self.articulation.load(Ordering::SeqCst))
+        }
+
+        fn set_articulation(&self, articulation: f64) {
+            self.articulation
+                .store(articulation.to_bits(), Ordering::SeqCst);
+        }
+
+        fn min_playable_hold_ms(&self) -> f64 {
+            self.min_playable_hold_ms
+        }
+
+        fn key_registration_latency_ms(&self) -> f64 {
+            self.key_latency_ms
+        }
+
+        fn sleep(&self, duration_ms: Duration) {
+            self.clock.sleep(duration_ms);
+        }
+
+        fn key_up(&self, input: &Input) -> anyhow::Result<()> {
+            self.clock.advance(self.input_delay);
+            if input.keys == [PLAY_KEY] {
+                self.releases.lock().unwrap().push(self.clock.elapsed());
+            }
+            Ok(())
+        }
+
+        fn key_down(&self, input: &Input) -> anyhow::Result<()> {
+            self.clock.advance(self.input_delay);
+            if input.keys == [PLAY_KEY] {
+                self.onsets.lock().unwrap().push(self.clock.elapsed());
+            }
+            Ok(())
+        }
+    }
+
+    /// A virtual clock whose sleeps wake up on the next whole millisecond, like a coarse OS timer,
+    /// while spinning still lands exactly on the deadline.
+    #[derive(Debug)]
+    struct CoarseClock(Arc<MockClock>);
+
+    impl Clock for CoarseClock {
+        fn now(&self) -> Instant {
+            self.0.now()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            let ms = (duration.as_secs_f64() * 1000.0).ceil();
+            self.0.advance(Duration::from_secs_f64(ms / 1000.0));
+        }
+
+        fn spin_until(&self, deadline: Instant) {
+            self.0.spin_until(deadline);
+        }
+    }
+
+    /// Records every key batch like a [`RecordingEngine`], with modifiers under
+    /// [`ModifierStyle::Tap`](crate::ModifierStyle::Tap).
+    #[derive(Debug)]
+    struct TapEngine {
+        inner: RecordingEngine,
+        toggles: ModifierToggles,
+    }
+
+    impl Default for TapEngine {
+        fn default() -> Self {
+            Self {
+                inner: RecordingEngine::new(1.0),
+                toggles: ModifierToggles::default(),
+            }
+        }
+    }
+
+    impl InputEngine for TapEngine {
+        fn get_articulation(&self) -> f64 {
+            1.0
+        }
+
+        fn modifier_toggles(&self) -> Option<&ModifierToggles> {
+            Some(&self.toggles)
+        }
+
+        fn sleep(&self, duration_ms: Duration) {
+            self.inner.sleep(duration_ms);
+        }
+
+        fn key_up(&self, input: &Input) -> anyhow::Result<()> {
+            self.inner.key_up(input)
+        }
+
+        fn key_down(&self, input: &Input) -> anyhow::Result<()> {
+            self.inner.key_down(input)
+        }
+    }
+
+    /// Records the articulation each note is played with, without pressing anything.
+    #[derive(Debug, Default)]
+    struct ArticulationEngine {
+        articulations: Mutex<Vec<f64>>,
+    }
+
+    impl InputEngine for ArticulationEngine {
+        fn get_articulation(&self) -> f64 {
+            0.75
+        }
+
+        fn set_articulation(&self, _articulation: f64) {}
+
+        fn sleep(&self, duration_ms: Duration) {
+            thread::sleep(duration_ms);
+        }
+
+        fn key_up(&self, _input: &Input) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn key_down(&self, _input: &Input) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn articulated_hold_ms(&self, hold_ms: f64, articulation: f64) -> f64 {
+            self.articulations.lock().unwrap().push(articulation);
+            hold_ms
+        }
+    }
+
+    /// Never manages to report the active window.
+    #[derive(Debug)]
+    struct FailingWatcher;
+
+    impl WindowWatcher for FailingWatcher {
+        fn active_window_title(&self) -> anyhow::Result<String> {
+            anyhow::bail!("Failed to query the active window..!")
+        }
+    }
+
+    /// Panics on the first query, taking the playback worker down with it.
+    #[derive(Debug)]
+    struct PanickingWatcher;
+
+    impl WindowWatcher for PanickingWatcher {
+        fn active_window_title(&self) -> anyhow::Result<String> {
+            panic!("The window watcher fell over..!")
+        }
+    }
+
+    /// Reports the game window for the first `ok_for` queries, then fails every one after.
+    #[derive(Debug)]
+    struct VanishingWatcher {
+        ok_for: usize,
+        queries: Arc<AtomicUsize>,
+    }
+
+    impl WindowWatcher for VanishingWatcher {
+        fn active_window_title(&self) -> anyhow::Result<String> {
+            if self.queries.fetch_add(1, Ordering::SeqCst) < self.ok_for {
+                Ok(GAME_WINDOW_TITLE.to_owned())
+            } else {
+                anyhow::bail!("Failed to query the active window..!")
+            }
+        }
+    }
+
+    /// Reports the game window, but holds the worker at each query in `hold_at` until the test has
+    /// met it at the gate twice: once to know the worker is there, & again to let it carry on.
+    #[derive(Debug)]
+    struct GatedWatcher {
+        hold_at: Vec<usize>,
+        queries: AtomicUsize,
+        gate: Arc<Barrier>,
+        fails: bool,
+    }
+
+    impl GatedWatcher {
+        fn new(hold_at: &[usize]) -> Self {
+            Self {
+                hold_at: hold_at.to_vec(),
+                queries: AtomicUsize::new(0),
+                gate: Arc::new(Barrier::new(2)),
+                fails: false,
+            }
+        }
+
+        /// Fails every query instead of reporting the game window.
+        fn failing(hold_at: &[usize]) -> Self {
+            Self {
+                fails: true,
+                ..Self::new(hold_at)
+            }
+        }
+    }
+
+    impl WindowWatcher for GatedWatcher {
+        fn active_window_title(&self) -> anyhow::Result<String> {
+            if self
+                .hold_at
+                .contains(&self.queries.fetch_add(1, Ordering::SeqCst))
+            {
+                self.gate.wait();
+                self.gate.wait();
+            }
+
+            if self.fails {
+                anyhow::bail!("Failed to query the active window..!")
+            }
+            Ok(GAME_WINDOW_TITLE.to_owned())
+        }
+    }
+
+    /// Always reports the game window, counting how often it was asked.
+    #[derive(Debug, Default)]
+    struct CountingWatcher {
+        queries: Arc<AtomicUsize>,
+    }
+
+    impl WindowWatcher for CountingWatcher {
+        fn active_window_title(&self) -> anyhow::Result<String> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            Ok(GAME_WINDOW_TITLE.to_owned())
+        }
+    }
+
+    impl WindowWatcher for FlakyWatcher {
+        fn active_window_title(&self) -> anyhow::Result<String> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.0 += 1;
+
+            if calls.0 == self.lost_at_call {
+                calls.1 = Some(Instant::now());
+            }
+
+            match calls.1 {
+                Some(lost) if lost.elapsed() < self.lost_for => Ok(String::from("Desktop")),
+                _ => Ok(GAME_WINDOW_TITLE.to_owned()),
+            }
+        }
+    }
+
+    fn song_from_notes(notes: &[(u8, f64, f64)]) -> Song {
+        Song {
+            metadata: Metadata {
+                title: Some(String::from("Test Song")),
+                tempo_bpm: None,
+                markers: Vec::new(),
+            },
+            events: notes
+                .iter()
+                .map(|&(midi, time_ms, duration_ms)| Event {
+                    note: Note {
+                        midi,
+                        velocity: 100,
+                    },
+                    time_ms,
+                    duration_ms,
+                    override_keys: None,
+                })
+                .collect(),
+        }
+    }
+
+    // Plays into the game through the Windows engine, so it needs ANIMAL WELL focused.
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn mimic_cuckoo_clock() {
+        env_logger::try_init().unwrap_or(());
+
+        let art = 0.75;
+        let engine = DefaultInputEngine::new(art);
+
+        const E6: u8 = 88;
+        const CS6: u8 = 85;
+        const REST_MS: f64 = 1000.0;
+        const DURATION_MS: f64 = 200.0;
+        const NOTE_GROUPS: &[&[u8]] = &[
+            &[E6, E6, CS6],
+            &[E6, E6, CS6],
+            &[E6, E6, CS6],
+            &[E6, E6, CS6],
+            &[E6, E6],
+        ];
+
+        let mut time_ms = 0.0;
+        let mut raw_events: Vec<(u8, f64)> = Vec::new();
+        for (n, group) in NOTE_GROUPS.iter().enumerate() {
+            if n > 0 {
+                time_ms += REST_MS;
+            }
+
+            for &midi in *group {
+                raw_events.push((midi, time_ms));
+                time_ms += DURATION_MS;
+            }
+        }
+
+        let song = Song {
+            metadata: Metadata {
+                title: Some(String::from("Cuckoo Clock")),
+                tempo_bpm: None,
+                markers: Vec::new(),
+            },
+            events: raw_events
+                .iter()
                 .map(|&(midi, start_time_ms)| Event {
                     note: Note {
                         midi,
@@ -375,41 +1879,1384 @@ mod test {
                     },
                     time_ms: start_time_ms,
                     duration_ms: DURATION_MS,
+                    override_keys: None,
                 })
                 .collect(),
         };
 
-        let player = Player::new(engine, true, 0);
+        let player = Player::new(engine, true, 0);
+
+        ensure_active_window();
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+    }
+
+    // Plays into the game through the Windows engine, so it needs ANIMAL WELL focused.
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn play_from_midi_file() {
+        env_logger::try_init().unwrap_or(());
+
+        let song = import_midi_file(
+            "./resources/songs/Twinkle_Twinkle_Little_Star.mid",
+            0,
+            PolyPolicy::Highest,
+            false,
+            Some((69, 93)),
+        );
+
+        if song.is_err() {
+            warn!("{:?}", song);
+        }
+
+        assert!(song.is_ok());
+
+        let art = 0.55;
+        let engine = DefaultInputEngine::new(art);
+        let player = Player::new(engine, true, 0);
+
+        ensure_active_window();
+        assert!(player.load_song(song.unwrap()).is_ok());
+        assert!(player.play(true).is_ok());
+    }
+
+    #[test]
+    fn progress_checkpoints_on_virtual_time() {
+        env_logger::try_init().unwrap_or(());
+
+        let path = std::env::temp_dir().join(format!(
+            "flute_well_progress_checkpoints_{}.log",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let player = Player::new_for_test(
+            RecordingEngine::new(1.0),
+            FixedWindowWatcher::game(),
+            MockClock::new(),
+        )
+        .with_progress_file(&path, Duration::from_secs(2));
+
+        let song = song_from_notes(&[
+            (69, 0.0, 500.0),
+            (71, 1500.0, 500.0),
+            (73, 3000.0, 500.0),
+            (74, 4500.0, 500.0),
+        ]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        let contents = fs::read_to_string(&path).expect("Progress should have been written..!");
+        let _ = fs::remove_file(&path);
+
+        // The last checkpoint is written as the final note is released at 5s, rather than at 6s.
+        assert_eq!(
+            contents.lines().collect::<Vec<_>>(),
+            vec![
+                "2/4 events, 00:00:02 elapsed",
+                "3/4 events, 00:00:04 elapsed",
+                "4/4 events, 00:00:05 elapsed",
+            ]
+        );
+    }
+
+    #[test]
+    fn record_emissions_to_file() {
+        env_logger::try_init().unwrap_or(());
+
+        let path = std::env::temp_dir().join(format!(
+            "flute_well_record_emissions_{}.csv",
+            std::process::id()
+        ));
+        let clock = Arc::new(MockClock::new());
+        let engine = RecordingEngine::new(1.0)
+            .with_input_delay(Duration::from_millis(3))
+            .with_clock(Arc::clone(&clock));
+        let player =
+            Player::new_for_test(engine, FixedWindowWatcher::game(), clock).with_recording(&path);
+
+        let scheduled = [0.0, 40.0, 80.0];
+        let song = song_from_notes(&[
+            (69, scheduled[0], 30.0),
+            (71, scheduled[1], 30.0),
+            (73, scheduled[2], 30.0),
+        ]);
+
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        let contents = fs::read_to_string(&path).expect("Recording should have been written..!");
+        let _ = fs::remove_file(&path);
+
+        let lines = contents.lines().skip(1).collect::<Vec<_>>();
+        assert_eq!(lines.len(), scheduled.len());
+
+        for (line, scheduled_ms) in lines.iter().zip(scheduled) {
+            let fields = line.split(',').collect::<Vec<_>>();
+            let recorded_scheduled_ms: f64 = fields[0].parse().unwrap();
+            let emitted_ms: f64 = fields[1].parse().unwrap();
+
+            assert_eq!(recorded_scheduled_ms, scheduled_ms);
+            assert!(emitted_ms >= scheduled_ms);
+        }
+    }
+
+    #[test]
+    fn replay_recorded_run() {
+        env_logger::try_init().unwrap_or(());
+
+        let dir = std::env::temp_dir();
+        let first_path = dir.join(format!(
+            "flute_well_replay_first_{}.csv",
+            std::process::id()
+        ));
+        let second_path = dir.join(format!(
+            "flute_well_replay_second_{}.csv",
+            std::process::id()
+        ));
+
+        let clock = Arc::new(MockClock::new());
+        let player = Player::new_for_test(
+            RecordingEngine::new(1.0).with_clock(Arc::clone(&clock)),
+            FixedWindowWatcher::game(),
+            Arc::clone(&clock),
+        )
+        .with_recording(&first_path);
+        let song = song_from_notes(&[(69, 0.0, 30.0), (76, 40.0, 30.0), (73, 80.0, 30.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        let recorded = load_recording(&first_path).expect("Recording should load..!");
+        let clock = Arc::new(MockClock::new());
+        let replayer = Player::new_for_test(
+            RecordingEngine::new(1.0).with_clock(Arc::clone(&clock)),
+            FixedWindowWatcher::game(),
+            clock,
+        )
+        .with_recording(&second_path);
+        assert!(replayer.play_recording(recorded.clone(), true).is_ok());
+
+        let first = fs::read_to_string(&first_path).unwrap();
+        let second = fs::read_to_string(&second_path).unwrap();
+        let _ = fs::remove_file(&first_path);
+        let _ = fs::remove_file(&second_path);
+
+        // The replay is scheduled for exactly when the first run emitted each event.
+        let emitted = |contents: &str, field: usize| {
+            contents
+                .lines()
+                .skip(1)
+                .map(|line| line.split(',').nth(field).unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(emitted(&second, 0), emitted(&first, 1));
+        assert_eq!(emitted(&second, 3), emitted(&first, 3));
+
+        let sent = |player: &Player<RecordingEngine>| {
+            player
+                .engine()
+                .recorded()
+                .into_iter()
+                .map(|input| (input.action, input.keys))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sent(&replayer), sent(&player));
+    }
+
+    #[test]
+    fn replay_after_completion() {
+        env_logger::try_init().unwrap_or(());
+
+        let clock = Arc::new(MockClock::new());
+        let player = Player::new_for_test(
+            RecordingEngine::new(1.0).with_clock(Arc::clone(&clock)),
+            FixedWindowWatcher::game(),
+            clock,
+        );
+        let song = song_from_notes(&[(69, 0.0, 20.0), (71, 25.0, 20.0)]);
+        assert!(player.load_song(song).is_ok());
+
+        assert!(player.play(true).is_ok());
+        assert!(player.play(true).is_ok());
+
+        // The finished worker is only reaped by the next play.
+        for _ in 0..2 {
+            assert!(player.play(false).is_ok());
+
+            let waiting = Instant::now();
+            while player.is_busy() && waiting.elapsed() < Duration::from_secs(5) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            assert!(!player.is_busy());
+        }
+        assert!(player.reset().is_ok());
+
+        let play_presses = player
+            .engine()
+            .recorded()
+            .iter()
+            .filter(|input| input.action == KeyAction::Down && input.keys == [PLAY_KEY])
+            .count();
+        assert_eq!(play_presses, 8);
+    }
+
+    #[test]
+    fn crossfade_playlist_overlaps_tails() {
+        env_logger::try_init().unwrap_or(());
+
+        const CROSSFADE_MS: f64 = 30.0;
+        let first = song_from_notes(&[(69, 0.0, 50.0), (71, 60.0, 50.0)]);
+        let second = song_from_notes(&[(73, 0.0, 50.0)]);
+
+        // How far into playback each input was sent, from the first play key press.
+        let play = |songs: Vec<Song>| {
+            let clock = Arc::new(MockClock::new());
+            let player = Player::new_for_test(
+                RecordingEngine::new(1.0).with_clock(Arc::clone(&clock)),
+                FixedWindowWatcher::game(),
+                clock,
+            )
+            .with_crossfade(CROSSFADE_MS);
+            assert!(player.load_playlist(songs).is_ok());
+            assert!(player.play(true).is_ok());
+
+            let recorded = player.engine().recorded();
+            let start = recorded
+                .iter()
+                .find(|input| input.action == KeyAction::Down && input.keys == [PLAY_KEY])
+                .map(|input| input.at)
+                .expect("Playback should press the play key..!");
+
+            recorded
+                .into_iter()
+                .map(|input| {
+                    let at_ms = input.at.saturating_duration_since(start).as_secs_f64() * 1000.0;
+                    (input, at_ms)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Played on its own, the first song's tail ends with the release of its last note, just
+        // before every key is released again as playback completes.
+        let last_keys = input_for_midi(71).unwrap().keys;
+        let first_release_ms = play(vec![first.clone()])
+            .into_iter()
+            .find(|(input, _)| input.action == KeyAction::Up && input.keys == last_keys)
+            .map(|(_, at_ms)| at_ms)
+            .expect("The first song's last note should be released..!");
+
+        let onsets = play(vec![first, second])
+            .into_iter()
+            .filter(|(input, _)| input.action == KeyAction::Down && input.keys == [PLAY_KEY])
+            .map(|(_, at_ms)| at_ms)
+            .collect::<Vec<_>>();
+        assert_eq!(onsets.len(), 3);
+
+        let second_onset_ms = onsets[2];
+        assert!(
+            second_onset_ms < first_release_ms,
+            "The second song should start before the first one's tail is released..!"
+        );
+
+        let overlap_ms = first_release_ms - second_onset_ms;
+        assert!(
+            (overlap_ms - CROSSFADE_MS).abs() < 2.0,
+            "Expected ~{}ms of overlap, got {:.3}ms..!",
+            CROSSFADE_MS,
+            overlap_ms
+        );
+    }
+
+    #[test]
+    fn pause_timeline_on_focus_loss() {
+        env_logger::try_init().unwrap_or(());
+
+        const LOST_FOR: Duration = Duration::from_millis(200);
+
+        // The 1st query is the initial focus check & the 2nd clears event 0, so focus is lost
+        // right before event 1 is emitted.
+        let player = Player::new(RecordingEngine::new(1.0), false, 0)
+            .with_watcher(FlakyWatcher::new(3, LOST_FOR))
+            .with_focus_lost_behavior(FocusLostBehavior::PauseTimeline);
+
+        let song = song_from_notes(&[(69, 0.0, 30.0), (71, 50.0, 30.0), (73, 100.0, 30.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        let onsets = player
+            .engine()
+            .recorded()
+            .into_iter()
+            .filter(|input| input.action == KeyAction::Down && input.keys == [PLAY_KEY])
+            .map(|input| input.at)
+            .collect::<Vec<_>>();
+        assert_eq!(onsets.len(), 3);
+
+        let ms_between = |a: Instant, b: Instant| b.duration_since(a).as_secs_f64() * 1000.0;
+
+        // Focus returns within one 50ms poll of the loss ending, and the rest of the song shifts with it.
+        let lost_ms = LOST_FOR.as_secs_f64() * 1000.0;
+        let shift_ms = ms_between(onsets[0], onsets[2]) - 100.0;
+        assert!(
+            (lost_ms - 10.0..lost_ms + 80.0).contains(&shift_ms),
+            "Expected a ~{}ms shift, got {:.3}ms..!",
+            lost_ms,
+            shift_ms
+        );
+
+        let gap_ms = ms_between(onsets[1], onsets[2]);
+        assert!(
+            (gap_ms - 50.0).abs() < 15.0,
+            "Expected events 1 & 2 to stay ~50ms apart, got {:.3}ms..!",
+            gap_ms
+        );
+    }
+
+    #[test]
+    fn warmup_taps_play_key_once() {
+        env_logger::try_init().unwrap_or(());
+
+        let player = Player::new(RecordingEngine::new(1.0), false, 0)
+            .with_watcher(FixedWindowWatcher::game())
+            .with_warmup(true);
+
+        let song = song_from_notes(&[(69, 0.0, 20.0), (71, 25.0, 20.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        let recorded = player.engine().recorded();
+        let first_note = recorded
+            .iter()
+            .position(|input| input.action == KeyAction::Down && input.keys != [PLAY_KEY])
+            .expect("A note should have been played..!");
+
+        let warmup = &recorded[..first_note];
+        assert_eq!(warmup.len(), 2);
+        assert_eq!(warmup[0].action, KeyAction::Down);
+        assert_eq!(warmup[1].action, KeyAction::Up);
+        assert!(warmup.iter().all(|input| input.keys == [PLAY_KEY]));
+
+        let play_presses = recorded
+            .iter()
+            .filter(|input| input.action == KeyAction::Down && input.keys == [PLAY_KEY])
+            .count();
+        assert_eq!(play_presses, 3);
+    }
+
+    #[test]
+    fn set_articulation_mid_song() {
+        env_logger::try_init().unwrap_or(());
+
+        // The 1st query is the initial focus check & the 2nd clears the first note, so the worker
+        // is held right before pressing the second one.
+        let clock = Arc::new(MockClock::new());
+        let watcher = GatedWatcher::new(&[2]);
+        let gate = Arc::clone(&watcher.gate);
+        let player = Player::new_for_test(
+            ClockedEngine::new(Arc::clone(&clock)),
+            watcher,
+            Arc::clone(&clock),
+        );
+
+        let song = song_from_notes(&[(69, 0.0, 100.0), (71, 200.0, 100.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(false).is_ok());
+
+        gate.wait();
+        player.set_articulation(0.5);
+        assert_eq!(player.engine().get_articulation(), 0.5);
+        gate.wait();
+
+        let waiting = Instant::now();
+        while player.is_busy() && waiting.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let onsets = player.engine().onsets.lock().unwrap().clone();
+        let releases = player.engine().releases.lock().unwrap().clone();
+
+        // Two presses, then the release of every key once playback completes.
+        assert_eq!(onsets.len(), 2);
+        assert_eq!(releases.len(), 3);
+
+        let held_ms = onsets
+            .iter()
+            .zip(releases.iter())
+            .map(|(onset, release)| (*release - *onset).as_secs_f64() * 1000.0)
+            .collect::<Vec<_>>();
+
+        assert!(
+            (held_ms[0] - 100.0).abs() < 1.0,
+            "First note held for {:.3}ms..!",
+            held_ms[0]
+        );
+        assert!(
+            (held_ms[1] - 50.0).abs() < 1.0,
+            "Second note held for {:.3}ms..!",
+            held_ms[1]
+        );
+    }
+
+    #[test]
+    fn negative_times_keep_spacing() {
+        env_logger::try_init().unwrap_or(());
+
+        let clock = Arc::new(MockClock::new());
+        let player = Player::new_for_test(
+            RecordingEngine::new(1.0).with_clock(Arc::clone(&clock)),
+            FixedWindowWatcher::game(),
+            clock,
+        );
+
+        let song = song_from_notes(&[
+            (69, -60.0, 15.0),
+            (71, -40.0, 15.0),
+            (73, -20.0, 15.0),
+            (74, 0.0, 15.0),
+        ]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        let expected = [69, 71, 73, 74]
+            .iter()
+            .map(|&midi| {
+                input_for_midi(midi)
+                    .expect("Midi values should be in range..!")
+                    .keys
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+
+        let notes = player
+            .engine()
+            .recorded()
+            .into_iter()
+            .filter(|input| input.action == KeyAction::Down && input.keys != [PLAY_KEY])
+            .collect::<Vec<_>>();
+        let played = notes
+            .iter()
+            .map(|input| input.keys.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(played, expected);
+
+        for pair in notes.windows(2) {
+            let gap_ms = pair[1].at.duration_since(pair[0].at).as_secs_f64() * 1000.0;
+            assert!(
+                (gap_ms - 20.0).abs() < 3.0,
+                "Expected notes ~20ms apart, got {:.3}ms..!",
+                gap_ms
+            );
+        }
+    }
+
+    #[test]
+    fn window_check_interval_skips_queries() {
+        env_logger::try_init().unwrap_or(());
+
+        let notes = (0..10)
+            .map(|i| (69 + i as u8, i as f64 * 15.0, 10.0))
+            .collect::<Vec<_>>();
+
+        let count_queries = |interval: Duration| {
+            let watcher = CountingWatcher::default();
+            let queries = Arc::clone(&watcher.queries);
+
+            let player = Player::new(RecordingEngine::new(1.0), false, 0)
+                .with_watcher(watcher)
+                .with_window_check_interval(interval);
+            assert!(player.load_song(song_from_notes(&notes)).is_ok());
+            assert!(player.play(true).is_ok());
+
+            queries.load(Ordering::SeqCst)
+        };
+
+        // One query to wait for focus, then one more before every event.
+        assert_eq!(count_queries(Duration::ZERO), 11);
+        assert!(count_queries(Duration::from_millis(100)) <= 4);
+    }
+
+    #[test]
+    fn schedule_accessors_after_mapping() {
+        let player = Player::new(RecordingEngine::new(1.0), false, 0);
+        assert_eq!(player.schedule_len(), 0);
+        assert_eq!(player.schedule_duration_ms(), 0.0);
+
+        // 40 is below the flute, so only the other two notes are scheduled.
+        let song = song_from_notes(&[(69, 0.0, 100.0), (40, 100.0, 500.0), (71, 200.0, 150.0)]);
+        let event_count = song.events.len();
+        assert!(player.load_song(song).is_ok());
+
+        assert_eq!(player.schedule_len(), event_count - 1);
+        assert_eq!(player.schedule_duration_ms(), 350.0);
+    }
+
+    #[test]
+    fn schedule_drops_unmapped_notes() {
+        env_logger::try_init().unwrap_or(());
+
+        let song = song_from_notes(&[
+            (71, 200.0, 50.0),
+            (40, 100.0, 50.0),
+            (69, 0.0, 50.0),
+            (120, 300.0, 50.0),
+            (73, 150.0, 50.0),
+        ]);
+
+        let schedule = schedule_for_song(&song);
+        let summary = schedule
+            .iter()
+            .map(|event| (event.time_ms, event.input.note_label))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(0.0, "A4 (69)"), (150.0, "C#5 (73)"), (200.0, "B4 (71)")]
+        );
+
+        let player = Player::new(RecordingEngine::new(1.0), false, 0);
+        assert!(player.load_song(song).is_ok());
+
+        let loaded = player
+            .schedule
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| (event.time_ms, event.input.note_label))
+            .collect::<Vec<_>>();
+        assert_eq!(loaded, summary);
+    }
+
+    #[test]
+    fn coincident_events_play_lowest_first() {
+        env_logger::try_init().unwrap_or(());
+
+        let notes = [
+            (76, 100.0, 50.0),
+            (69, 100.0, 50.0),
+            (81, 0.0, 50.0),
+            (73, 100.0, 50.0),
+            (71, 0.0, 50.0),
+        ];
+        let expected = vec![
+            (0.0, "B4 (71)"),
+            (0.0, "A5 (81)"),
+            (100.0, "A4 (69)"),
+            (100.0, "C#5 (73)"),
+            (100.0, "E5 (76)"),
+        ];
+
+        // Whatever order the song lists them in, the schedule comes out the same every time.
+        for rotation in 0..notes.len() {
+            let mut rotated = notes;
+            rotated.rotate_left(rotation);
+
+            for _ in 0..3 {
+                let summary = schedule_for_song(&song_from_notes(&rotated))
+                    .iter()
+                    .map(|event| (event.time_ms, event.input.note_label))
+                    .collect::<Vec<_>>();
+                assert_eq!(summary, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn load_song_while_busy() {
+        env_logger::try_init().unwrap_or(());
+
+        // Without focus the worker stays busy waiting for the game window.
+        let player = Player::new(RecordingEngine::new(1.0), false, 0).with_watcher(FailingWatcher);
+        let first = song_from_notes(&[(69, 0.0, 100.0)]);
+        let second = song_from_notes(&[(71, 0.0, 100.0)]);
+
+        assert!(player.load_song(first).is_ok());
+        assert!(!player.is_busy());
+
+        assert!(player.play(false).is_ok());
+        assert!(player.is_busy());
+
+        let why = player
+            .load_song(second.clone())
+            .expect_err("Loading during playback should fail..!");
+        assert!(why.to_string().contains("Playback in progress"), "{}", why);
+
+        let labels = |player: &Player<RecordingEngine>| {
+            player
+                .schedule
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|event| event.input.note_label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels(&player), vec!["A4 (69)"]);
+
+        assert!(player.stop().is_ok());
+        assert!(!player.is_busy());
+        assert!(player.load_song(second).is_ok());
+        assert_eq!(labels(&player), vec!["B4 (71)"]);
+    }
 
-        ensure_active_window();
+    #[test]
+    fn not_busy_after_worker_panics() {
+        env_logger::try_init().unwrap_or(());
+
+        let player =
+            Player::new(RecordingEngine::new(1.0), false, 0).with_watcher(PanickingWatcher);
+        let first = song_from_notes(&[(69, 0.0, 100.0)]);
+        assert!(player.load_song(first).is_ok());
+        assert!(player.play(false).is_ok());
+
+        let waiting = Instant::now();
+        while player.is_busy() && waiting.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!player.is_busy());
+
+        let second = song_from_notes(&[(71, 0.0, 100.0)]);
+        assert!(player.load_song(second).is_ok());
+    }
+
+    #[test]
+    fn give_up_when_focus_never_returns() {
+        env_logger::try_init().unwrap_or(());
+
+        // Focus is there for the initial check & the first note, then gone for good.
+        let player = Player::new_for_test(
+            RecordingEngine::new(1.0),
+            FlakyWatcher::new(3, Duration::MAX),
+            MockClock::new(),
+        );
+        let song = song_from_notes(&[(69, 0.0, 100.0), (71, 200.0, 100.0)]);
         assert!(player.load_song(song).is_ok());
+
+        // Joining would re-raise a panic from the worker.
         assert!(player.play(true).is_ok());
+        assert!(!player.is_busy());
+
+        let play_presses = player
+            .engine()
+            .recorded()
+            .into_iter()
+            .filter(|input| input.keys == [PLAY_KEY] && input.action == KeyAction::Down)
+            .count();
+        assert_eq!(play_presses, 1);
     }
 
     #[test]
-    fn play_from_midi_file() {
+    fn articulation_per_section() {
         env_logger::try_init().unwrap_or(());
 
-        let song = import_midi_file(
-            "./resources/songs/Twinkle_Twinkle_Little_Star.mid",
-            0,
-            PolyPolicy::Highest,
-            false,
-            Some((69, 93)),
+        let sections = ArticulationSchedule::new(vec![ArticulationRange {
+            start_ms: 100.0,
+            end_ms: 300.0,
+            factor: 0.5,
+        }]);
+
+        let player = Player::new(ArticulationEngine::default(), false, 0)
+            .with_watcher(FixedWindowWatcher::game())
+            .with_articulation_schedule(sections);
+        let song = song_from_notes(&[
+            (69, 0.0, 50.0),
+            (71, 100.0, 50.0),
+            (73, 200.0, 50.0),
+            (74, 300.0, 50.0),
+        ]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        let articulations = player.engine().articulations.lock().unwrap().clone();
+        assert_eq!(articulations, vec![0.75, 0.5, 0.5, 0.75]);
+    }
+
+    #[test]
+    fn validate_without_loading() {
+        env_logger::try_init().unwrap_or(());
+
+        let player = Player::new(RecordingEngine::new(1.0), false, 0).with_watcher(FailingWatcher);
+        let song = song_from_notes(&[(69, 0.0, 100.0), (120, 150.0, 100.0), (71, 200.0, 300.0)]);
+
+        let report = player.validate_song(&song);
+        assert_eq!(
+            report,
+            ValidationReport {
+                mappable: 2,
+                unmappable: 1,
+                duration_ms: 500.0,
+            }
         );
 
-        if song.is_err() {
-            warn!("{:?}", song);
+        // Nothing was loaded or played.
+        assert!(player.schedule.lock().unwrap().is_empty());
+        assert!(player.play(true).is_err());
+        assert!(player.engine().recorded().is_empty());
+    }
+
+    #[test]
+    fn schedule_uses_override_keys() {
+        env_logger::try_init().unwrap_or(());
+
+        let mut song = song_from_notes(&[(69, 0.0, 50.0), (71, 100.0, 50.0), (40, 200.0, 50.0)]);
+        let custom = vec![key_by_name("Enter").unwrap().0, key_by_name("Q").unwrap().0];
+        song.events[1].override_keys = Some(custom.clone());
+        // Unmapped notes are still played when given keys of their own.
+        song.events[2].override_keys = Some(custom.clone());
+
+        let codes = |input: &Input| input.keys.iter().map(|vk| vk.0).collect::<Vec<_>>();
+        let mapped = |midi| codes(input_for_midi(midi).unwrap());
+
+        let schedule = schedule_for_song(&song);
+        let keys = schedule
+            .iter()
+            .map(|event| codes(event.input))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![mapped(69), custom.clone(), custom]);
+        assert_ne!(keys[1], mapped(71));
+
+        // Both events share the one custom input.
+        assert!(std::ptr::eq(schedule[1].input, schedule[2].input));
+    }
+
+    #[test]
+    fn virtual_time_plays_without_waiting() {
+        env_logger::try_init().unwrap_or(());
+
+        let player = Player::new(RecordingEngine::new(1.0), false, 0).with_virtual_time();
+        let song = song_from_notes(&[(69, 0.0, 50.0), (71, 10_000.0, 50.0)]);
+        assert!(player.load_song(song).is_ok());
+
+        let started = Instant::now();
+        assert!(player.play(true).is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let presses = player
+            .engine()
+            .recorded()
+            .into_iter()
+            .filter(|input| input.action == KeyAction::Down && input.keys == [PLAY_KEY])
+            .count();
+        assert_eq!(presses, 2);
+    }
+
+    #[test]
+    fn mock_clock_emits_at_virtual_targets() {
+        env_logger::try_init().unwrap_or(());
+
+        let clock = Arc::new(MockClock::new());
+        let engine = ClockedEngine::new(Arc::clone(&clock));
+
+        let player = Player::new(engine, false, 0)
+            .with_watcher(FixedWindowWatcher::game())
+            .with_clock(Arc::clone(&clock));
+
+        let targets = [0.0, 120.0, 250.0, 1000.0];
+        let song = song_from_notes(&[
+            (69, targets[0], 100.0),
+            (71, targets[1], 100.0),
+            (73, targets[2], 100.0),
+            (74, targets[3], 100.0),
+        ]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        // Every note is pressed 1ms after its target, once its keys have settled.
+        let onsets = player.engine().onsets.lock().unwrap().clone();
+        assert_eq!(onsets.len(), targets.len());
+        for (onset, target_ms) in onsets.iter().zip(targets) {
+            let onset_ms = onset.as_secs_f64() * 1000.0;
+            assert!(
+                (onset_ms - (target_ms + 1.0)).abs() < 1e-3,
+                "Expected an onset at {}ms, got {:.6}ms..!",
+                target_ms + 1.0,
+                onset_ms
+            );
         }
 
-        assert!(song.is_ok());
+        assert!(clock.elapsed() >= Duration::from_millis(1100));
+    }
 
-        let art = 0.55;
-        let engine = DefaultInputEngine::new(art);
-        let player = Player::new(engine, true, 0);
+    #[test]
+    fn final_spin_tightens_onsets() {
+        env_logger::try_init().unwrap_or(());
 
-        ensure_active_window();
-        assert!(player.load_song(song.unwrap()).is_ok());
+        // Targets between whole milliseconds, which coarse sleeps overshoot.
+        let targets = [100.5, 250.3, 400.7];
+
+        let worst_error_ms = |final_spin: Duration| {
+            let clock = Arc::new(MockClock::new());
+            let engine = ClockedEngine::new(Arc::clone(&clock));
+            let player = Player::new_for_test(
+                engine,
+                FixedWindowWatcher::game(),
+                CoarseClock(Arc::clone(&clock)),
+            )
+            .with_final_spin(final_spin);
+
+            let notes = targets.map(|target| (69, target, 100.0));
+            assert!(player.load_song(song_from_notes(&notes)).is_ok());
+            assert!(player.play(true).is_ok());
+
+            // Each play key goes down 1ms after its target, once the note keys have settled.
+            let onsets = player.engine().onsets.lock().unwrap().clone();
+            assert_eq!(onsets.len(), targets.len());
+            onsets
+                .iter()
+                .zip(targets)
+                .map(|(onset, target_ms)| (onset.as_secs_f64() * 1000.0 - (target_ms + 1.0)).abs())
+                .fold(0.0, f64::max)
+        };
+
+        let spun = worst_error_ms(DEFAULT_FINAL_SPIN);
+        assert!(spun < 1e-3, "Spinning still missed by {:.6}ms..!", spun);
+
+        let slept = worst_error_ms(Duration::ZERO);
+        assert!(
+            slept > 0.2,
+            "Sleeping alone only missed by {:.6}ms..!",
+            slept
+        );
+    }
+
+    #[test]
+    fn min_playable_hold_extends_fast_notes() {
+        env_logger::try_init().unwrap_or(());
+
+        let clock = Arc::new(MockClock::new());
+        let engine = ClockedEngine {
+            min_playable_hold_ms: 40.0,
+            ..ClockedEngine::new(Arc::clone(&clock))
+        };
+        let player = Player::new_for_test(engine, FixedWindowWatcher::game(), Arc::clone(&clock));
+
+        // The first 10ms note has room to reach the floor, the second only until the third starts.
+        let notes = [(69, 0.0, 10.0), (71, 100.0, 10.0), (73, 120.0, 50.0)];
+        assert!(player.load_song(song_from_notes(&notes)).is_ok());
+        assert!(player.play(true).is_ok());
+
+        // The play key goes down 1ms after each note's time, once its keys have settled.
+        let ms = |times: &Mutex<Vec<Duration>>| {
+            times
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|at| (at.as_secs_f64() * 1000.0 * 1000.0).round() / 1000.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ms(&player.engine().onsets), vec![1.0, 101.0, 121.0]);
+
+        // The second leaves 1ms to send its release & 4ms to lift the keys the third changes,
+        // before the final release of every key.
+        let releases = ms(&player.engine().releases);
+        assert_eq!(releases[..3], [41.0, 115.0, 171.0]);
+    }
+
+    #[test]
+    fn key_latency_advances_presses() {
+        env_logger::try_init().unwrap_or(());
+
+        let onsets_with_latency = |key_latency_ms: f64| {
+            let clock = Arc::new(MockClock::new());
+            let engine = ClockedEngine {
+                key_latency_ms,
+                ..ClockedEngine::new(Arc::clone(&clock))
+            };
+            let player =
+                Player::new_for_test(engine, FixedWindowWatcher::game(), Arc::clone(&clock));
+
+            let notes = [(69, 100.0, 100.0), (71, 300.0, 100.0)];
+            assert!(player.load_song(song_from_notes(&notes)).is_ok());
+            assert!(player.play(true).is_ok());
+
+            let onsets = player.engine().onsets.lock().unwrap().clone();
+            onsets
+                .iter()
+                .map(|onset| (onset.as_secs_f64() * 1000.0 * 1000.0).round() / 1000.0)
+                .collect::<Vec<_>>()
+        };
+
+        // The play key goes down 1ms after each note's time, once its keys have settled.
+        assert_eq!(onsets_with_latency(0.0), vec![101.0, 301.0]);
+        assert_eq!(onsets_with_latency(20.0), vec![81.0, 281.0]);
+    }
+
+    #[test]
+    fn sleep_through_rests() {
+        env_logger::try_init().unwrap_or(());
+
+        let clock = Arc::new(MockClock::new());
+        let engine = ClockedEngine::new(Arc::clone(&clock));
+        let player = Player::new_for_test(engine, FixedWindowWatcher::game(), Arc::clone(&clock));
+
+        let mut song = song_from_notes(&[(69, 0.0, 100.0), (71, 300.0, 100.0)]);
+        song.events.insert(1, Event::rest(100.0, 200.0));
+        assert_eq!(player.validate_song(&song).unmappable, 0);
+        assert!(player.load_song(song).is_ok());
+        assert_eq!(player.schedule_len(), 2);
+        assert!(player.play(true).is_ok());
+
+        // Only the notes are pressed, each 1ms after its time once its keys have settled.
+        let onsets = player.engine().onsets.lock().unwrap().clone();
+        assert_eq!(
+            onsets,
+            vec![Duration::from_millis(1), Duration::from_millis(301)]
+        );
+    }
+
+    #[test]
+    fn tap_tempo_retimes_remainder() {
+        env_logger::try_init().unwrap_or(());
+
+        // The 1st query is the initial focus check, so the worker is held right before pressing
+        // the second & third notes, 200ms apart on the virtual clock.
+        let clock = Arc::new(MockClock::new());
+        let watcher = GatedWatcher::new(&[2, 3]);
+        let gate = Arc::clone(&watcher.gate);
+        let player = Player::new_for_test(
+            ClockedEngine::new(Arc::clone(&clock)),
+            watcher,
+            Arc::clone(&clock),
+        );
+
+        // Quarter notes at the default 120bpm, until two taps 200ms apart ask for 300bpm.
+        let notes = (0..10)
+            .map(|i| (69, i as f64 * 200.0, 100.0))
+            .collect::<Vec<_>>();
+        assert!(player.load_song(song_from_notes(&notes)).is_ok());
+        assert!(player.play(false).is_ok());
+
+        gate.wait();
+        assert!(player.tap_tempo().is_none());
+        gate.wait();
+
+        gate.wait();
+        let bpm = player.tap_tempo().expect("Two taps should set a tempo..!");
+        assert!((bpm - 300.0).abs() < 0.001, "Tapped {:.3}bpm..!", bpm);
+        gate.wait();
+
+        let waiting = Instant::now();
+        while player.is_busy() && waiting.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let onsets = player.engine().onsets.lock().unwrap().clone();
+        assert_eq!(onsets.len(), notes.len());
+
+        let gaps = onsets
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).as_secs_f64() * 1000.0)
+            .collect::<Vec<_>>();
+        assert!((gaps[0] - 200.0).abs() < 1.0, "Gaps: {:?}..!", gaps);
+        for gap in gaps[gaps.len() - 4..].iter() {
+            assert!((gap - 80.0).abs() < 1.0, "Gaps: {:?}..!", gaps);
+        }
+    }
+
+    #[test]
+    fn play_key_offset_lands_on_time() {
+        env_logger::try_init().unwrap_or(());
+
+        // Sending each key batch takes 30ms, so the play key goes out after the note keys' batch,
+        // the settle time & its own batch.
+        let input_delay = Duration::from_millis(30);
+        let offset_ms = 2.0 * input_delay.as_secs_f64() * 1000.0 + PLAY_KEY_SETTLE_MS;
+
+        let clock = Arc::new(MockClock::new());
+        let engine = RecordingEngine::new(1.0)
+            .with_input_delay(input_delay)
+            .with_clock(Arc::clone(&clock));
+        let player = Player::new_for_test(engine, FixedWindowWatcher::game(), Arc::clone(&clock))
+            .with_play_key_offset(offset_ms);
+
+        // Repeats one note, since changing keys between notes adds a release of its own.
+        let targets = [200.0, 600.0, 1000.0];
+        let song = song_from_notes(&[
+            (69, targets[0], 100.0),
+            (69, targets[1], 100.0),
+            (69, targets[2], 100.0),
+        ]);
+        assert!(player.load_song(song).is_ok());
+
+        let started = clock.now();
+        assert!(player.play(true).is_ok());
+
+        let onsets = player
+            .engine()
+            .recorded()
+            .into_iter()
+            .filter(|input| input.action == KeyAction::Down && input.keys == [PLAY_KEY])
+            .map(|input| input.at.duration_since(started).as_secs_f64() * 1000.0)
+            .collect::<Vec<_>>();
+        assert_eq!(onsets.len(), targets.len());
+
+        for (onset_ms, target_ms) in onsets.into_iter().zip(targets) {
+            assert!(
+                (onset_ms - target_ms).abs() < 1e-3,
+                "Expected the play key at {}ms, got {:.3}ms..!",
+                target_ms,
+                onset_ms
+            );
+        }
+    }
+
+    #[test]
+    fn long_hold_leaves_next_onset_alone() {
+        env_logger::try_init().unwrap_or(());
+
+        let clock = Arc::new(MockClock::new());
+        let engine = ClockedEngine::new(Arc::clone(&clock));
+        let player = Player::new_for_test(engine, FixedWindowWatcher::game(), Arc::clone(&clock));
+
+        let song = song_from_notes(&[(69, 0.0, 2000.0), (71, 100.0, 100.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(true).is_ok());
+
+        // Each note's press & release, then the release of every key once playback completes.
+        let onsets = player.engine().onsets.lock().unwrap().clone();
+        let releases = player.engine().releases.lock().unwrap().clone();
+        assert_eq!(onsets.len(), 2);
+        assert_eq!(releases.len(), 3);
+
+        // The first note is released in time for the second, which sounds on schedule give or
+        // take the few ms its changed keys take to settle.
+        assert!(
+            releases[0] < onsets[1],
+            "Released at {:?}, after the second note at {:?}..!",
+            releases[0],
+            onsets[1]
+        );
+        let second_ms = onsets[1].as_secs_f64() * 1000.0;
+        assert!(
+            (second_ms - 100.0).abs() < 5.0,
+            "Second note sounded at {:.3}ms..!",
+            second_ms
+        );
+    }
+
+    #[test]
+    fn catch_up_on_slow_presses() {
+        env_logger::try_init().unwrap_or(());
+
+        // Every press spends about 34ms sending its four key batches & settling on top of its
+        // hold, more than half of the 60ms each legato note lasts.
+        let notes = (0..15)
+            .map(|i| (69, i as f64 * 60.0, 60.0))
+            .collect::<Vec<_>>();
+
+        let drift_ms = |catch_up: bool| {
+            let clock = Arc::new(MockClock::new());
+            let engine = ClockedEngine {
+                input_delay: Duration::from_millis(8),
+                ..ClockedEngine::new(Arc::clone(&clock))
+            };
+            let player = Player::new_for_test(engine, FixedWindowWatcher::game(), clock)
+                .with_catch_up(catch_up);
+            assert!(player.load_song(song_from_notes(&notes)).is_ok());
+            assert!(player.play(true).is_ok());
+
+            let onsets = player.engine().onsets.lock().unwrap().clone();
+            assert_eq!(onsets.len(), notes.len());
+
+            let last = onsets.len() - 1;
+            (onsets[last] - onsets[0]).as_secs_f64() * 1000.0 - notes[last].1
+        };
+
+        let drift = drift_ms(true);
+        assert!(
+            drift.abs() < 1.0,
+            "Drifted {:.3}ms despite catching up..!",
+            drift
+        );
+
+        // Without catching up, each press pushes every later one back.
+        let drift = drift_ms(false);
+        assert!(drift > 400.0, "Only drifted {:.3}ms..!", drift);
+    }
+
+    #[test]
+    fn stop_while_window_query_fails() {
+        env_logger::try_init().unwrap_or(());
+
+        let player = Player::new(RecordingEngine::new(0.5), false, 0).with_watcher(FailingWatcher);
+        let song = song_from_notes(&[(69, 0.0, 100.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(false).is_ok());
+
+        thread::sleep(Duration::from_millis(200));
+
+        let stopping = Instant::now();
+        assert!(player.stop().is_ok());
+        assert!(
+            stopping.elapsed() < Duration::from_millis(500),
+            "Stopping took {:?}..!",
+            stopping.elapsed()
+        );
+        assert!(player.engine().recorded().is_empty());
+    }
+
+    #[test]
+    fn stop_mid_song_while_window_query_fails() {
+        env_logger::try_init().unwrap_or(());
+
+        // The first two queries cover the initial wait & the first note, so the second note is
+        // stuck retrying its window check.
+        let queries = Arc::new(AtomicUsize::new(0));
+        let watcher = VanishingWatcher {
+            ok_for: 2,
+            queries: Arc::clone(&queries),
+        };
+        let clock = Arc::new(MockClock::new());
+        let player = Player::new_for_test(
+            RecordingEngine::new(0.5).with_clock(Arc::clone(&clock)),
+            watcher,
+            clock,
+        );
+        let song = song_from_notes(&[(69, 0.0, 100.0), (71, 300.0, 100.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(false).is_ok());
+
+        let waiting = Instant::now();
+        while queries.load(Ordering::SeqCst) < 5 && waiting.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let stopping = Instant::now();
+        assert!(player.stop().is_ok());
+        assert!(
+            stopping.elapsed() < Duration::from_millis(500),
+            "Stopping took {:?}..!",
+            stopping.elapsed()
+        );
+
+        let recorded = player.engine().recorded();
+        let play_presses = recorded
+            .iter()
+            .filter(|r| r.action == KeyAction::Down && r.keys.contains(&PLAY_KEY))
+            .count();
+        assert_eq!(play_presses, 1);
+        assert_eq!(recorded.last().map(|r| r.action), Some(KeyAction::Up));
+    }
+
+    #[test]
+    fn stop_reaches_worker_retrying_failed_queries() {
+        env_logger::try_init().unwrap_or(());
+
+        // Held in its 3rd failed query of the initial window wait.
+        let clock = Arc::new(MockClock::new());
+        let watcher = GatedWatcher::failing(&[2]);
+        let gate = Arc::clone(&watcher.gate);
+        let player = Player::new_for_test(RecordingEngine::new(1.0), watcher, Arc::clone(&clock));
+        let song = song_from_notes(&[(69, 0.0, 100.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(false).is_ok());
+
+        gate.wait();
+        let stopped_at = clock.now();
+        let sent = player
+            .control_tx
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tx| tx.send(ControlMsg::Stop));
+        assert!(matches!(sent, Some(Ok(()))));
+        gate.wait();
+        assert!(player.reset().is_ok());
+
+        // The query failing didn't skip the stop check before the next one.
+        let stopped_after = clock.now().duration_since(stopped_at);
+        assert!(
+            stopped_after <= WINDOW_POLL_INTERVAL,
+            "Stopped after {:?}..!",
+            stopped_after
+        );
+        assert!(player.engine().recorded().is_empty());
+    }
+
+    #[test]
+    fn release_keys_after_completion() {
+        env_logger::try_init().unwrap_or(());
+
+        let player = Player::new_for_test(
+            RecordingEngine::new(1.0),
+            FixedWindowWatcher::game(),
+            MockClock::new(),
+        );
+        let song = song_from_notes(&[(69, 0.0, 50.0), (71, 100.0, 50.0)]);
+        assert!(player.load_song(song).is_ok());
         assert!(player.play(true).is_ok());
+
+        // The last note releases the play key & its own keys, then the play key & every mapping
+        // are released once more without a stop message.
+        let recorded = player.engine().recorded();
+        let last_down = recorded
+            .iter()
+            .rposition(|r| r.action == KeyAction::Down)
+            .unwrap();
+        let released = &recorded[last_down + 1..];
+        assert!(released.iter().all(|r| r.action == KeyAction::Up));
+        assert_eq!(released.len(), 2 + 1 + crate::active_mappings().len());
+        assert_eq!(released[2].keys, [PLAY_KEY]);
+    }
+
+    #[test]
+    fn hold_final_note_until_stop() {
+        env_logger::try_init().unwrap_or(());
+
+        let clock = Arc::new(MockClock::new());
+        let player = Player::new_for_test(
+            RecordingEngine::new(1.0).with_clock(Arc::clone(&clock)),
+            FixedWindowWatcher::game(),
+            Arc::clone(&clock),
+        )
+        .with_hold_final_note(true);
+        let song = song_from_notes(&[(69, 0.0, 50.0), (71, 100.0, 50.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(false).is_ok());
+
+        // Both notes have been pressed once the play key went down twice.
+        let play_presses = || {
+            player
+                .engine()
+                .recorded()
+                .iter()
+                .filter(|r| r.action == KeyAction::Down && r.keys == [PLAY_KEY])
+                .count()
+        };
+        let waiting = Instant::now();
+        while play_presses() < 2 && waiting.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let final_keys = input_for_midi(71).unwrap().keys.to_vec();
+        let released_final = |recorded: &[RecordedInput]| {
+            recorded
+                .iter()
+                .any(|r| r.action == KeyAction::Up && r.keys == final_keys)
+        };
+
+        let held = player.engine().recorded();
+        assert!(
+            held.iter()
+                .any(|r| r.action == KeyAction::Down && r.keys == final_keys)
+        );
+        assert!(!released_final(&held));
+        assert_eq!(held.last().map(|r| r.action), Some(KeyAction::Down));
+
+        assert!(player.stop().is_ok());
+
+        let recorded = player.engine().recorded();
+        assert!(released_final(&recorded));
+        assert_eq!(recorded.last().map(|r| r.action), Some(KeyAction::Up));
+    }
+
+    #[test]
+    fn hold_final_note_taps_modifiers() {
+        env_logger::try_init().unwrap_or(());
+
+        let player = Player::new(TapEngine::default(), false, 0)
+            .with_watcher(FixedWindowWatcher::game())
+            .with_hold_final_note(true);
+        let song = song_from_notes(&[(69, 0.0, 50.0)]);
+        assert!(player.load_song(song).is_ok());
+        assert!(player.play(false).is_ok());
+
+        let holding = || {
+            player
+                .engine()
+                .inner
+                .recorded()
+                .iter()
+                .any(|r| r.action == KeyAction::Down && r.keys == [PLAY_KEY])
+        };
+        let waiting = Instant::now();
+        while !holding() && waiting.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(player.stop().is_ok());
+
+        // The octave is tapped on by itself, then the rest of the note is held as usual.
+        let octave = modifier_keys().octave;
+        let a4 = input_for_midi(69).unwrap();
+        let rest = a4
+            .keys
+            .iter()
+            .copied()
+            .filter(|key| *key != octave)
+            .collect::<Vec<_>>();
+
+        let recorded = player
+            .engine()
+            .inner
+            .recorded()
+            .into_iter()
+            .map(|r| (r.action, r.keys))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            recorded[..4],
+            [
+                (KeyAction::Down, vec![octave]),
+                (KeyAction::Up, vec![octave]),
+                (KeyAction::Down, rest),
+                (KeyAction::Down, vec![PLAY_KEY]),
+            ]
+        );
+
+        // Stopping taps the octave back off.
+        assert_eq!(recorded.last(), Some(&(KeyAction::Up, vec![octave])));
+        assert!(player.engine().toggles.active().is_empty());
+    }
+
+    #[test]
+    fn pause_and_resume_mid_song() {
+        env_logger::try_init().unwrap_or(());
+
+        // Held right before pressing the second note, 300ms into the virtual timeline.
+        let clock = Arc::new(MockClock::new());
+        let watcher = GatedWatcher::new(&[2]);
+        let gate = Arc::clone(&watcher.gate);
+        let player = Player::new_for_test(
+            ClockedEngine::new(Arc::clone(&clock)),
+            watcher,
+            Arc::clone(&clock),
+        );
+
+        let notes = [(69, 0.0, 100.0), (71, 300.0, 100.0), (73, 600.0, 100.0)];
+        assert!(player.load_song(song_from_notes(&notes)).is_ok());
+        assert!(player.play(false).is_ok());
+
+        gate.wait();
+        assert!(player.pause().is_ok());
+        gate.wait();
+
+        // The second note is pressed, then released at once as the worker picks up the pause.
+        let waiting = Instant::now();
+        while player.engine().releases.lock().unwrap().len() < 2
+            && waiting.elapsed() < Duration::from_secs(5)
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // Nothing moves while paused, however long it lasts.
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(player.engine().onsets.lock().unwrap().len(), 2);
+        assert_eq!(player.engine().releases.lock().unwrap().len(), 2);
+        assert!(player.is_busy());
+
+        clock.advance(Duration::from_millis(500));
+        assert!(player.resume().is_ok());
+
+        let waiting = Instant::now();
+        while player.is_busy() && waiting.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let ms = |at: &Duration| at.as_secs_f64() * 1000.0;
+        let onsets = player.engine().onsets.lock().unwrap().clone();
+        let releases = player.engine().releases.lock().unwrap().clone();
+        assert_eq!(onsets.len(), notes.len());
+        assert!((ms(&releases[1]) - ms(&onsets[1])).abs() < 1.0);
+
+        // The pause pushes the third note back by as long as it lasted, keeping it in order.
+        assert!(onsets.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((ms(&onsets[1]) - 300.0).abs() < 2.0);
+        assert!((ms(&onsets[2]) - 1100.0).abs() < 2.0);
     }
 }
@@ -0,0 +1,117 @@
+use crate::model::song::Song;
+use anyhow::{Result, anyhow};
+use log::info;
+use rodio::buffer::SamplesBuffer;
+use rodio::{OutputStreamBuilder, Sink};
+
+pub const PREVIEW_SAMPLE_RATE: u32 = 44_100;
+
+/// Peak amplitude of each tone, kept low since square waves are harsh at full scale.
+const PREVIEW_AMPLITUDE: f32 = 0.2;
+
+/// Plays the song through the default audio output as a plain square wave per event, blocking
+/// until it has finished.
+pub fn play_audio_preview(song: &Song) -> Result<()> {
+    let samples = preview_samples(song, PREVIEW_SAMPLE_RATE);
+
+    let mut stream = OutputStreamBuilder::open_default_stream()
+        .map_err(|e| anyhow!("Failed to open an audio output stream: {}", e))?;
+    stream.log_on_drop(false);
+
+    let sink = Sink::connect_new(stream.mixer());
+    sink.append(SamplesBuffer::new(1, PREVIEW_SAMPLE_RATE, samples));
+
+    info!(
+        "Previewing {} events through the system audio..!",
+        song.events.len()
+    );
+    sink.sleep_until_end();
+
+    Ok(())
+}
+
+/// Renders the song to mono samples, with a tone at each event's pitch for its full duration &
+/// silence everywhere else. Events scheduled before zero shift the whole song later.
+pub fn preview_samples(song: &Song, sample_rate: u32) -> Vec<f32> {
+    let to_samples = |ms: f64| (ms * sample_rate as f64 / 1000.0).round().max(0.0) as usize;
+    let origin_ms = song.events.iter().map(|e| e.time_ms).fold(0.0, f64::min);
+
+    let total = song
+        .events
+        .iter()
+        .map(|e| to_samples(e.time_ms - origin_ms) + to_samples(e.duration_ms))
+        .max()
+        .unwrap_or(0);
+
+    let mut samples = vec![0.0; total];
+    for event in song.events.iter().filter(|event| !event.is_rest()) {
+        let start = to_samples(event.time_ms - origin_ms);
+        let len = to_samples(event.duration_ms);
+        let freq = 440.0 * 2f64.powf((event.note.midi as f64 - 69.0) / 12.0);
+
+        for (i, sample) in samples[start..start + len].iter_mut().enumerate() {
+            let phase = (i as f64 * freq / sample_rate as f64).fract();
+            *sample = if phase < 0.5 {
+                PREVIEW_AMPLITUDE
+            } else {
+                -PREVIEW_AMPLITUDE
+            };
+        }
+    }
+
+    samples
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Event, Metadata, Note, PREVIEW_SAMPLE_RATE, Song, preview_samples};
+
+    #[test]
+    fn two_note_sample_buffer() {
+        let song = Song {
+            metadata: Metadata {
+                title: Some(String::from("Two Tones")),
+                tempo_bpm: None,
+                markers: Vec::new(),
+            },
+            events: vec![
+                Event {
+                    note: Note {
+                        midi: 69,
+                        velocity: 100,
+                    },
+                    time_ms: 0.0,
+                    duration_ms: 100.0,
+                    override_keys: None,
+                },
+                Event {
+                    note: Note {
+                        midi: 76,
+                        velocity: 100,
+                    },
+                    time_ms: 150.0,
+                    duration_ms: 200.0,
+                    override_keys: None,
+                },
+            ],
+        };
+
+        let samples = preview_samples(&song, PREVIEW_SAMPLE_RATE);
+        assert_eq!(samples.len(), 15_435);
+
+        // Runs of sound, as (start, length) in samples.
+        let mut segments: Vec<(usize, usize)> = Vec::new();
+        for (i, sample) in samples.iter().enumerate() {
+            if *sample == 0.0 {
+                continue;
+            }
+
+            match segments.last_mut() {
+                Some((start, len)) if *start + *len == i => *len += 1,
+                _ => segments.push((i, 1)),
+            }
+        }
+
+        assert_eq!(segments, vec![(0, 4_410), (6_615, 8_820)]);
+    }
+}
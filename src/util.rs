@@ -1,26 +1,338 @@
-use crate::PolyPolicy;
-use log::info;
+use crate::engine::ModifierStyle;
+use crate::model::mappings::MAPPINGS;
+use crate::model::song::{Event, Metadata, Note, Song};
+use crate::{EmitVelocity, PolyPolicy};
+use anyhow::{Result, anyhow, bail};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Roughly the shortest hold the game reliably picks up as a note.
+pub const MIN_PLAYABLE_HOLD_MS: f64 = 25.0;
+
+/// The named articulation presets & how much of each note they hold for.
+pub fn articulation_presets() -> Vec<(&'static str, f64)> {
+    vec![
+        ("tenuto", 1.0),
+        ("portato", 0.75),
+        ("staccato", 0.5),
+        ("staccatissimo", 0.25),
+    ]
+}
 
 pub fn parse_articulation(input: &str, custom: Option<f64>) -> f64 {
-    match input.to_lowercase().as_str() {
-        "t" | "tenuto" => 1.0,
-        "s" | "staccato" => 0.5,
-        "ss" | "staccatissimo" => 0.25,
-        "p" | "portato" | "portamento" => 0.75,
-        "c" | "custom" => {
-            if let Some(hold_perc) = custom.as_ref() {
-                hold_perc.clamp(0.0, 1.0)
-            } else {
-                info!(
-                    "No custom articulation given..!\nExample usage: `--hold-percentage 0.42` | Defaulting to 0.75 (Portato)..!"
-                );
-                0.75
-            }
+    match parse_articulation_strict(input, custom) {
+        Ok(articulation) => articulation,
+        Err(why) => {
+            warn!("{} | Defaulting to 0.75 (Portato)..!", why);
+            0.75
+        }
+    }
+}
+
+/// Like [`parse_articulation`], but reports unknown presets & a missing custom value instead of
+/// falling back to portato. Warns when a custom value had to be clamped.
+pub fn parse_articulation_strict(input: &str, custom: Option<f64>) -> Result<f64> {
+    let parsed = parse_articulation_checked(input, custom)?;
+
+    if let Some(given) = parsed.clamped_from {
+        warn!(
+            "Custom articulation {} is outside 0.0..=1.0, using {} instead..!",
+            given, parsed.articulation
+        );
+    }
+
+    Ok(parsed.articulation)
+}
+
+/// An articulation parsed from a preset name or custom value, along with what was off about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedArticulation {
+    pub articulation: f64,
+
+    /// The preset it was resolved from, or `custom`.
+    pub preset: &'static str,
+
+    /// The custom value as given, when it was outside 0.0..=1.0 & had to be clamped.
+    pub clamped_from: Option<f64>,
+
+    /// Whether a custom value was given along with a preset, which then ignores it.
+    pub ignored_custom: bool,
+}
+
+/// Resolves an articulation like [`parse_articulation_strict`], reporting any clamping or ignored
+/// custom value for the caller to surface rather than logging it.
+pub fn parse_articulation_checked(input: &str, custom: Option<f64>) -> Result<ParsedArticulation> {
+    let Some(preset) = preset_name(input) else {
+        return Err(anyhow!(
+            "Unknown articulation '{}', expected one of tenuto|portato|staccato|staccatissimo|custom..!",
+            input.to_lowercase()
+        ));
+    };
+
+    if preset == "custom" {
+        let Some(given) = custom else {
+            return Err(anyhow!(
+                "No custom articulation given..!\nExample usage: `--hold-percentage 0.42`"
+            ));
+        };
+
+        if given.is_nan() {
+            return Err(anyhow!("Custom articulation must be a number..!"));
         }
-        _ => 0.75,
+
+        let articulation = given.clamp(0.0, 1.0);
+        return Ok(ParsedArticulation {
+            articulation,
+            preset,
+            clamped_from: (articulation != given).then_some(given),
+            ignored_custom: false,
+        });
+    }
+
+    let articulation = articulation_presets()
+        .into_iter()
+        .find(|(name, _)| *name == preset)
+        .map(|(_, articulation)| articulation)
+        .ok_or_else(|| anyhow!("Missing articulation preset '{}'..!", preset))?;
+
+    Ok(ParsedArticulation {
+        articulation,
+        preset,
+        clamped_from: None,
+        ignored_custom: custom.is_some(),
+    })
+}
+
+/// The shortest a note is held for under each articulation (in ms), however short the note, so
+/// that the game still registers it. Tenuto always holds the whole note, so it needs no floor.
+pub fn articulation_min_holds() -> Vec<(&'static str, f64)> {
+    vec![
+        ("tenuto", 0.0),
+        ("portato", MIN_PLAYABLE_HOLD_MS),
+        ("staccato", MIN_PLAYABLE_HOLD_MS),
+        ("staccatissimo", MIN_PLAYABLE_HOLD_MS * 0.8),
+        ("custom", MIN_PLAYABLE_HOLD_MS * 0.8),
+    ]
+}
+
+/// The default minimum hold of an articulation preset (by name or alias), see
+/// [`articulation_min_holds`]. Unknown presets get the portato floor.
+pub fn articulation_min_hold_ms(input: &str) -> f64 {
+    let preset = preset_name(input).unwrap_or("portato");
+
+    articulation_min_holds()
+        .into_iter()
+        .find(|(name, _)| *name == preset)
+        .map_or(MIN_PLAYABLE_HOLD_MS, |(_, min_hold_ms)| min_hold_ms)
+}
+
+/// Resolves an articulation name or alias to its preset name, including `custom`.
+fn preset_name(input: &str) -> Option<&'static str> {
+    match input.to_lowercase().as_str() {
+        "t" | "tenuto" => Some("tenuto"),
+        "s" | "staccato" => Some("staccato"),
+        "ss" | "staccatissimo" => Some("staccatissimo"),
+        "p" | "portato" | "portamento" => Some("portato"),
+        "c" | "custom" => Some("custom"),
+        _ => None,
+    }
+}
+
+/// How log records are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// env_logger's usual human readable lines.
+    #[default]
+    Text,
+
+    /// One JSON object per record, with `level`, `target`, `timestamp` & `message` fields.
+    Json,
+}
+
+pub fn parse_log_format(input: &str) -> Result<LogFormat> {
+    match input.to_lowercase().as_str() {
+        "text" | "plain" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        other => Err(anyhow!(
+            "Unknown log format '{}', expected one of text|json..!",
+            other
+        )),
     }
 }
 
+/// A logger builder configured from `RUST_LOG` that writes records in the given format.
+pub fn logger_builder(format: LogFormat) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::from_default_env();
+
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "timestamp": buf.timestamp().to_string(),
+                "message": record.args().to_string(),
+            });
+
+            writeln!(buf, "{}", line)
+        });
+    }
+
+    builder
+}
+
+pub fn parse_modifier_style(input: &str) -> Result<ModifierStyle> {
+    match input.to_lowercase().as_str() {
+        "hold" => Ok(ModifierStyle::Hold),
+        "tap" | "toggle" => Ok(ModifierStyle::Tap),
+        other => Err(anyhow!(
+            "Unknown modifier style '{}', expected one of hold|tap..!",
+            other
+        )),
+    }
+}
+
+pub fn parse_emit_velocity(input: &str) -> Result<EmitVelocity> {
+    match input.to_lowercase().as_str() {
+        "onset" => Ok(EmitVelocity::Onset),
+        "peak" | "max" => Ok(EmitVelocity::Peak),
+        "decay" => Ok(EmitVelocity::Decay),
+        other => Err(anyhow!(
+            "Unknown emit velocity '{}', expected one of onset|peak|decay..!",
+            other
+        )),
+    }
+}
+
+/// The named scales `--scale` accepts, as the semitones of each degree above the root.
+pub fn scale_presets() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("major", vec![0, 2, 4, 5, 7, 9, 11]),
+        ("minor", vec![0, 2, 3, 5, 7, 8, 10]),
+        ("harmonic-minor", vec![0, 2, 3, 5, 7, 8, 11]),
+        ("dorian", vec![0, 2, 3, 5, 7, 9, 10]),
+        ("mixolydian", vec![0, 2, 4, 5, 7, 9, 10]),
+        ("pentatonic", vec![0, 2, 4, 7, 9]),
+        ("major-pentatonic", vec![0, 2, 4, 7, 9]),
+        ("minor-pentatonic", vec![0, 3, 5, 7, 10]),
+        ("blues", vec![0, 3, 5, 6, 7, 10]),
+        ("whole-tone", vec![0, 2, 4, 6, 8, 10]),
+        ("chromatic", (0..12).collect()),
+    ]
+}
+
+/// Parses a scale for [`ImportOptions::scale_constraint`](crate::ImportOptions), given either as a
+/// root & a named scale from [`scale_presets`], e.g. `a-minor-pentatonic` or `"Eb major"`, or as a
+/// comma separated list of pitch classes from 0 (C) to 11 (B), e.g. `0,2,4,7,9`.
+pub fn parse_scale(input: &str) -> Result<Vec<u8>> {
+    let input = input.trim().to_lowercase();
+
+    if input.starts_with(|c: char| c.is_ascii_digit()) {
+        let mut classes = input
+            .split(',')
+            .map(|class| match class.trim().parse::<u8>() {
+                Ok(class) if class < 12 => Ok(class),
+                _ => Err(anyhow!(
+                    "Invalid pitch class '{}' in scale, expected 0..=11..!",
+                    class.trim()
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        classes.sort_unstable();
+        classes.dedup();
+        return Ok(classes);
+    }
+
+    let words = input
+        .split(|c: char| c == '-' || c == '_' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    let Some((root, name)) = words.split_first() else {
+        return Err(anyhow!("No scale given..!"));
+    };
+
+    let root = match *root {
+        "c" | "b#" => 0,
+        "c#" | "db" => 1,
+        "d" => 2,
+        "d#" | "eb" => 3,
+        "e" | "fb" => 4,
+        "f" | "e#" => 5,
+        "f#" | "gb" => 6,
+        "g" => 7,
+        "g#" | "ab" => 8,
+        "a" => 9,
+        "a#" | "bb" => 10,
+        "b" | "cb" => 11,
+        other => {
+            return Err(anyhow!(
+                "Unknown scale root '{}', expected a note name like c, f# or bb..!",
+                other
+            ));
+        }
+    };
+    let name = if name.is_empty() {
+        "major".to_string()
+    } else {
+        name.join("-")
+    };
+
+    let presets = scale_presets();
+    let Some((_, degrees)) = presets.iter().find(|(preset, _)| *preset == name) else {
+        let names = presets
+            .iter()
+            .map(|(preset, _)| *preset)
+            .collect::<Vec<_>>()
+            .join("|");
+        return Err(anyhow!(
+            "Unknown scale '{}', expected one of {}..!",
+            name,
+            names
+        ));
+    };
+
+    let mut classes = degrees
+        .iter()
+        .map(|degree| (root + degree) % 12)
+        .collect::<Vec<_>>();
+    classes.sort_unstable();
+    Ok(classes)
+}
+
+/// Parses `--channel-gain` values of the form `<channel>:<gain>`, e.g. `0:1.5`, into the gain of
+/// each (0-based) MIDI channel.
+pub fn parse_channel_gains(inputs: &[String]) -> Result<HashMap<u8, f64>> {
+    inputs
+        .iter()
+        .map(|input| {
+            let (channel, gain) = input.split_once(':').ok_or_else(|| {
+                anyhow!(
+                    "Invalid channel gain '{}', expected <channel>:<gain> like 0:1.5..!",
+                    input
+                )
+            })?;
+
+            let channel = match channel.trim().parse::<u8>() {
+                Ok(channel) if channel < 16 => channel,
+                _ => bail!(
+                    "Invalid MIDI channel '{}', expected 0..=15..!",
+                    channel.trim()
+                ),
+            };
+            let gain = match gain.trim().parse::<f64>() {
+                Ok(gain) if gain.is_finite() && gain >= 0.0 => gain,
+                _ => bail!(
+                    "Invalid gain '{}' for channel {}, expected a non-negative number..!",
+                    gain.trim(),
+                    channel
+                ),
+            };
+
+            Ok((channel, gain))
+        })
+        .collect()
+}
+
 pub fn parse_policy(s: &str) -> PolyPolicy {
     match s.to_lowercase().as_str() {
         "h"|"highest" => PolyPolicy::Highest,
@@ -34,32 +346,177 @@ pub fn parse_policy(s: &str) -> PolyPolicy {
     }
 }
 
+/// A scale that plays every mapped note once on the way up & once more on the way down, each held
+/// for `note_ms`, to check that every key combo sounds the right note in game.
+pub fn build_scale_song(note_ms: f64) -> Song {
+    let ascending = MAPPINGS.iter().map(|(midi, _input)| *midi);
+    let descending = MAPPINGS.iter().rev().map(|(midi, _input)| *midi);
+
+    let events = ascending
+        .chain(descending)
+        .enumerate()
+        .map(|(i, midi)| Event {
+            note: Note {
+                midi,
+                velocity: 100,
+            },
+            time_ms: i as f64 * note_ms,
+            duration_ms: note_ms,
+            override_keys: None,
+        })
+        .collect();
+
+    Song {
+        metadata: Metadata {
+            title: Some(String::from("Test Scale")),
+            tempo_bpm: None,
+            markers: Vec::new(),
+        },
+        events,
+    }
+}
+
 /// Blocks for 30 seconds while checking that the active window's title is ANIMAL WELL, then panics or returns.
 #[cfg(test)]
 pub fn ensure_active_window() {
-    use log::debug;
-    use std::time::{Duration, Instant};
+    use crate::window::{GAME_WINDOW_TITLE, wait_for_window};
+    use std::time::Duration;
 
-    let now = Instant::now();
-    loop {
-        let active_window = active_win_pos_rs::get_active_window();
+    wait_for_window(GAME_WINDOW_TITLE, Duration::from_secs(30))
+        .expect("Active window title was never ANIMAL WELL..!");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        if active_window.is_err() {
-            continue;
+    #[test]
+    fn list_articulation_presets() {
+        let presets = articulation_presets();
+
+        assert!(presets.contains(&("staccatissimo", 0.25)));
+        assert!(presets.contains(&("portato", 0.75)));
+        assert_eq!(parse_articulation_strict("ss", None).unwrap(), 0.25);
+    }
+
+    #[test]
+    fn strict_articulation_rejects_unknown() {
+        assert!(parse_articulation_strict("legato", None).is_err());
+        assert!(parse_articulation_strict("custom", None).is_err());
+        assert_eq!(parse_articulation_strict("custom", Some(1.5)).unwrap(), 1.0);
+
+        // The lenient parser still falls back to portato.
+        assert_eq!(parse_articulation("legato", None), 0.75);
+    }
+
+    #[test]
+    fn articulation_diagnostics() {
+        let clamped = parse_articulation_checked("custom", Some(1.5)).unwrap();
+        assert_eq!(clamped.articulation, 1.0);
+        assert_eq!(clamped.clamped_from, Some(1.5));
+
+        let clamped = parse_articulation_checked("c", Some(-0.2)).unwrap();
+        assert_eq!(clamped.articulation, 0.0);
+        assert_eq!(clamped.clamped_from, Some(-0.2));
+
+        let in_range = parse_articulation_checked("custom", Some(0.42)).unwrap();
+        assert_eq!(in_range.articulation, 0.42);
+        assert_eq!(in_range.clamped_from, None);
+
+        // Custom without a value is an error, which the lenient parser turns into portato.
+        assert!(parse_articulation_checked("custom", None).is_err());
+        assert!(parse_articulation_checked("custom", Some(f64::NAN)).is_err());
+        assert_eq!(parse_articulation("custom", None), 0.75);
+
+        for (alias, preset, articulation) in [
+            ("t", "tenuto", 1.0),
+            ("Tenuto", "tenuto", 1.0),
+            ("p", "portato", 0.75),
+            ("portamento", "portato", 0.75),
+            ("s", "staccato", 0.5),
+            ("STACCATO", "staccato", 0.5),
+            ("ss", "staccatissimo", 0.25),
+            ("staccatissimo", "staccatissimo", 0.25),
+        ] {
+            let parsed = parse_articulation_checked(alias, None).unwrap();
+            assert_eq!((parsed.preset, parsed.articulation), (preset, articulation));
+            assert!(!parsed.ignored_custom);
         }
 
-        let title = active_window.unwrap().title;
+        let ignored = parse_articulation_checked("staccato", Some(0.3)).unwrap();
+        assert!(ignored.ignored_custom);
+    }
+
+    #[test]
+    fn min_hold_per_preset() {
+        assert_eq!(articulation_min_hold_ms("t"), 0.0);
+        assert_eq!(articulation_min_hold_ms("Staccato"), MIN_PLAYABLE_HOLD_MS);
+        assert!(articulation_min_hold_ms("ss") < MIN_PLAYABLE_HOLD_MS);
+        assert_eq!(articulation_min_hold_ms("legato"), MIN_PLAYABLE_HOLD_MS);
+    }
 
-        debug!("Active window: \"{}\"", title);
-        if title == "ANIMAL WELL" {
-            break;
-        } else {
-            let elapsed = now.elapsed();
-            if elapsed > Duration::from_secs(30) {
-                panic!("Active window title was never ANIMAL WELL..! (waited 30 seconds.)")
+    #[test]
+    fn json_log_records() {
+        use log::{Level, Log, Record};
+        use std::sync::{Arc, Mutex};
+
+        /// Collects everything the logger writes.
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Captured {
+            fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
             }
         }
 
-        spin_sleep::sleep(Duration::from_millis(50));
+        let captured = Captured::default();
+        let logger = logger_builder(parse_log_format("JSON").unwrap())
+            .filter_level(log::LevelFilter::Info)
+            .target(env_logger::Target::Pipe(Box::new(captured.clone())))
+            .build();
+
+        logger.log(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("FLUTE_WELL::player")
+                .args(format_args!("Playback stopped during wait..!"))
+                .build(),
+        );
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 1);
+
+        let line: serde_json::Value =
+            serde_json::from_str(output.trim()).expect("Log line should be valid JSON..!");
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], "FLUTE_WELL::player");
+        assert_eq!(line["message"], "Playback stopped during wait..!");
+        assert!(line["timestamp"].as_str().is_some_and(|ts| !ts.is_empty()));
+
+        assert!(parse_log_format("xml").is_err());
+    }
+
+    #[test]
+    fn scale_song_covers_mappings_both_ways() {
+        let song = build_scale_song(200.0);
+        assert_eq!(song.events.len(), MAPPINGS.len() * 2);
+
+        let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+        let (up, down) = pitches.split_at(MAPPINGS.len());
+        let mapped = MAPPINGS.iter().map(|(midi, _)| *midi).collect::<Vec<_>>();
+        assert_eq!(up, mapped.as_slice());
+        assert!(down.iter().eq(up.iter().rev()));
+
+        assert!(
+            song.events
+                .windows(2)
+                .all(|w| w[0].time_ms + w[0].duration_ms <= w[1].time_ms)
+        );
     }
 }
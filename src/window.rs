@@ -0,0 +1,278 @@
+use crate::clock::{Clock, SystemClock};
+use anyhow::{Result, anyhow, bail};
+use log::debug;
+use std::fmt::{Debug, Display};
+use std::time::Duration;
+
+/// The title of the game window that inputs should be sent to.
+pub const GAME_WINDOW_TITLE: &str = "ANIMAL WELL";
+
+/// How long to wait before asking for the active window again.
+pub const WINDOW_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The window with focus, as reported by a [`WindowWatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveWindow {
+    pub title: String,
+
+    /// The ID of the process that owns the window, if the watcher knows it.
+    pub pid: Option<u32>,
+}
+
+/// Reports which window currently has focus, so playback can be driven without a real desktop.
+pub trait WindowWatcher: Send + Sync + Debug {
+    fn active_window_title(&self) -> Result<String>;
+
+    /// The window with focus along with its owning process, for watchers that can tell. Others
+    /// only report the title.
+    fn active_window(&self) -> Result<ActiveWindow> {
+        self.active_window_title()
+            .map(|title| ActiveWindow { title, pid: None })
+    }
+}
+
+/// Queries the operating system for the foreground window.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ActiveWindowWatcher;
+
+impl WindowWatcher for ActiveWindowWatcher {
+    fn active_window_title(&self) -> Result<String> {
+        self.active_window().map(|window| window.title)
+    }
+
+    fn active_window(&self) -> Result<ActiveWindow> {
+        active_win_pos_rs::get_active_window()
+            .map(|window| ActiveWindow {
+                title: window.title,
+                pid: u32::try_from(window.process_id).ok(),
+            })
+            .map_err(|_| anyhow!("Failed to query the active window..!"))
+    }
+}
+
+/// Which window inputs are meant for, e.g. one of several running instances of the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowTarget {
+    /// Any window with this title.
+    Title(String),
+
+    /// Only the window owned by this process, whatever its title.
+    Pid(u32),
+}
+
+impl WindowTarget {
+    /// Any window titled [`GAME_WINDOW_TITLE`].
+    pub fn game() -> Self {
+        Self::Title(GAME_WINDOW_TITLE.to_owned())
+    }
+
+    pub fn matches(&self, window: &ActiveWindow) -> bool {
+        match self {
+            Self::Title(title) => window.title == *title,
+            Self::Pid(pid) => window.pid == Some(*pid),
+        }
+    }
+}
+
+impl Default for WindowTarget {
+    fn default() -> Self {
+        Self::game()
+    }
+}
+
+impl Display for WindowTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Title(title) => write!(f, "{}", title),
+            Self::Pid(pid) => write!(f, "the window of process {}", pid),
+        }
+    }
+}
+
+/// Always reports the same window title.
+#[derive(Debug, Clone)]
+pub struct FixedWindowWatcher(pub String);
+
+impl FixedWindowWatcher {
+    pub fn game() -> Self {
+        Self(GAME_WINDOW_TITLE.to_owned())
+    }
+}
+
+impl WindowWatcher for FixedWindowWatcher {
+    fn active_window_title(&self) -> Result<String> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Blocks until the active window is titled `title`, or fails once `timeout` has passed without it.
+pub fn wait_for_window(title: &str, timeout: Duration) -> Result<()> {
+    wait_for_window_with(
+        &ActiveWindowWatcher,
+        &SystemClock::default(),
+        &WindowTarget::Title(title.to_owned()),
+        timeout,
+        || false,
+    )
+}
+
+/// Like [`wait_for_window`], asking `watcher` on `clock`'s time for a window matching `target`.
+/// Also fails as soon as `cancelled` returns true, which is checked before every query.
+pub fn wait_for_window_with(
+    watcher: &dyn WindowWatcher,
+    clock: &dyn Clock,
+    target: &WindowTarget,
+    timeout: Duration,
+    mut cancelled: impl FnMut() -> bool,
+) -> Result<()> {
+    let started = clock.now();
+
+    loop {
+        if cancelled() {
+            bail!("Stopped waiting for the active window to be {}..!", target);
+        }
+
+        match watcher.active_window() {
+            Ok(active) => {
+                debug!("Active window: \"{}\" (pid {:?})", active.title, active.pid);
+                if target.matches(&active) {
+                    return Ok(());
+                }
+            }
+            Err(why) => debug!("{}", why),
+        }
+
+        if clock.elapsed_since(started) > timeout {
+            bail!(
+                "Active window was never {}..! (waited {} seconds.)",
+                target,
+                timeout.as_secs_f64()
+            );
+        }
+
+        clock.sleep(WINDOW_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Reports some other window until the `focus_at`th query, then the game.
+    #[derive(Debug)]
+    struct FocusingWatcher {
+        focus_at: usize,
+        queries: AtomicUsize,
+    }
+
+    impl WindowWatcher for FocusingWatcher {
+        fn active_window_title(&self) -> Result<String> {
+            if self.queries.fetch_add(1, Ordering::SeqCst) + 1 >= self.focus_at {
+                Ok(GAME_WINDOW_TITLE.to_owned())
+            } else {
+                Ok("Desktop".to_owned())
+            }
+        }
+    }
+
+    #[test]
+    fn wait_for_window_without_panicking() {
+        let clock = MockClock::new();
+        let watcher = FocusingWatcher {
+            focus_at: 5,
+            queries: AtomicUsize::new(0),
+        };
+
+        let waited = wait_for_window_with(
+            &watcher,
+            &clock,
+            &WindowTarget::game(),
+            Duration::from_secs(1),
+            || false,
+        );
+        assert!(waited.is_ok());
+        assert_eq!(watcher.queries.load(Ordering::SeqCst), 5);
+
+        // Still on the desktop after a full (virtual) second.
+        let clock = MockClock::new();
+        let watcher = FixedWindowWatcher("Desktop".to_owned());
+        let waited = wait_for_window_with(
+            &watcher,
+            &clock,
+            &WindowTarget::game(),
+            Duration::from_secs(1),
+            || false,
+        );
+        assert!(waited.is_err());
+        assert!(clock.elapsed() > Duration::from_secs(1));
+
+        let waited = wait_for_window_with(
+            &watcher,
+            &MockClock::new(),
+            &WindowTarget::game(),
+            Duration::from_secs(1),
+            || true,
+        );
+        assert!(waited.is_err());
+    }
+
+    /// Two instances of the game, taking turns having focus.
+    #[derive(Debug)]
+    struct TwoInstancesWatcher {
+        queries: AtomicUsize,
+    }
+
+    impl WindowWatcher for TwoInstancesWatcher {
+        fn active_window_title(&self) -> Result<String> {
+            self.active_window().map(|window| window.title)
+        }
+
+        fn active_window(&self) -> Result<ActiveWindow> {
+            let pid = [1111, 2222][self.queries.fetch_add(1, Ordering::SeqCst) % 2];
+
+            Ok(ActiveWindow {
+                title: GAME_WINDOW_TITLE.to_owned(),
+                pid: Some(pid),
+            })
+        }
+    }
+
+    #[test]
+    fn target_one_of_two_instances() {
+        let watcher = TwoInstancesWatcher {
+            queries: AtomicUsize::new(0),
+        };
+
+        let first = watcher.active_window().unwrap();
+        let second = watcher.active_window().unwrap();
+        assert_eq!(first.title, second.title);
+
+        let target = WindowTarget::Pid(2222);
+        assert!(!target.matches(&first));
+        assert!(target.matches(&second));
+        assert!(WindowTarget::game().matches(&first));
+
+        // Waits past the other instance for the targeted one.
+        let waited = wait_for_window_with(
+            &watcher,
+            &MockClock::new(),
+            &target,
+            Duration::from_secs(1),
+            || false,
+        );
+        assert!(waited.is_ok());
+        assert_eq!(watcher.queries.load(Ordering::SeqCst), 4);
+
+        // Without knowing which process owns the window, no PID can be confirmed.
+        let waited = wait_for_window_with(
+            &FixedWindowWatcher::game(),
+            &MockClock::new(),
+            &target,
+            Duration::from_secs(1),
+            || false,
+        );
+        assert!(waited.is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use FLUTE_WELL::{
+    FLUTE_RANGE, FixedWindowWatcher, ImportOptions, KeyAction, MockClock, PLAY_KEY, Player,
+    PolyPolicy, RecordingEngine, import_midi_file_with_options, input_for_midi,
+};
+use midly::num::{u4, u7, u15, u28};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use std::fs;
+
+const TICKS_PER_QUARTER: u16 = 96;
+
+/// An A major scale up to E6 & back down, one pitch per eighth note.
+const MELODY: [u8; 8] = [81, 83, 85, 86, 88, 86, 85, 83];
+
+/// A harmony lasting as long as the melody, one pitch per quarter note, every one of them under it.
+const HARMONY: [u8; 4] = [73, 76, 78, 76];
+
+fn note(key: u8, on: bool) -> TrackEventKind<'static> {
+    let (key, vel) = (u7::new(key), u7::new(if on { 100 } else { 0 }));
+
+    TrackEventKind::Midi {
+        channel: u4::new(0),
+        message: if on {
+            MidiMessage::NoteOn { key, vel }
+        } else {
+            MidiMessage::NoteOff { key, vel }
+        },
+    }
+}
+
+fn track(pitches: &[u8], note_ticks: u32) -> Vec<TrackEvent<'static>> {
+    let mut events = pitches
+        .iter()
+        .flat_map(|&key| [(0, note(key, true)), (note_ticks, note(key, false))])
+        .map(|(delta, kind)| TrackEvent {
+            delta: u28::new(delta),
+            kind,
+        })
+        .collect::<Vec<_>>();
+
+    events.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    events
+}
+
+fn write_test_midi(name: &str) -> std::path::PathBuf {
+    let eighth = TICKS_PER_QUARTER as u32 / 2;
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(u15::new(TICKS_PER_QUARTER)),
+    ));
+    smf.tracks.push(track(&MELODY, eighth));
+    smf.tracks.push(track(&HARMONY, 2 * eighth));
+
+    let path = std::env::temp_dir().join(name);
+    smf.save(&path).expect("Synthetic MIDI should save..!");
+    path
+}
+
+#[test]
+fn import_and_play_headless() {
+    env_logger::try_init().unwrap_or(());
+
+    let path = write_test_midi("flute_well_pipeline.mid");
+    let options = ImportOptions {
+        policy: PolyPolicy::Highest,
+        clip_to_range: Some(FLUTE_RANGE),
+        ..Default::default()
+    };
+    let song = import_midi_file_with_options(&path, &options);
+    let _ = fs::remove_file(&path);
+
+    // The harmony is always lower, so only the melody survives the reduction.
+    let song = song.expect("Synthetic MIDI should import..!");
+    let pitches = song.events.iter().map(|e| e.note.midi).collect::<Vec<_>>();
+    assert_eq!(pitches, MELODY);
+
+    let player = Player::new_for_test(
+        RecordingEngine::new(1.0),
+        FixedWindowWatcher::game(),
+        MockClock::new(),
+    );
+    assert!(player.load_song(song).is_ok());
+    assert!(player.play(true).is_ok());
+
+    // Every note's keys go down, then the play key, in melody order.
+    let pressed = player
+        .engine()
+        .recorded()
+        .into_iter()
+        .filter(|input| input.action == KeyAction::Down)
+        .map(|input| input.keys)
+        .collect::<Vec<_>>();
+    let expected = MELODY
+        .iter()
+        .flat_map(|&midi| {
+            let input = input_for_midi(midi).expect("Melody should be mapped..!");
+            [input.keys.to_vec(), vec![PLAY_KEY]]
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(pressed, expected);
+}